@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE subscription_tokens RENAME subscription_token TO subscription_token_hash;
+UPDATE subscription_tokens
+SET subscription_token_hash = encode(sha256(convert_to(subscription_token_hash, 'UTF8')), 'hex');
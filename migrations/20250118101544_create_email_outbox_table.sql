@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE email_outbox(
+    email_id uuid NOT NULL,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(email_id)
+);
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE welcome_email(
+    id BOOLEAN NOT NULL DEFAULT TRUE CHECK (id),
+    enabled BOOLEAN NOT NULL,
+    subject TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    PRIMARY KEY(id)
+);
+INSERT INTO welcome_email (enabled, subject, text_content, html_content) VALUES (
+    TRUE,
+    'Welcome aboard!',
+    'Thanks for confirming your subscription! You will receive our next issue as soon as it is published.',
+    '<p>Thanks for confirming your subscription!</p><p>You will receive our next issue as soon as it is published.</p>'
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE email_outbox ADD COLUMN n_retries SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE email_outbox ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,45 @@
+-- Addresses that only differ by case belong to the same person. Keep one
+-- subscription per address, preferring a confirmed one, then the oldest.
+CREATE TEMPORARY TABLE subscription_duplicates AS
+SELECT
+    id,
+    email,
+    row_number() OVER same_address AS position,
+    min(subscribed_at) OVER (PARTITION BY lower(email)) AS first_subscribed_at
+FROM subscriptions
+WINDOW same_address AS (
+    PARTITION BY lower(email)
+    ORDER BY status = 'confirmed' DESC, subscribed_at
+);
+
+UPDATE subscriptions
+SET subscribed_at = d.first_subscribed_at
+FROM subscription_duplicates d
+WHERE subscriptions.id = d.id AND d.position = 1;
+
+DELETE FROM subscription_tokens
+WHERE subscriber_id IN (SELECT id FROM subscription_duplicates WHERE position > 1);
+DELETE FROM issue_delivery_queue
+WHERE subscriber_email IN (SELECT email FROM subscription_duplicates WHERE position > 1);
+DELETE FROM email_outbox
+WHERE recipient IN (SELECT email FROM subscription_duplicates WHERE position > 1);
+DELETE FROM subscriptions
+WHERE id IN (SELECT id FROM subscription_duplicates WHERE position > 1);
+
+DROP TABLE subscription_duplicates;
+
+-- Domains are case-insensitive: store them in lowercase, as new addresses are.
+UPDATE issue_delivery_queue
+SET subscriber_email = substring(subscriber_email from '^(.*)@')
+    || '@' || lower(substring(subscriber_email from '@([^@]*)$'))
+WHERE subscriber_email LIKE '%@%';
+UPDATE email_outbox
+SET recipient = substring(recipient from '^(.*)@')
+    || '@' || lower(substring(recipient from '@([^@]*)$'))
+WHERE recipient LIKE '%@%';
+UPDATE subscriptions
+SET email = substring(email from '^(.*)@') || '@' || lower(substring(email from '@([^@]*)$'))
+WHERE email LIKE '%@%';
+
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+CREATE UNIQUE INDEX subscriptions_email_lower_key ON subscriptions (lower(email));
@@ -0,0 +1,5 @@
+CREATE TABLE email_domain_rules(
+    domain TEXT NOT NULL PRIMARY KEY,
+    rule TEXT NOT NULL CHECK (rule IN ('block', 'allow')),
+    created_at timestamptz NOT NULL DEFAULT now()
+);
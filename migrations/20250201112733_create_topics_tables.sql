@@ -0,0 +1,27 @@
+CREATE TABLE topics(
+    topic_id uuid NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT '',
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE TABLE subscription_topics(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    topic_id uuid NOT NULL REFERENCES topics (topic_id) ON DELETE CASCADE,
+    PRIMARY KEY (subscriber_id, topic_id)
+);
+
+CREATE TABLE newsletter_issue_topics(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    topic_id uuid NOT NULL REFERENCES topics (topic_id) ON DELETE CASCADE,
+    PRIMARY KEY (newsletter_issue_id, topic_id)
+);
+
+-- Until now there was a single newsletter: everyone is subscribed to it.
+INSERT INTO topics (topic_id, name, description)
+VALUES (gen_random_uuid(), 'General', 'News and updates');
+
+INSERT INTO subscription_topics (subscriber_id, topic_id)
+SELECT subscriptions.id, topics.topic_id
+FROM subscriptions, topics;
@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE issue_deliveries(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    subscriber_email TEXT NOT NULL,
+    outcome TEXT NOT NULL CHECK (outcome IN ('sent', 'failed')),
+    attempted_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_id)
+);
+CREATE INDEX issue_deliveries_subscriber_id_idx ON issue_deliveries (subscriber_id, attempted_at);
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at DESC, id DESC);
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- subscriber_id is not a foreign key: entries outlive the subscribers they
+-- are about, deleted ones included.
+CREATE TABLE admin_audit_log(
+    audit_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    action TEXT NOT NULL,
+    subscriber_id uuid NOT NULL,
+    subscriber_email TEXT NOT NULL,
+    performed_at timestamptz NOT NULL
+);
+CREATE INDEX admin_audit_log_subscriber_id_idx ON admin_audit_log (subscriber_id, performed_at);
@@ -0,0 +1,20 @@
+-- Add migration script here
+CREATE TABLE subscriber_imports(
+    import_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    confirmed BOOLEAN NOT NULL,
+    n_imported INTEGER NOT NULL DEFAULT 0,
+    n_duplicates INTEGER NOT NULL DEFAULT 0,
+    n_rejected INTEGER NOT NULL DEFAULT 0,
+    created_at timestamptz NOT NULL,
+    finished_at timestamptz NULL
+);
+CREATE TABLE subscriber_import_rejections(
+    import_id uuid NOT NULL
+        REFERENCES subscriber_imports (import_id) ON DELETE CASCADE,
+    row_number BIGINT NOT NULL,
+    email TEXT NOT NULL,
+    name TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    PRIMARY KEY(import_id, row_number)
+);
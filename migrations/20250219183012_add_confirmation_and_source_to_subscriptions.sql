@@ -0,0 +1,14 @@
+-- When the current subscription was confirmed or ended. Both are unknown for
+-- subscribers who got there before they were tracked.
+ALTER TABLE subscriptions ADD COLUMN confirmed_at timestamptz NULL;
+ALTER TABLE subscriptions ADD COLUMN unsubscribed_at timestamptz NULL;
+-- Where the signup came from and which consent text the subscriber agreed
+-- to. Left empty for subscribers added by admins or imported.
+ALTER TABLE subscriptions ADD COLUMN source_url TEXT NULL;
+ALTER TABLE subscriptions ADD COLUMN referrer TEXT NULL;
+ALTER TABLE subscriptions ADD COLUMN utm_source TEXT NULL;
+ALTER TABLE subscriptions ADD COLUMN utm_medium TEXT NULL;
+ALTER TABLE subscriptions ADD COLUMN utm_campaign TEXT NULL;
+ALTER TABLE subscriptions ADD COLUMN utm_term TEXT NULL;
+ALTER TABLE subscriptions ADD COLUMN utm_content TEXT NULL;
+ALTER TABLE subscriptions ADD COLUMN consent_version TEXT NULL;
@@ -0,0 +1,20 @@
+-- Bounces and spam complaints reported by the email provider. Events outlive
+-- the subscriber they matched, so that delivery problems can still be
+-- reported on; erasing a subscriber removes them by address.
+CREATE TABLE email_events(
+    event_id uuid PRIMARY KEY,
+    -- The id Postmark gives the event, so that redelivered webhooks are
+    -- only recorded once.
+    postmark_id BIGINT NOT NULL UNIQUE,
+    kind TEXT NOT NULL CHECK (kind IN ('bounce', 'spam_complaint')),
+    email TEXT NOT NULL,
+    subscriber_id uuid NULL
+        REFERENCES subscriptions (id) ON DELETE SET NULL,
+    bounce_type TEXT NULL,
+    description TEXT NULL,
+    message_id TEXT NULL,
+    occurred_at timestamptz NOT NULL,
+    received_at timestamptz NOT NULL
+);
+CREATE INDEX email_events_subscriber_id_idx ON email_events (subscriber_id, occurred_at);
+CREATE INDEX email_events_email_idx ON email_events (lower(email));
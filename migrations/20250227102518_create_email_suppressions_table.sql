@@ -0,0 +1,12 @@
+-- Addresses nothing is sent to anymore, whatever sends it. Addresses are
+-- stored lowercased, so that a lookup can't be fooled by casing.
+CREATE TABLE email_suppressions(
+    email TEXT PRIMARY KEY CHECK (email = lower(email)),
+    reason TEXT NOT NULL CHECK (reason IN ('bounce', 'spam_complaint', 'manual')),
+    note TEXT NULL,
+    created_at timestamptz NOT NULL
+);
+ALTER TABLE issue_deliveries DROP CONSTRAINT issue_deliveries_outcome_check;
+ALTER TABLE issue_deliveries
+    ADD CONSTRAINT issue_deliveries_outcome_check
+    CHECK (outcome IN ('sent', 'failed', 'suppressed'));
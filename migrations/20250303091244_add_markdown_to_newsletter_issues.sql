@@ -0,0 +1,3 @@
+-- The Markdown an issue was written in, if it was. The text and HTML bodies
+-- are rendered from it when the issue is published.
+ALTER TABLE newsletter_issues ADD COLUMN content_markdown TEXT NULL;
@@ -0,0 +1,34 @@
+-- Layouts emails are wrapped in: branding, and a footer with the unsubscribe
+-- link and the sender's postal address. Issues pick one; the default one is
+-- used for issues that didn't, and for confirmation emails.
+CREATE TABLE email_templates(
+    template_id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    html_layout TEXT NOT NULL,
+    text_layout TEXT NOT NULL,
+    postal_address TEXT NOT NULL,
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL
+);
+CREATE UNIQUE INDEX email_templates_default_idx ON email_templates (is_default) WHERE is_default;
+INSERT INTO email_templates (
+    template_id, name, html_layout, text_layout, postal_address, is_default, created_at
+)
+VALUES (
+    gen_random_uuid(),
+    'Default',
+    '{{ content }}<hr />'
+        || '<p><a href="{{ preferences_url }}">Manage your subscription</a> | '
+        || '<a href="{{ unsubscribe_url }}">Unsubscribe</a></p>'
+        || '<p>{{ postal_address }}</p>',
+    E'{{ content }}\n\n'
+        || E'Manage your subscription: {{ preferences_url }}\n'
+        || E'Unsubscribe: {{ unsubscribe_url }}\n\n'
+        || '{{ postal_address }}',
+    '',
+    true,
+    now()
+);
+ALTER TABLE newsletter_issues
+    ADD COLUMN template_id uuid NULL
+    REFERENCES email_templates (template_id) ON DELETE SET NULL;
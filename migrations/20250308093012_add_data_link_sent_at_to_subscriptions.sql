@@ -0,0 +1,3 @@
+-- When the last link to the subscriber's data was sent, so that the data
+-- request form can't be used to flood someone's inbox.
+ALTER TABLE subscriptions ADD COLUMN data_link_sent_at timestamptz NULL;
@@ -0,0 +1,80 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Changes admins make to subscriptions by hand.
+#[derive(Clone, Copy, Debug)]
+pub enum AdminAction {
+    CreateSubscriber,
+    ForceConfirm,
+    Unsubscribe,
+    Delete,
+    ExportData,
+    Erase,
+}
+
+impl AdminAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdminAction::CreateSubscriber => "create_subscriber",
+            AdminAction::ForceConfirm => "force_confirm",
+            AdminAction::Unsubscribe => "unsubscribe",
+            AdminAction::Delete => "delete",
+            AdminAction::ExportData => "export_data",
+            AdminAction::Erase => "erase",
+        }
+    }
+}
+
+pub struct AuditLogEntry {
+    pub username: String,
+    pub action: String,
+    pub subscriber_email: String,
+    pub performed_at: DateTime<Utc>,
+}
+
+/// Meant to run in the same transaction as the action it records.
+#[tracing::instrument(skip(transaction))]
+pub async fn record_admin_action(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    action: AdminAction,
+    subscriber_id: Uuid,
+    subscriber_email: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO admin_audit_log (
+            audit_id, user_id, action, subscriber_id, subscriber_email, performed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        action.as_str(),
+        subscriber_id,
+        subscriber_email,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn get_audit_log<'a>(
+    executor: impl PgExecutor<'a>,
+    subscriber_id: Uuid,
+) -> Result<Vec<AuditLogEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        AuditLogEntry,
+        r#"
+        SELECT u.username, a.action, a.subscriber_email, a.performed_at
+        FROM admin_audit_log a
+        JOIN users u ON u.user_id = a.user_id
+        WHERE a.subscriber_id = $1
+        ORDER BY a.performed_at DESC
+        "#,
+        subscriber_id
+    )
+    .fetch_all(executor)
+    .await
+}
@@ -4,6 +4,7 @@ use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
+use std::net::IpAddr;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
@@ -11,6 +12,45 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    pub spam_protection: SpamProtectionSettings,
+    pub domain_rules: DomainRulesSettings,
+    pub webhooks: WebhookSettings,
+}
+
+/// HTTP Basic credentials the email provider's webhooks have to present.
+/// They are part of the webhook URL configured on the provider's side.
+#[derive(serde::Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// Files listing email domains, one per line, that subscriptions are
+/// refused from (blocklist) or always accepted from (allowlist).
+#[derive(serde::Deserialize, Clone)]
+pub struct DomainRulesSettings {
+    pub blocklist_file: Option<String>,
+    pub allowlist_file: Option<String>,
+}
+
+/// Limits applied to the public subscription endpoint.
+#[derive(serde::Deserialize, Clone)]
+pub struct SpamProtectionSettings {
+    /// How long a human needs, at least, between loading the signup form
+    /// and submitting it.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_submit_seconds: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub rate_limit_window_seconds: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_subscriptions_per_ip: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_subscriptions_per_domain: u64,
+    /// Prepended to the keys attempts are counted under in Redis.
+    pub redis_key_prefix: String,
+    /// The load balancers and reverse proxies in front of the application.
+    /// Their `X-Forwarded-For` header is the only one believed.
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -48,6 +88,9 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// Where to send subscribers once they have confirmed their subscription.
+    /// When unset, a built-in landing page is shown instead.
+    pub confirmation_redirect_url: Option<String>,
 }
 
 impl ApplicationSettings {
@@ -84,7 +127,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
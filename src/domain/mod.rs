@@ -1,8 +1,12 @@
 mod subscriber_name;
 mod subscriber_email;
 mod new_subscriber;
+mod signup_source;
+mod subscription_status;
 
 pub use subscriber_name::SubscriberName;
 pub use subscriber_email::SubscriberEmail;
 pub use new_subscriber::NewSubscriber;
+pub use signup_source::SignupSource;
+pub use subscription_status::{InvalidStatusTransition, SubscriptionStatus};
 
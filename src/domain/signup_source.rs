@@ -0,0 +1,83 @@
+const MAX_URL_LENGTH: usize = 2048;
+const MAX_PARAMETER_LENGTH: usize = 256;
+
+/// Where a signup came from and the version of the consent text shown next
+/// to the form. Everything is optional and reported by the form itself.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SignupSource {
+    /// The page the signup form is on.
+    pub source_url: Option<String>,
+    /// The page that led the visitor to the form.
+    pub referrer: Option<String>,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub utm_term: Option<String>,
+    pub utm_content: Option<String>,
+    pub consent_version: Option<String>,
+}
+
+fn clean(value: Option<String>, max_length: usize) -> Option<String> {
+    let value = value?;
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.chars().take(max_length).collect())
+    }
+}
+
+impl SignupSource {
+    /// Trims the values, drops the empty ones and cuts the overly long ones:
+    /// a mangled tracking parameter is no reason to turn a subscriber away.
+    pub fn normalized(self) -> Self {
+        Self {
+            source_url: clean(self.source_url, MAX_URL_LENGTH),
+            referrer: clean(self.referrer, MAX_URL_LENGTH),
+            utm_source: clean(self.utm_source, MAX_PARAMETER_LENGTH),
+            utm_medium: clean(self.utm_medium, MAX_PARAMETER_LENGTH),
+            utm_campaign: clean(self.utm_campaign, MAX_PARAMETER_LENGTH),
+            utm_term: clean(self.utm_term, MAX_PARAMETER_LENGTH),
+            utm_content: clean(self.utm_content, MAX_PARAMETER_LENGTH),
+            consent_version: clean(self.consent_version, MAX_PARAMETER_LENGTH),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SignupSource;
+
+    #[test]
+    fn blank_values_are_dropped() {
+        let source = SignupSource {
+            utm_source: Some("  ".into()),
+            utm_medium: Some("".into()),
+            ..Default::default()
+        }
+        .normalized();
+        assert_eq!(source, SignupSource::default());
+    }
+
+    #[test]
+    fn values_are_trimmed() {
+        let source = SignupSource {
+            utm_campaign: Some(" spring ".into()),
+            ..Default::default()
+        }
+        .normalized();
+        assert_eq!(source.utm_campaign.as_deref(), Some("spring"));
+    }
+
+    #[test]
+    fn overly_long_values_are_cut() {
+        let source = SignupSource {
+            utm_source: Some("й".repeat(300)),
+            source_url: Some(format!("https://example.com/{}", "a".repeat(3000))),
+            ..Default::default()
+        }
+        .normalized();
+        assert_eq!(source.utm_source.unwrap().chars().count(), 256);
+        assert_eq!(source.source_url.unwrap().chars().count(), 2048);
+    }
+}
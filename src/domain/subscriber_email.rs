@@ -4,11 +4,21 @@ use validator::ValidateEmail;
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
+    /// The domain is normalized, lowercase and IDNA-encoded, while the local
+    /// part is kept as it was typed.
     pub fn parse(s: String) -> Result<Self, String> {
-        if s.validate_email() {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not valid email", s))
+        if !s.validate_email() {
+            return Err(format!("{} is not valid email", s));
+        }
+        let normalized = s
+            .rsplit_once('@')
+            .and_then(|(local_part, domain)| {
+                let domain = idna::domain_to_ascii(domain).ok()?;
+                Some(format!("{}@{}", local_part, domain))
+            });
+        match normalized {
+            Some(email) => Ok(Self(email)),
+            None => Err(format!("{} is not valid email", s)),
         }
     }
 }
@@ -65,6 +75,18 @@ mod tests {
         assert_err!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn the_domain_is_lowercased() {
+        let email = SubscriberEmail::parse("Ursula@Domain.COM".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula@domain.com");
+    }
+
+    #[test]
+    fn internationalized_domains_are_idna_encoded() {
+        let email = SubscriberEmail::parse("ursula@bücher.example".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.example");
+    }
+
     #[quickcheck_macros::quickcheck]
     fn valid_emails_are_parsed_successfully(valid_email: ValidationEmailFixture) {
         assert_ok!(SubscriberEmail::parse(valid_email.0));
@@ -0,0 +1,139 @@
+/// Where a subscriber stands, stored as the `subscription_status` Postgres
+/// enum. Only `Confirmed` subscribers receive issues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, serde::Serialize, serde::Deserialize)]
+#[sqlx(type_name = "subscription_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    /// Mail to the address can't be delivered.
+    Bounced,
+    /// The subscriber marked an email from us as spam.
+    Complained,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("A subscription can't go from {from} to {to}.")]
+pub struct InvalidStatusTransition {
+    pub from: SubscriptionStatus,
+    pub to: SubscriptionStatus,
+}
+
+impl SubscriptionStatus {
+    pub const ALL: [SubscriptionStatus; 5] = [
+        SubscriptionStatus::PendingConfirmation,
+        SubscriptionStatus::Confirmed,
+        SubscriptionStatus::Unsubscribed,
+        SubscriptionStatus::Bounced,
+        SubscriptionStatus::Complained,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("{} is not a subscription status", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::PendingConfirmation => "pending_confirmation",
+            SubscriptionStatus::Confirmed => "confirmed",
+            SubscriptionStatus::Unsubscribed => "unsubscribed",
+            SubscriptionStatus::Bounced => "bounced",
+            SubscriptionStatus::Complained => "complained",
+        }
+    }
+
+    /// The one place that decides how a subscription may evolve:
+    /// - only a pending subscription can be confirmed;
+    /// - any subscription that is not over yet can end, by unsubscribing,
+    ///   bouncing or a complaint;
+    /// - a bounced address can still be unsubscribed, and a complaint
+    ///   trumps any other way the subscription ended;
+    /// - once it has ended, signing up again starts the double opt-in over.
+    ///
+    /// Staying in the same status is not a transition.
+    pub fn can_transition_to(&self, to: SubscriptionStatus) -> bool {
+        use SubscriptionStatus::*;
+        matches!(
+            (self, to),
+            (
+                PendingConfirmation,
+                Confirmed | Unsubscribed | Bounced | Complained
+            ) | (Confirmed, Unsubscribed | Bounced | Complained)
+                | (Unsubscribed, PendingConfirmation | Complained)
+                | (Bounced, PendingConfirmation | Unsubscribed | Complained)
+                | (Complained, PendingConfirmation)
+        )
+    }
+
+    pub fn transition_to(
+        self,
+        to: SubscriptionStatus,
+    ) -> Result<SubscriptionStatus, InvalidStatusTransition> {
+        if self.can_transition_to(to) {
+            Ok(to)
+        } else {
+            Err(InvalidStatusTransition { from: self, to })
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionStatus::{self, *};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn statuses_round_trip_through_their_names() {
+        for status in SubscriptionStatus::ALL {
+            assert_eq!(SubscriptionStatus::parse(status.as_str()), Ok(status));
+        }
+        assert_err!(SubscriptionStatus::parse("spam"));
+    }
+
+    #[test]
+    fn a_subscription_is_confirmed_before_it_ends() {
+        assert_ok!(PendingConfirmation.transition_to(Confirmed));
+        assert_ok!(Confirmed.transition_to(Unsubscribed));
+        assert_ok!(Confirmed.transition_to(Bounced));
+        assert_ok!(Confirmed.transition_to(Complained));
+    }
+
+    #[test]
+    fn only_pending_subscriptions_can_be_confirmed() {
+        for status in [Unsubscribed, Bounced, Complained] {
+            assert_err!(status.transition_to(Confirmed));
+        }
+    }
+
+    #[test]
+    fn ended_subscriptions_start_over_as_pending() {
+        for status in [Unsubscribed, Bounced, Complained] {
+            assert_ok!(status.transition_to(PendingConfirmation));
+        }
+        assert_err!(Confirmed.transition_to(PendingConfirmation));
+    }
+
+    #[test]
+    fn complaints_are_not_overridden() {
+        assert_err!(Complained.transition_to(Unsubscribed));
+        assert_err!(Complained.transition_to(Bounced));
+        assert_err!(Unsubscribed.transition_to(Bounced));
+    }
+
+    #[test]
+    fn staying_in_the_same_status_is_not_a_transition() {
+        for status in SubscriptionStatus::ALL {
+            assert!(!status.can_transition_to(status));
+        }
+    }
+}
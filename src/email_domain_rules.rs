@@ -0,0 +1,156 @@
+use crate::configuration::DomainRulesSettings;
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use sqlx::PgExecutor;
+use std::collections::HashSet;
+
+/// Email domains subscriptions are refused from or always accepted from.
+/// A rule on a domain applies to its subdomains as well, and allowing a
+/// domain takes precedence over blocking it.
+#[derive(Default)]
+pub struct DomainRules {
+    blocked: HashSet<String>,
+    allowed: HashSet<String>,
+}
+
+pub struct DomainRuleRecord {
+    pub domain: String,
+    pub rule: String,
+}
+
+impl DomainRules {
+    pub fn from_files(settings: &DomainRulesSettings) -> Result<Self, anyhow::Error> {
+        let mut rules = Self::default();
+        if let Some(path) = &settings.blocklist_file {
+            rules.blocked = read_domains(path)?;
+        }
+        if let Some(path) = &settings.allowlist_file {
+            rules.allowed = read_domains(path)?;
+        }
+        Ok(rules)
+    }
+
+    /// Checks the domain of `email` against the rules from the configuration
+    /// files and the ones managed from the admin area. Returns the message
+    /// to show if the domain is blocked.
+    pub async fn check<'a>(
+        &self,
+        executor: impl PgExecutor<'a>,
+        email: &SubscriberEmail,
+    ) -> Result<Result<(), String>, sqlx::Error> {
+        let domain = match email.as_ref().rsplit_once('@') {
+            Some((_, domain)) => domain,
+            None => return Ok(Ok(())),
+        };
+        let parents: Vec<&str> = std::iter::successors(Some(domain), |d| {
+            d.split_once('.').map(|(_, parent)| parent)
+        })
+        .collect();
+        let admin_rules = get_rules_for_domains(executor, &parents).await?;
+        if admin_rules.iter().any(|rule| rule == "allow")
+            || parents.iter().any(|d| self.allowed.contains(*d))
+        {
+            return Ok(Ok(()));
+        }
+        if !admin_rules.is_empty() || parents.iter().any(|d| self.blocked.contains(*d)) {
+            return Ok(Err(format!(
+                "Addresses at {} can't be used to subscribe to this newsletter.",
+                domain
+            )));
+        }
+        Ok(Ok(()))
+    }
+}
+
+/// One domain per line; blank lines and lines starting with `#` are skipped.
+fn read_domains(path: &str) -> Result<HashSet<String>, anyhow::Error> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the domain list at {}.", path))?;
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| normalize_domain(line).map_err(|e| anyhow::anyhow!("{} in {}.", e, path)))
+        .collect()
+}
+
+/// Domains are stored the way `SubscriberEmail` normalizes them.
+pub fn normalize_domain(domain: &str) -> Result<String, String> {
+    let domain = domain.trim().trim_start_matches('@');
+    match idna::domain_to_ascii(domain) {
+        Ok(domain) if !domain.is_empty() => Ok(domain),
+        _ => Err(format!("{} is not a valid domain", domain)),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_domain_rules<'a>(
+    executor: impl PgExecutor<'a>,
+) -> Result<Vec<DomainRuleRecord>, sqlx::Error> {
+    let rules = sqlx::query_as!(
+        DomainRuleRecord,
+        r#"
+        SELECT domain, rule
+        FROM email_domain_rules
+        ORDER BY domain
+        "#
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rules)
+}
+
+/// The rules managed from the admin area for any of `domains`.
+#[tracing::instrument(skip(executor))]
+async fn get_rules_for_domains<'a>(
+    executor: impl PgExecutor<'a>,
+    domains: &[&str],
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT rule
+        FROM email_domain_rules
+        WHERE domain = ANY($1)
+        "#,
+        domains as &[&str]
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn save_domain_rule<'a>(
+    executor: impl PgExecutor<'a>,
+    domain: &str,
+    rule: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_domain_rules (domain, rule)
+        VALUES ($1, $2)
+        ON CONFLICT (domain) DO UPDATE SET rule = EXCLUDED.rule
+        "#,
+        domain,
+        rule,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn delete_domain_rule<'a>(
+    executor: impl PgExecutor<'a>,
+    domain: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM email_domain_rules
+        WHERE domain = $1
+        "#,
+        domain
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+/// What the email provider told us about an email we sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmailEventKind {
+    Bounce,
+    SpamComplaint,
+}
+
+impl EmailEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailEventKind::Bounce => "bounce",
+            EmailEventKind::SpamComplaint => "spam_complaint",
+        }
+    }
+}
+
+pub struct NewEmailEvent<'a> {
+    /// The provider's own id for the event.
+    pub postmark_id: i64,
+    pub kind: EmailEventKind,
+    pub email: &'a str,
+    pub subscriber_id: Option<Uuid>,
+    pub bounce_type: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub message_id: Option<&'a str>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+pub struct EmailEvent {
+    pub kind: String,
+    pub email: String,
+    pub bounce_type: Option<String>,
+    pub description: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Returns `false` if the event had already been recorded, in which case
+/// it has been acted upon already too.
+#[tracing::instrument(skip(transaction, event), fields(postmark_id = event.postmark_id))]
+pub async fn record_email_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    event: &NewEmailEvent<'_>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            event_id,
+            postmark_id,
+            kind,
+            email,
+            subscriber_id,
+            bounce_type,
+            description,
+            message_id,
+            occurred_at,
+            received_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now())
+        ON CONFLICT (postmark_id) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        event.postmark_id,
+        event.kind.as_str(),
+        event.email,
+        event.subscriber_id,
+        event.bounce_type,
+        event.description,
+        event.message_id,
+        event.occurred_at,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn get_email_events<'a>(
+    executor: impl PgExecutor<'a>,
+    subscriber_id: Uuid,
+) -> Result<Vec<EmailEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailEvent,
+        r#"
+        SELECT kind, email, bounce_type, description, occurred_at
+        FROM email_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at DESC
+        "#,
+        subscriber_id
+    )
+    .fetch_all(executor)
+    .await
+}
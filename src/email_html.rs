@@ -0,0 +1,236 @@
+//! The HTML content of newsletter issues, prepared for mail clients when the
+//! issue is published. CSS from `<style>` blocks is inlined into `style`
+//! attributes, since many clients drop stylesheets, and the markup is then
+//! sanitized against an allow-list: scripts, forms, embedded frames and
+//! event handler attributes don't make it into the email.
+//!
+//! The editor is told what was removed, and when the email is big enough for
+//! Gmail to clip it.
+
+use ammonia::Builder;
+use css_inline::CSSInliner;
+
+/// Gmail only shows the first ~102KB of an email's HTML, behind a "View
+/// entire message" link.
+pub const GMAIL_CLIPPING_THRESHOLD: usize = 102 * 1024;
+
+pub struct PreparedHtml {
+    pub html: String,
+    /// What the editor should know about, e.g. that a `<script>` was removed.
+    pub warnings: Vec<String>,
+}
+
+/// Elements that are expected to go away and are not worth a warning: the
+/// document structure around the content, and stylesheets once they are
+/// inlined. So are classes, which mean nothing without a stylesheet.
+const SILENTLY_REMOVED: [&str; 4] = ["html", "head", "body", "style"];
+
+pub fn prepare_html(html: &str) -> PreparedHtml {
+    let mut warnings = Vec::new();
+    let inliner = CSSInliner::options().load_remote_stylesheets(false).build();
+    let inlined = match inliner.inline_fragment(html, "") {
+        Ok(inlined) => inlined,
+        Err(e) => {
+            warnings.push(format!(
+                "The CSS could not be inlined, the styles were dropped: {}",
+                e
+            ));
+            html.to_string()
+        }
+    };
+    let sanitized = sanitizer().clean(&inlined).to_string();
+
+    let removed = removed_markup(&markup(html), &markup(&sanitized));
+    if !removed.is_empty() {
+        warnings.push(format!(
+            "Removed from the HTML content, as mail clients block or mangle it: {}.",
+            removed.join(", ")
+        ));
+    }
+    PreparedHtml {
+        html: sanitized,
+        warnings,
+    }
+}
+
+/// A warning if `html`, the whole body of the email, is big enough to be
+/// clipped.
+pub fn size_warning(html: &str) -> Option<String> {
+    (html.len() > GMAIL_CLIPPING_THRESHOLD).then(|| {
+        format!(
+            "The HTML email is {}KB: Gmail clips emails above {}KB, hiding the rest \
+            of the issue and the footer behind a link.",
+            html.len().div_ceil(1024),
+            GMAIL_CLIPPING_THRESHOLD / 1024
+        )
+    })
+}
+
+fn sanitizer() -> Builder<'static> {
+    let mut builder = Builder::default();
+    builder
+        .add_tags(["center", "font"])
+        // Layout attributes are still how tables are laid out in email.
+        .add_generic_attributes(["style", "align", "valign", "bgcolor", "width", "height"])
+        .add_tag_attributes("table", ["border", "cellpadding", "cellspacing"])
+        .add_tag_attributes("font", ["color", "face", "size"])
+        .add_clean_content_tags(["title"])
+        .link_rel(None);
+    builder
+}
+
+/// The elements and attributes of `html`, as `tag` and `tag attribute`.
+/// Only good enough to tell what sanitization removed: it doesn't look
+/// inside comments, scripts or stylesheets.
+fn markup(html: &str) -> Vec<String> {
+    let mut markup = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if rest.starts_with("!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+        let name_len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+            .unwrap_or(rest.len());
+        if name_len == 0 || !rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue;
+        }
+        let tag = rest[..name_len].to_ascii_lowercase();
+        rest = &rest[name_len..];
+        loop {
+            rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+            if rest.is_empty() || rest.starts_with('>') {
+                break;
+            }
+            let attribute_len = rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/'))
+                .unwrap_or(rest.len())
+                .max(1);
+            markup.push(format!(
+                "{} {}",
+                tag,
+                rest[..attribute_len].to_ascii_lowercase()
+            ));
+            rest = rest[attribute_len..].trim_start();
+            if let Some(value) = rest.strip_prefix('=') {
+                let value = value.trim_start();
+                rest = match value.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        value[1..].find(quote).map_or("", |end| &value[end + 2..])
+                    }
+                    _ => {
+                        let end = value
+                            .find(|c: char| c.is_whitespace() || c == '>')
+                            .unwrap_or(value.len());
+                        &value[end..]
+                    }
+                };
+            }
+        }
+        if tag == "script" || tag == "style" {
+            let closing = format!("</{}", tag);
+            rest = rest
+                .to_ascii_lowercase()
+                .find(&closing)
+                .map_or("", |end| &rest[end..]);
+        }
+        markup.push(tag);
+    }
+    markup
+}
+
+/// What is in `before` and not in `after`, as `<tag>` or `<tag attribute>`,
+/// once each.
+fn removed_markup(before: &[String], after: &[String]) -> Vec<String> {
+    let mut after = after.to_vec();
+    let mut removed: Vec<&String> = Vec::new();
+    for item in before {
+        match after.iter().position(|a| a == item) {
+            Some(i) => {
+                after.swap_remove(i);
+            }
+            None => removed.push(item),
+        }
+    }
+    let removed_tags: Vec<&str> = removed
+        .iter()
+        .filter(|item| !item.contains(' '))
+        .map(|item| item.as_str())
+        .collect();
+    let mut report: Vec<String> = Vec::new();
+    for item in removed {
+        let (tag, attribute) = match item.split_once(' ') {
+            Some((tag, attribute)) => (tag, Some(attribute)),
+            None => (item.as_str(), None),
+        };
+        if SILENTLY_REMOVED.contains(&tag) {
+            continue;
+        }
+        let entry = match attribute {
+            // Reported along with the element.
+            Some(_) if removed_tags.contains(&tag) => continue,
+            Some("class") => continue,
+            Some(attribute) => format!("<{} {}>", tag, attribute),
+            None => format!("<{}>", tag),
+        };
+        if !report.contains(&entry) {
+            report.push(entry);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prepare_html, size_warning, GMAIL_CLIPPING_THRESHOLD};
+
+    #[test]
+    fn styles_are_inlined() {
+        let prepared = prepare_html(
+            "<style>p { color: red; } .big { font-size: 20px }</style>\
+            <p class=\"big\">Hello</p>",
+        );
+        assert_eq!(
+            prepared.html,
+            "<p style=\"color: red;font-size: 20px;\">Hello</p>"
+        );
+        assert!(prepared.warnings.is_empty());
+    }
+
+    #[test]
+    fn unsafe_markup_is_removed_with_a_warning() {
+        let prepared = prepare_html(
+            "<p onclick=\"steal()\">Hi</p><script src=\"x.js\">alert(1)</script>\
+            <a href=\"javascript:steal()\">link</a>",
+        );
+        assert_eq!(prepared.html, "<p>Hi</p><a>link</a>");
+        assert_eq!(
+            prepared.warnings,
+            vec![
+                "Removed from the HTML content, as mail clients block or mangle it: \
+                <p onclick>, <script>, <a href>."
+                    .to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn email_markup_and_placeholders_are_kept() {
+        let html = "<table width=\"100%\" cellpadding=\"0\"><tbody><tr>\
+            <td align=\"center\" bgcolor=\"#eeeeee\">Hi {{ subscriber.name }}, \
+            <a href=\"{{ unsubscribe_url }}\">unsubscribe</a></td>\
+            </tr></tbody></table>";
+        let prepared = prepare_html(html);
+        assert_eq!(prepared.html, html);
+        assert!(prepared.warnings.is_empty());
+    }
+
+    #[test]
+    fn big_emails_get_a_warning() {
+        assert!(size_warning(&"a".repeat(GMAIL_CLIPPING_THRESHOLD)).is_none());
+        let warning = size_warning(&"a".repeat(GMAIL_CLIPPING_THRESHOLD + 1));
+        assert!(warning.unwrap().contains("Gmail clips emails above 102KB"));
+    }
+}
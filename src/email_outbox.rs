@@ -0,0 +1,112 @@
+use crate::domain::SubscriberEmail;
+use chrono::{Duration, Utc};
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::Uuid;
+
+/// How many times the worker retries a failed delivery before dropping it.
+pub const MAX_RETRIES: i16 = 5;
+
+pub struct OutboxEmail {
+    pub email_id: Uuid,
+    pub recipient: String,
+    pub subject: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub n_retries: i16,
+}
+
+/// Stores a fully rendered email in the outbox, to be picked up by the
+/// background worker once `transaction` is committed. The worker drops it
+/// if the recipient is on the suppression list by then.
+#[tracing::instrument(skip(transaction, subject, html_content, text_content))]
+pub async fn enqueue_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let email_id = Uuid::new_v4();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO email_outbox (
+            email_id,
+            recipient,
+            subject,
+            text_content,
+            html_content,
+            created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        email_id,
+        recipient.as_ref(),
+        subject,
+        text_content,
+        html_content,
+    );
+    transaction.execute(query).await?;
+    Ok(email_id)
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn dequeue_email(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<OutboxEmail>, sqlx::Error> {
+    let email = sqlx::query_as!(
+        OutboxEmail,
+        r#"
+        SELECT email_id, recipient, subject, text_content, html_content, n_retries
+        FROM email_outbox
+        WHERE execute_after <= now()
+        ORDER BY created_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(email)
+}
+
+#[tracing::instrument(skip(transaction))]
+pub(crate) async fn delete_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM email_outbox
+        WHERE email_id = $1
+        "#,
+        email_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Pushes a failed email back with an exponential backoff: 1 minute after the
+/// first failure, then 2, 4, 8 and so on.
+#[tracing::instrument(skip(transaction))]
+pub(crate) async fn schedule_retry(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_id: Uuid,
+    n_retries: i16,
+) -> Result<(), sqlx::Error> {
+    let execute_after = Utc::now() + Duration::minutes(1 << n_retries);
+    let query = sqlx::query!(
+        r#"
+        UPDATE email_outbox
+        SET
+            n_retries = $2,
+            execute_after = $3
+        WHERE email_id = $1
+        "#,
+        email_id,
+        n_retries + 1,
+        execute_after,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use crate::newsletter_template::{Placeholder, Template, TemplateError, TemplateValues};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// The layout emails are wrapped in when they are sent. Issues pick one;
+/// the default one is used for the others and for confirmation emails.
+pub struct EmailTemplate {
+    pub template_id: Uuid,
+    pub name: String,
+    pub html_layout: String,
+    pub text_layout: String,
+    pub postal_address: String,
+    pub is_default: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LayoutError {
+    #[error(transparent)]
+    InvalidTemplate(#[from] TemplateError),
+    #[error("The layout must have exactly one {{{{ content }}}} slot.")]
+    ContentSlot,
+    #[error("The layout must have a {{{{ {0} }}}} in its footer.")]
+    MissingFooterPlaceholder(&'static str),
+}
+
+/// Every email must say where it comes from and how to stop receiving
+/// them, so layouts can't leave out the postal address or the unsubscribe
+/// link.
+pub fn validate_layout(layout: &str) -> Result<(), LayoutError> {
+    let template = Template::parse_layout(layout)?;
+    if template.count(Placeholder::Content) != 1 {
+        return Err(LayoutError::ContentSlot);
+    }
+    for placeholder in [Placeholder::UnsubscribeUrl, Placeholder::PostalAddress] {
+        if template.count(placeholder) == 0 {
+            return Err(LayoutError::MissingFooterPlaceholder(placeholder.name()));
+        }
+    }
+    Ok(())
+}
+
+impl EmailTemplate {
+    /// Wraps an email's HTML and text bodies, placeholders already filled
+    /// in, in the layouts.
+    pub fn wrap(
+        &self,
+        values: &TemplateValues,
+        html_content: &str,
+        text_content: &str,
+    ) -> (String, String) {
+        // Layouts are validated when they are saved.
+        let html = match Template::parse_layout(&self.html_layout) {
+            Ok(layout) => layout.wrap_html(values, html_content),
+            Err(_) => html_content.to_string(),
+        };
+        let text = match Template::parse_layout(&self.text_layout) {
+            Ok(layout) => layout.wrap_text(values, text_content),
+            Err(_) => text_content.to_string(),
+        };
+        (html, text)
+    }
+}
+
+/// Options for a `<select>` of `templates`, with `selected` or else the
+/// default one selected.
+pub fn template_options(templates: &[EmailTemplate], selected: Option<Uuid>) -> String {
+    let mut html = String::new();
+    for template in templates {
+        let is_selected = match selected {
+            Some(selected) => selected == template.template_id,
+            None => template.is_default,
+        };
+        let _ = write!(
+            html,
+            r#"<option value="{}"{}>{}</option>"#,
+            template.template_id,
+            if is_selected { " selected" } else { "" },
+            htmlescape::encode_minimal(&template.name),
+        );
+    }
+    html
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_email_templates<'a>(
+    executor: impl PgExecutor<'a>,
+) -> Result<Vec<EmailTemplate>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailTemplate,
+        r#"
+        SELECT template_id, name, html_layout, text_layout, postal_address, is_default
+        FROM email_templates
+        ORDER BY is_default DESC, name
+        "#
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn get_email_template<'a>(
+    executor: impl PgExecutor<'a>,
+    template_id: Uuid,
+) -> Result<Option<EmailTemplate>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailTemplate,
+        r#"
+        SELECT template_id, name, html_layout, text_layout, postal_address, is_default
+        FROM email_templates
+        WHERE template_id = $1
+        "#,
+        template_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// The template with id `template_id`, or the default one if there is no
+/// such template or no id.
+#[tracing::instrument(skip(executor))]
+pub async fn get_email_template_or_default<'a>(
+    executor: impl PgExecutor<'a>,
+    template_id: Option<Uuid>,
+) -> Result<EmailTemplate, sqlx::Error> {
+    sqlx::query_as!(
+        EmailTemplate,
+        r#"
+        SELECT template_id, name, html_layout, text_layout, postal_address, is_default
+        FROM email_templates
+        WHERE template_id = $1 OR is_default
+        ORDER BY is_default
+        LIMIT 1
+        "#,
+        template_id
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// The new template starts with the layouts of the default one.
+#[tracing::instrument(skip(executor))]
+pub async fn insert_email_template<'a>(
+    executor: impl PgExecutor<'a>,
+    name: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let template_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_templates (
+            template_id, name, html_layout, text_layout, postal_address, created_at
+        )
+        SELECT $1, $2, html_layout, text_layout, postal_address, now()
+        FROM email_templates
+        WHERE is_default
+        "#,
+        template_id,
+        name,
+    )
+    .execute(executor)
+    .await?;
+    Ok(template_id)
+}
+
+#[tracing::instrument(skip_all, fields(template_id = %template.template_id))]
+pub async fn update_email_template<'a>(
+    executor: impl PgExecutor<'a>,
+    template: &EmailTemplate,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE email_templates
+        SET
+            name = $2,
+            html_layout = $3,
+            text_layout = $4,
+            postal_address = $5
+        WHERE template_id = $1
+        "#,
+        template.template_id,
+        template.name,
+        template.html_layout,
+        template.text_layout,
+        template.postal_address,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction))]
+pub async fn make_default_email_template(
+    transaction: &mut Transaction<'_, Postgres>,
+    template_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    // The unique index on `is_default` is checked row by row, so the old
+    // default goes first.
+    sqlx::query!(
+        r#"
+        UPDATE email_templates
+        SET is_default = false
+        WHERE is_default AND template_id <> $1
+        "#,
+        template_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE email_templates
+        SET is_default = true
+        WHERE template_id = $1
+        "#,
+        template_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// The default template can't be deleted. Returns `false` if nothing was
+/// deleted.
+#[tracing::instrument(skip(executor))]
+pub async fn delete_email_template<'a>(
+    executor: impl PgExecutor<'a>,
+    template_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM email_templates
+        WHERE template_id = $1 AND NOT is_default
+        "#,
+        template_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
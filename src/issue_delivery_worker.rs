@@ -1,5 +1,11 @@
 use crate::domain::SubscriberEmail;
 use crate::email_client::EmailClient;
+use crate::email_outbox::{delete_email, dequeue_email, schedule_retry, MAX_RETRIES};
+use crate::email_templates::get_email_template_or_default;
+use crate::newsletter_template::{Template, TemplateValues};
+use crate::routes::{archived_issue_link, preferences_link, unsubscribe_link};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::suppression_list::is_suppressed;
 use crate::{configuration::Settings, startup::get_connection_pool};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
 use std::time::Duration;
@@ -16,6 +22,7 @@ struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    template_id: Option<Uuid>,
 }
 
 #[tracing::instrument(skip_all)]
@@ -23,7 +30,7 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
+        SELECT title, text_content, html_content, template_id
         FROM newsletter_issues
         WHERE
             newsletter_issue_id = $1
@@ -46,38 +53,219 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
+    // Confirmation and welcome emails go first: someone is waiting for
+    // them, and they would otherwise sit behind every issue in the queue.
+    if let ExecutionOutcome::TaskCompleted = try_send_outbox_email(pool, email_client).await? {
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
     let task = dequeue_task(pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    if let Some((transaction, issue_id, email)) = task {
+    if let Some((mut transaction, issue_id, email)) = task {
         Span::current()
-            .record("newsletter_issue_id", &display(issue_id))
-            .record("subscriber_email", &display(&email));
+            .record("newsletter_issue_id", display(issue_id))
+            .record("subscriber_email", display(&email));
         let issue = get_issue(pool, issue_id).await?;
-        if let Err(e) = email_client
-            .send_email(
-                &email,
-                &issue.title,
-                &issue.html_content,
-                &issue.text_content,
-            )
-            .await
-        {
+        let subscriber = get_subscriber(pool, &email).await?;
+        let template = get_email_template_or_default(pool, issue.template_id).await?;
+        let values = TemplateValues {
+            subscriber_name: subscriber
+                .as_ref()
+                .map(|s| s.name.clone())
+                .unwrap_or_default(),
+            subscriber_email: email.as_ref().to_string(),
+            issue_title: issue.title.clone(),
+            issue_web_url: archived_issue_link(base_url, issue_id),
+            preferences_url: subscriber
+                .as_ref()
+                .map(|s| preferences_link(base_url, hmac_secret, s.id))
+                .unwrap_or_default(),
+            unsubscribe_url: subscriber
+                .as_ref()
+                .map(|s| unsubscribe_link(base_url, hmac_secret, s.id))
+                .unwrap_or_default(),
+            postal_address: template.postal_address.clone(),
+        };
+        let (html_content, text_content) = personalize(&issue, &values);
+        let (html_content, text_content) = template.wrap(&values, &html_content, &text_content);
+        let subscriber_id = subscriber.map(|s| s.id);
+        let outcome = if is_suppressed(pool, email.as_ref()).await? {
+            tracing::info!("Skipping an address on the suppression list.");
+            "suppressed"
+        } else {
+            match email_client
+                .send_email(&email, &issue.title, &html_content, &text_content)
+                .await
+            {
+                Ok(()) => "sent",
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber. Skipping."
+                    );
+                    "failed"
+                }
+            }
+        };
+        if let Some(subscriber_id) = subscriber_id {
+            record_delivery(&mut transaction, issue_id, subscriber_id, &email, outcome).await?;
+        }
+        delete_task(transaction, issue_id, email.as_ref()).await?;
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        email_id=tracing::field::Empty,
+        recipient=tracing::field::Empty
+    ),
+    err
+)]
+async fn try_send_outbox_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let email = match dequeue_email(&mut transaction).await? {
+        Some(email) => email,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current()
+        .record("email_id", display(email.email_id))
+        .record("recipient", display(&email.recipient));
+    let recipient = match SubscriberEmail::parse(email.recipient) {
+        Ok(recipient) => recipient,
+        Err(e) => {
             tracing::error!(
+                error.message = %e,
+                "Skipping an outbox email with an invalid recipient."
+            );
+            delete_email(&mut transaction, email.email_id).await?;
+            transaction.commit().await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    if is_suppressed(&mut *transaction, recipient.as_ref()).await? {
+        tracing::info!("Dropping an outbox email to an address on the suppression list.");
+        delete_email(&mut transaction, email.email_id).await?;
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    match email_client
+        .send_email(
+            &recipient,
+            &email.subject,
+            &email.html_content,
+            &email.text_content,
+        )
+        .await
+    {
+        Ok(()) => delete_email(&mut transaction, email.email_id).await?,
+        Err(e) if email.n_retries < MAX_RETRIES => {
+            tracing::warn!(
                 error.cause_chain = ?e,
                 error.message = %e,
-                "Failed to deliver issue to a confirmed subscriber. Skipping."
+                n_retries = email.n_retries,
+                "Failed to deliver an outbox email. Retrying later."
             );
+            schedule_retry(&mut transaction, email.email_id, email.n_retries).await?;
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to deliver an outbox email. Giving up."
+            );
+            delete_email(&mut transaction, email.email_id).await?;
         }
-        delete_task(transaction, issue_id, email.as_ref()).await?;
     }
+    transaction.commit().await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+/// Fills in the placeholders of the issue's HTML and text content. Issues
+/// published before placeholders existed may not parse, and are sent as
+/// they are.
+fn personalize(issue: &NewsletterIssue, values: &TemplateValues) -> (String, String) {
+    let html_content = match Template::parse(&issue.html_content) {
+        Ok(template) => template.render_html(values),
+        Err(e) => {
+            tracing::warn!(error.message = %e, "Sending the HTML content as it is.");
+            issue.html_content.clone()
+        }
+    };
+    let text_content = match Template::parse(&issue.text_content) {
+        Ok(template) => template.render_text(values),
+        Err(e) => {
+            tracing::warn!(error.message = %e, "Sending the text content as it is.");
+            issue.text_content.clone()
+        }
+    };
+    (html_content, text_content)
+}
+
+struct Subscriber {
+    id: Uuid,
+    name: String,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, name
+        FROM subscriptions
+        WHERE email = $1
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(subscriber)
+}
+
 type PgTransaction = Transaction<'static, Postgres>;
 
+/// Keeps track of what was sent to whom, for the admin subscriber pages.
+#[tracing::instrument(skip(transaction))]
+async fn record_delivery(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    email: &SubscriberEmail,
+    outcome: &str,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
+            newsletter_issue_id, subscriber_id, subscriber_email, outcome, attempted_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_id) DO UPDATE
+        SET subscriber_email = EXCLUDED.subscriber_email,
+            outcome = EXCLUDED.outcome,
+            attempted_at = EXCLUDED.attempted_at
+        "#,
+        issue_id,
+        subscriber_id,
+        email.as_ref(),
+        outcome,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     pool: &PgPool,
@@ -127,9 +315,14 @@ async fn delete_task(
     Ok(())
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: ApplicationBaseUrl,
+    hmac_secret: HmacSecret,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        match try_execute_task(&pool, &email_client, &base_url, &hmac_secret).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(1)).await;
             }
@@ -144,5 +337,7 @@ async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyh
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
     let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    let base_url = ApplicationBaseUrl(configuration.application.base_url);
+    let hmac_secret = HmacSecret(configuration.application.hmac_secret);
+    worker_loop(connection_pool, email_client, base_url, hmac_secret).await
 }
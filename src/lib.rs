@@ -1,11 +1,25 @@
+pub mod admin_audit;
 pub mod authentication;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_domain_rules;
+pub mod email_events;
+pub mod email_html;
+pub mod email_outbox;
+pub mod email_templates;
 mod idempotency;
 pub mod issue_delivery_worker;
+pub mod markdown;
+pub mod newsletter_template;
 pub mod routes;
 mod session_state;
+pub mod spam_protection;
 pub mod startup;
+pub mod subscriber_data;
+pub mod subscriber_import;
+pub mod suppression_list;
 pub mod telemetry;
+pub mod topics;
 mod utils;
+pub mod welcome_email;
@@ -0,0 +1,276 @@
+//! Newsletter issues written in Markdown, rendered to the HTML and plain
+//! text bodies of the email at publish time.
+//!
+//! Raw HTML in the source is shown as text rather than passed through, and
+//! links may only use the `http`, `https` and `mailto` schemes. Placeholders
+//! such as `{{ unsubscribe_url }}` are carried over to both bodies as they
+//! are, including in link destinations.
+
+use crate::newsletter_template::Placeholder;
+use pulldown_cmark::{html, CowStr, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub text: String,
+}
+
+pub fn render_markdown(source: &str) -> RenderedMarkdown {
+    let (source, placeholders) = protect_placeholders(source);
+    let events: Vec<Event> = Parser::new_ext(&source, Options::ENABLE_STRIKETHROUGH)
+        .map(sanitize)
+        .collect();
+    let mut html = String::new();
+    html::push_html(&mut html, events.iter().cloned());
+    let text = plain_text(&events);
+    RenderedMarkdown {
+        html: restore_placeholders(html, &placeholders),
+        text: restore_placeholders(text, &placeholders),
+    }
+}
+
+fn placeholder_token(i: usize) -> String {
+    format!("ZPLACEHOLDER{}Z", i)
+}
+
+/// Known placeholders are swapped for tokens Markdown leaves alone: `{{` and
+/// spaces would otherwise be escaped in, or break, link destinations.
+fn protect_placeholders(source: &str) -> (String, Vec<&str>) {
+    let mut protected = String::new();
+    let mut placeholders = Vec::new();
+    let mut rest = source;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end + 2) else {
+            break;
+        };
+        let placeholder = &rest[start..end];
+        let name = placeholder[2..placeholder.len() - 2].trim();
+        protected.push_str(&rest[..start]);
+        if Placeholder::ALL.iter().any(|p| p.name() == name) {
+            protected.push_str(&placeholder_token(placeholders.len()));
+            placeholders.push(placeholder);
+        } else {
+            protected.push_str(placeholder);
+        }
+        rest = &rest[end..];
+    }
+    protected.push_str(rest);
+    (protected, placeholders)
+}
+
+fn restore_placeholders(mut rendered: String, placeholders: &[&str]) -> String {
+    for (i, placeholder) in placeholders.iter().enumerate() {
+        rendered = rendered.replace(&placeholder_token(i), placeholder);
+    }
+    rendered
+}
+
+fn is_safe_url(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) if !scheme.contains(['/', '?', '#']) => {
+            ["http", "https", "mailto"].contains(&scheme.to_ascii_lowercase().as_str())
+        }
+        // A relative URL.
+        _ => true,
+    }
+}
+
+fn sanitize(event: Event) -> Event {
+    match event {
+        Event::Start(Tag::HtmlBlock) => Event::Start(Tag::Paragraph),
+        Event::End(TagEnd::HtmlBlock) => Event::End(TagEnd::Paragraph),
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if !is_safe_url(&dest_url) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: CowStr::Borrowed(""),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if !is_safe_url(&dest_url) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: CowStr::Borrowed(""),
+            title,
+            id,
+        }),
+        event => event,
+    }
+}
+
+/// Prefixes the first line of `block` with `first` and the others with
+/// `rest`, leaving blank lines blank.
+fn prefix_lines(block: &str, first: &str, rest: &str) -> String {
+    let mut prefixed = String::new();
+    for (i, line) in block.trim_end().lines().enumerate() {
+        let prefix = if i == 0 { first } else { rest };
+        if line.is_empty() {
+            prefixed.push_str(prefix.trim_end());
+        } else {
+            prefixed.push_str(prefix);
+            prefixed.push_str(line);
+        }
+        prefixed.push('\n');
+    }
+    prefixed
+}
+
+/// Renders the events as text meant to be read as is: links are followed
+/// by their URL, headings are underlined and lists keep their markers.
+/// Blocks that need their lines prefixed are rendered in a buffer of their
+/// own, pushed on `buffers`.
+fn plain_text(events: &[Event]) -> String {
+    let mut buffers = vec![String::new()];
+    let mut list_numbers: Vec<Option<u64>> = Vec::new();
+    let mut links: Vec<(usize, &str)> = Vec::new();
+    let mut heading_start = 0;
+    for event in events {
+        let buffer = buffers.last_mut().unwrap();
+        match event {
+            Event::Start(Tag::Heading { .. }) => heading_start = buffer.len(),
+            Event::End(TagEnd::Heading(level)) => {
+                let underline = if *level == HeadingLevel::H1 { "=" } else { "-" };
+                let width = buffer[heading_start..].chars().count();
+                buffer.push('\n');
+                buffer.push_str(&underline.repeat(width));
+                buffer.push_str("\n\n");
+            }
+            Event::End(TagEnd::Paragraph) => buffer.push_str("\n\n"),
+            Event::Start(Tag::BlockQuote(_) | Tag::Item | Tag::CodeBlock(_)) => {
+                buffers.push(String::new())
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                let block = buffers.pop().unwrap();
+                let parent = buffers.last_mut().unwrap();
+                parent.push_str(&prefix_lines(&block, "> ", "> "));
+                parent.push('\n');
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                let block = buffers.pop().unwrap();
+                let parent = buffers.last_mut().unwrap();
+                parent.push_str(&prefix_lines(&block, "    ", "    "));
+                parent.push('\n');
+            }
+            Event::Start(Tag::List(first_number)) => {
+                // Items of tight lists are not paragraphs, and don't end
+                // with a line break of their own.
+                if !buffer.is_empty() && !buffer.ends_with('\n') {
+                    buffer.push('\n');
+                }
+                list_numbers.push(*first_number)
+            }
+            Event::End(TagEnd::List(_)) => {
+                list_numbers.pop();
+                buffer.push('\n');
+            }
+            Event::End(TagEnd::Item) => {
+                let marker = match list_numbers.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                let item = buffers.pop().unwrap();
+                let parent = buffers.last_mut().unwrap();
+                let indent = " ".repeat(marker.len());
+                parent.push_str(&prefix_lines(item.trim(), &marker, &indent));
+            }
+            Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) => {
+                links.push((buffer.len(), dest_url))
+            }
+            Event::End(TagEnd::Link | TagEnd::Image) => {
+                let (start, url) = links.pop().unwrap();
+                if !url.is_empty() && buffer[start..] != *url {
+                    buffer.push_str(&format!(" ({})", url));
+                }
+            }
+            Event::Text(text) | Event::Code(text) | Event::Html(text) => buffer.push_str(text),
+            Event::SoftBreak | Event::HardBreak => buffer.push('\n'),
+            Event::Rule => buffer.push_str("----------\n\n"),
+            _ => {}
+        }
+    }
+    let mut text = buffers.swap_remove(0).trim_end().to_string();
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_markdown;
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        let rendered =
+            render_markdown("# Rust Weekly\n\nSome *news*, [read more](https://example.com).");
+        assert_eq!(
+            rendered.html,
+            "<h1>Rust Weekly</h1>\n\
+            <p>Some <em>news</em>, <a href=\"https://example.com\">read more</a>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn the_plain_text_version_reads_like_text() {
+        let rendered = render_markdown(
+            "# Rust Weekly\n\n\
+            Some *news*, [read more](https://example.com) or see <https://example.org>.\n\n\
+            > A quote\n> on two lines\n\n\
+            1. First\n2. Second\n   - nested\n\n\
+            ```\nlet x = 1;\n```",
+        );
+        assert_eq!(
+            rendered.text,
+            "Rust Weekly\n\
+            ===========\n\n\
+            Some news, read more (https://example.com) or see https://example.org.\n\n\
+            > A quote\n\
+            > on two lines\n\n\
+            1. First\n\
+            2. Second\n   \
+            - nested\n\n    \
+            let x = 1;\n"
+        );
+    }
+
+    #[test]
+    fn raw_html_is_shown_as_text() {
+        let rendered = render_markdown("<script>alert(1)</script>\n\nHi <b>there</b>");
+        assert!(!rendered.html.contains("<script>"));
+        assert!(rendered.html.contains("&lt;script&gt;"));
+        assert!(rendered.html.contains("Hi &lt;b&gt;there&lt;/b&gt;"));
+    }
+
+    #[test]
+    fn links_with_unsafe_schemes_are_dropped() {
+        let rendered = render_markdown("[click](javascript:alert(1)) [mail](mailto:a@example.com)");
+        assert!(!rendered.html.contains("javascript"));
+        assert!(rendered
+            .html
+            .contains(r#"<a href="mailto:a@example.com">mail</a>"#));
+    }
+
+    #[test]
+    fn placeholders_are_carried_over() {
+        let rendered = render_markdown(
+            "Hi {{ subscriber.name }}, [unsubscribe]({{ unsubscribe_url }}) {{ not.a.placeholder }}",
+        );
+        assert_eq!(
+            rendered.html,
+            "<p>Hi {{ subscriber.name }}, \
+            <a href=\"{{ unsubscribe_url }}\">unsubscribe</a> {{ not.a.placeholder }}</p>\n"
+        );
+        assert_eq!(
+            rendered.text,
+            "Hi {{ subscriber.name }}, unsubscribe ({{ unsubscribe_url }}) {{ not.a.placeholder }}\n"
+        );
+    }
+}
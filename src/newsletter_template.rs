@@ -0,0 +1,267 @@
+//! Placeholders in the content of newsletter issues, e.g.
+//! `Hello {{ subscriber.name }}!`, filled in for each recipient when the
+//! issue is sent. Only the placeholders listed in [`Placeholder`] exist:
+//! content is checked when the issue is published, so that a typo doesn't
+//! reach subscribers.
+//!
+//! The layouts of email templates use the same syntax, plus a
+//! `{{ content }}` slot for the body of the email.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placeholder {
+    SubscriberName,
+    SubscriberEmail,
+    IssueTitle,
+    /// Where the issue can be read in the archive.
+    IssueWebUrl,
+    PreferencesUrl,
+    UnsubscribeUrl,
+    /// The sender's, from the email template.
+    PostalAddress,
+    /// Where a layout puts the body of the email. Only layouts have it.
+    Content,
+}
+
+impl Placeholder {
+    /// The placeholders email content can use.
+    pub const ALL: [Placeholder; 7] = [
+        Placeholder::SubscriberName,
+        Placeholder::SubscriberEmail,
+        Placeholder::IssueTitle,
+        Placeholder::IssueWebUrl,
+        Placeholder::PreferencesUrl,
+        Placeholder::UnsubscribeUrl,
+        Placeholder::PostalAddress,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Placeholder::SubscriberName => "subscriber.name",
+            Placeholder::SubscriberEmail => "subscriber.email",
+            Placeholder::IssueTitle => "issue.title",
+            Placeholder::IssueWebUrl => "issue.web_url",
+            Placeholder::PreferencesUrl => "preferences_url",
+            Placeholder::UnsubscribeUrl => "unsubscribe_url",
+            Placeholder::PostalAddress => "postal_address",
+            Placeholder::Content => "content",
+        }
+    }
+}
+
+fn placeholder_list() -> String {
+    Placeholder::ALL
+        .iter()
+        .map(|p| format!("{{{{ {} }}}}", p.name()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum TemplateError {
+    #[error("{{{{ {0} }}}} is not a known placeholder. Use one of {list}.", list = placeholder_list())]
+    UnknownPlaceholder(String),
+    #[error("A placeholder is missing its closing }}}}.")]
+    UnclosedPlaceholder,
+}
+
+/// What the placeholders stand for, for one recipient.
+pub struct TemplateValues {
+    pub subscriber_name: String,
+    pub subscriber_email: String,
+    pub issue_title: String,
+    pub issue_web_url: String,
+    pub preferences_url: String,
+    pub unsubscribe_url: String,
+    pub postal_address: String,
+}
+
+impl TemplateValues {
+    /// Made-up values, to preview an issue before it is published.
+    pub fn sample(base_url: &str, issue_title: &str, postal_address: &str) -> Self {
+        Self {
+            subscriber_name: "Ursula Le Guin".into(),
+            subscriber_email: "ursula@example.com".into(),
+            issue_title: issue_title.into(),
+            issue_web_url: format!("{}/archive", base_url),
+            preferences_url: format!("{}/subscriptions/preferences", base_url),
+            unsubscribe_url: format!("{}/subscriptions/unsubscribe", base_url),
+            postal_address: postal_address.into(),
+        }
+    }
+
+    fn get(&self, placeholder: Placeholder) -> &str {
+        match placeholder {
+            Placeholder::SubscriberName => &self.subscriber_name,
+            Placeholder::SubscriberEmail => &self.subscriber_email,
+            Placeholder::IssueTitle => &self.issue_title,
+            Placeholder::IssueWebUrl => &self.issue_web_url,
+            Placeholder::PreferencesUrl => &self.preferences_url,
+            Placeholder::UnsubscribeUrl => &self.unsubscribe_url,
+            Placeholder::PostalAddress => &self.postal_address,
+            Placeholder::Content => "",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Template(Vec<Segment>);
+
+impl Template {
+    pub fn parse(s: &str) -> Result<Self, TemplateError> {
+        Self::parse_with(s, &Placeholder::ALL)
+    }
+
+    /// Like `parse`, for the layout of an email template.
+    pub fn parse_layout(s: &str) -> Result<Self, TemplateError> {
+        Self::parse_with(
+            s,
+            &[&Placeholder::ALL[..], &[Placeholder::Content]].concat(),
+        )
+    }
+
+    fn parse_with(s: &str, allowed: &[Placeholder]) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or(TemplateError::UnclosedPlaceholder)?;
+            let name = after[..end].trim();
+            let placeholder = allowed
+                .iter()
+                .copied()
+                .find(|p| p.name() == name)
+                .ok_or_else(|| TemplateError::UnknownPlaceholder(name.to_string()))?;
+            segments.push(Segment::Placeholder(placeholder));
+            rest = &after[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        Ok(Self(segments))
+    }
+
+    /// How many times the template uses `placeholder`.
+    pub fn count(&self, placeholder: Placeholder) -> usize {
+        self.0
+            .iter()
+            .filter(|segment| **segment == Segment::Placeholder(placeholder))
+            .count()
+    }
+
+    pub fn render_text(&self, values: &TemplateValues) -> String {
+        self.render(values, |value| value.to_string(), "")
+    }
+
+    /// Values are escaped, so that a subscriber's name can't inject markup.
+    pub fn render_html(&self, values: &TemplateValues) -> String {
+        self.render(values, htmlescape::encode_minimal, "")
+    }
+
+    /// Renders a layout around `content`, which is inserted as it is.
+    pub fn wrap_text(&self, values: &TemplateValues, content: &str) -> String {
+        self.render(values, |value| value.to_string(), content)
+    }
+
+    /// Renders a layout around `content`, which is HTML already and is
+    /// inserted as it is.
+    pub fn wrap_html(&self, values: &TemplateValues, content: &str) -> String {
+        self.render(values, htmlescape::encode_minimal, content)
+    }
+
+    fn render(
+        &self,
+        values: &TemplateValues,
+        escape: impl Fn(&str) -> String,
+        content: &str,
+    ) -> String {
+        let mut rendered = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Placeholder(Placeholder::Content) => rendered.push_str(content),
+                Segment::Placeholder(placeholder) => {
+                    rendered.push_str(&escape(values.get(*placeholder)))
+                }
+            }
+        }
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Template, TemplateError, TemplateValues};
+    use claims::{assert_err, assert_ok};
+
+    fn values() -> TemplateValues {
+        TemplateValues {
+            subscriber_name: "Ursula <Le Guin>".into(),
+            ..TemplateValues::sample("https://example.com", "Issue #1", "1 Main St")
+        }
+    }
+
+    #[test]
+    fn content_without_placeholders_is_left_as_is() {
+        let template = assert_ok!(Template::parse("Hello, <b>world</b> {"));
+        assert_eq!(template.render_html(&values()), "Hello, <b>world</b> {");
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let template = assert_ok!(Template::parse(
+            "Hi {{subscriber.name}}, {{ issue.title }} is at {{  issue.web_url }}"
+        ));
+        assert_eq!(
+            template.render_text(&values()),
+            "Hi Ursula <Le Guin>, Issue #1 is at https://example.com/archive"
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let template = assert_ok!(Template::parse("<p>Hi {{ subscriber.name }}</p>"));
+        assert_eq!(
+            template.render_html(&values()),
+            "<p>Hi Ursula &lt;Le Guin&gt;</p>"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        let error = assert_err!(Template::parse("Hi {{ subscriber.nmae }}"));
+        assert_eq!(
+            error,
+            TemplateError::UnknownPlaceholder("subscriber.nmae".into())
+        );
+        assert!(error.to_string().contains("{{ subscriber.name }}"));
+    }
+
+    #[test]
+    fn only_layouts_have_a_content_slot() {
+        assert_err!(Template::parse("{{ content }}"));
+        let layout = assert_ok!(Template::parse_layout(
+            "<main>{{ content }}</main><footer>{{ postal_address }}</footer>"
+        ));
+        assert_eq!(
+            layout.wrap_html(&values(), "<p>Hi</p>"),
+            "<main><p>Hi</p></main><footer>1 Main St</footer>"
+        );
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        assert_eq!(
+            Template::parse("Hi {{ subscriber.name"),
+            Err(TemplateError::UnclosedPlaceholder)
+        );
+    }
+}
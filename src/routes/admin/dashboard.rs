@@ -18,7 +18,11 @@ pub async fn admin_dashboard(
 
     let mut html_message = String::new();
     for message in flash_messages.iter() {
-        let _ = write!(html_message, "<p><i>{}</i></p>", message.content());
+        let _ = write!(
+            html_message,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(message.content())
+        );
     }
 
     Ok(HttpResponse::Ok().body(format!(
@@ -34,6 +38,12 @@ pub async fn admin_dashboard(
     {html_message}
     <ol>
         <li><a href="/admin/newsletters">Send newsletters</a></li>
+        <li><a href="/admin/subscribers">Subscribers</a></li>
+        <li><a href="/admin/topics">Topics</a></li>
+        <li><a href="/admin/welcome_email">Edit welcome email</a></li>
+        <li><a href="/admin/templates">Email templates</a></li>
+        <li><a href="/admin/domain_rules">Blocked and allowed email domains</a></li>
+        <li><a href="/admin/suppressions">Suppressed addresses</a></li>
         <li><a href="/admin/password">Change password</a></li>
         <li>
             <form name="logoutForm" action="/admin/logout" method="post">
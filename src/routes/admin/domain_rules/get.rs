@@ -0,0 +1,69 @@
+use crate::email_domain_rules::get_domain_rules;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn domain_rules_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_message = String::new();
+    for message in flash_messages.iter() {
+        let _ = write!(
+            html_message,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(message.content())
+        );
+    }
+    let rules = get_domain_rules(pool.get_ref()).await.map_err(e500)?;
+    let mut rules_html = String::new();
+    for rule in rules {
+        let _ = write!(
+            rules_html,
+            r#"<li>{domain} ({rule})
+    <form method="post" action="/admin/domain_rules/delete" style="display: inline">
+        <input hidden type="text" name="domain" value="{domain_attribute}" />
+        <button type="submit">Remove</button>
+    </form>
+</li>"#,
+            domain = htmlescape::encode_minimal(&rule.domain),
+            rule = rule.rule,
+            domain_attribute = htmlescape::encode_attribute(&rule.domain),
+        );
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>Email domain rules</title>
+</head>
+<body>
+{html_message}
+<p>Subscriptions from blocked domains are refused, on top of the blocklist in the configuration.
+Allowed domains are accepted even if a blocklist mentions them. Rules apply to subdomains too.</p>
+<ul>
+    {rules_html}
+</ul>
+<form method="post" action="/admin/domain_rules">
+    <div>
+        <label>Domain: <input type="text" placeholder="example.com" name="domain" /></label>
+    </div>
+    <div>
+        <label><input type="radio" name="rule" value="block" checked /> Block</label>
+        <label><input type="radio" name="rule" value="allow" /> Allow</label>
+    </div>
+    <button type="submit">Save</button>
+</form>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+    "#,
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::domain_rules_form;
+pub use post::{add_domain_rule, remove_domain_rule};
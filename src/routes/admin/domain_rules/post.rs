@@ -0,0 +1,62 @@
+use crate::authentication::UserId;
+use crate::email_domain_rules::{delete_domain_rule, normalize_domain, save_domain_rule};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct DomainRuleFormData {
+    domain: String,
+    rule: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveDomainRuleFormData {
+    domain: String,
+}
+
+#[tracing::instrument(
+    name = "Add an email domain rule",
+    skip(pool, form),
+    fields(user_id=%&*user_id, domain=%form.domain)
+)]
+pub async fn add_domain_rule(
+    pool: web::Data<PgPool>,
+    form: web::Form<DomainRuleFormData>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let domain = match normalize_domain(&form.domain) {
+        Ok(domain) => domain,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/domain_rules"));
+        }
+    };
+    if form.rule != "block" && form.rule != "allow" {
+        FlashMessage::error("A rule either blocks or allows a domain.").send();
+        return Ok(see_other("/admin/domain_rules"));
+    }
+    save_domain_rule(pool.get_ref(), &domain, &form.rule)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!("The rule for {} has been saved.", domain)).send();
+    Ok(see_other("/admin/domain_rules"))
+}
+
+#[tracing::instrument(
+    name = "Remove an email domain rule",
+    skip(pool, form),
+    fields(user_id=%&*user_id, domain=%form.domain)
+)]
+pub async fn remove_domain_rule(
+    pool: web::Data<PgPool>,
+    form: web::Form<RemoveDomainRuleFormData>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    delete_domain_rule(pool.get_ref(), &form.domain)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!("The rule for {} has been removed.", form.domain)).send();
+    Ok(see_other("/admin/domain_rules"))
+}
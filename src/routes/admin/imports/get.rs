@@ -0,0 +1,167 @@
+use crate::subscriber_import::{get_import_rejections, get_import_summary, get_recent_imports};
+use crate::utils::{csv_field, e500};
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn import_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_message = String::new();
+    for message in flash_messages.iter() {
+        let _ = write!(
+            html_message,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(message.content())
+        );
+    }
+    let imports = get_recent_imports(pool.get_ref()).await.map_err(e500)?;
+    let mut imports_html = String::new();
+    for import in imports {
+        let state = if import.finished_at.is_some() {
+            ""
+        } else {
+            " (unfinished)"
+        };
+        let _ = write!(
+            imports_html,
+            r#"<li><a href="/admin/imports/{}">{}</a> by {}: {} imported, {} rejected{}</li>"#,
+            import.import_id,
+            import.created_at.format("%Y-%m-%d %H:%M"),
+            htmlescape::encode_minimal(&import.username),
+            import.n_imported,
+            import.n_rejected,
+            state,
+        );
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>Import subscribers</title>
+</head>
+<body>
+{html_message}
+<p>Upload a CSV file with a header row that has an <code>email</code> and a <code>name</code> column.
+Other columns are ignored, and addresses that are already subscribed are skipped.</p>
+<form method="post" action="/admin/imports" enctype="multipart/form-data">
+    <div>
+        <label>Imported subscribers are:
+            <select name="mode">
+                <option value="send_confirmation">Sent a confirmation email</option>
+                <option value="confirmed">Confirmed already</option>
+            </select>
+        </label>
+    </div>
+    <div>
+        <label>File: <input type="file" name="file" accept=".csv,text/csv" /></label>
+    </div>
+    <button type="submit">Import</button>
+</form>
+<h2>Recent imports</h2>
+<ul>
+    {imports_html}
+</ul>
+<p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>
+    "#,
+        )))
+}
+
+pub async fn import_details(
+    pool: web::Data<PgPool>,
+    import_id: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let summary = match get_import_summary(pool.get_ref(), import_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(summary) => summary,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let state = match summary.finished_at {
+        Some(finished_at) => format!("Finished at {}.", finished_at.format("%Y-%m-%d %H:%M")),
+        None => "The upload was interrupted: only the rows counted below were processed.".into(),
+    };
+    let mode = if summary.confirmed {
+        "imported as confirmed"
+    } else {
+        "sent a confirmation email"
+    };
+    let report_link = if summary.n_rejected > 0 {
+        format!(
+            r#"<p><a href="/admin/imports/{}/rejections.csv">Download the rejected rows</a></p>"#,
+            import_id
+        )
+    } else {
+        String::new()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>Import</title>
+</head>
+<body>
+<p>Started at {created_at}. {state}</p>
+<ul>
+    <li>{n_imported} subscribers imported, {mode}</li>
+    <li>{n_duplicates} rows skipped, the address was already subscribed</li>
+    <li>{n_rejected} rows rejected</li>
+</ul>
+{report_link}
+<p><a href="/admin/imports">&lt;- Back</a></p>
+</body>
+</html>
+    "#,
+            created_at = summary.created_at.format("%Y-%m-%d %H:%M"),
+            n_imported = summary.n_imported,
+            n_duplicates = summary.n_duplicates,
+            n_rejected = summary.n_rejected,
+        )))
+}
+
+pub async fn import_rejections_csv(
+    pool: web::Data<PgPool>,
+    import_id: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let rejections = get_import_rejections(pool.get_ref(), import_id)
+        .await
+        .map_err(e500)?;
+    let mut csv = String::from("row,email,name,reason\n");
+    for rejection in rejections {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{}",
+            rejection.row_number,
+            csv_field(&rejection.email),
+            csv_field(&rejection.name),
+            csv_field(&rejection.reason),
+        );
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "import-{}-rejections.csv",
+                import_id
+            ))],
+        })
+        .body(csv))
+}
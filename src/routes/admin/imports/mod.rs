@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{import_details, import_form, import_rejections_csv};
+pub use post::import_subscribers;
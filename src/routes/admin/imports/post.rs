@@ -0,0 +1,138 @@
+use crate::authentication::UserId;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::subscriber_import::{
+    finish_import, import_batch, start_import, CsvRecords, ImportColumns, ImportRow,
+    IMPORT_BATCH_SIZE,
+};
+use crate::utils::{e400, e500, see_other};
+use actix_multipart::{Field, Multipart};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Expects a multipart form with a `mode` field, `confirmed` or
+/// `send_confirmation`, followed by the CSV `file`. The file is processed as
+/// it is uploaded, `IMPORT_BATCH_SIZE` rows at a time.
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(payload, pool, base_url, hmac_secret),
+    fields(user_id=%&*user_id, import_id=tracing::field::Empty)
+)]
+pub async fn import_subscribers(
+    mut payload: Multipart,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut confirmed = None;
+    while let Some(mut field) = payload.try_next().await.map_err(e400)? {
+        match field.name() {
+            Some("mode") => {
+                let mode = field.bytes(64).await.map_err(e400)?.map_err(e400)?;
+                confirmed = match &mode[..] {
+                    b"confirmed" => Some(true),
+                    b"send_confirmation" => Some(false),
+                    _ => None,
+                };
+            }
+            Some("file") => {
+                let confirmed = match confirmed {
+                    Some(confirmed) => confirmed,
+                    None => {
+                        FlashMessage::error("Pick how the imported subscribers are confirmed.")
+                            .send();
+                        return Ok(see_other("/admin/imports"));
+                    }
+                };
+                let outcome = import_file(
+                    &pool,
+                    &base_url,
+                    &hmac_secret,
+                    **user_id,
+                    confirmed,
+                    &mut field,
+                )
+                .await
+                .map_err(e500)?;
+                return match outcome {
+                    Ok(import_id) => Ok(see_other(&format!("/admin/imports/{}", import_id))),
+                    Err(e) => {
+                        FlashMessage::error(e).send();
+                        Ok(see_other("/admin/imports"))
+                    }
+                };
+            }
+            _ => while field.try_next().await.map_err(e400)?.is_some() {},
+        }
+    }
+    FlashMessage::error("Pick a CSV file to import.").send();
+    Ok(see_other("/admin/imports"))
+}
+
+/// Returns the id of the import, or why the file can't be imported at all.
+async fn import_file(
+    pool: &PgPool,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+    user_id: Uuid,
+    confirmed: bool,
+    file: &mut Field,
+) -> Result<Result<Uuid, String>, anyhow::Error> {
+    let mut csv = CsvRecords::default();
+    // Known once the header row has been read.
+    let mut import: Option<(Uuid, ImportColumns)> = None;
+    let mut row_number = 0;
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    loop {
+        let chunk = file
+            .try_next()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read the uploaded file: {}", e))?;
+        let records = match &chunk {
+            Some(chunk) => csv.push(chunk),
+            None => csv.finish(),
+        };
+        for record in records {
+            row_number += 1;
+            let (import_id, columns) = match &import {
+                Some((import_id, columns)) => (*import_id, columns),
+                None => {
+                    let columns = match ImportColumns::from_header(&record) {
+                        Ok(columns) => columns,
+                        Err(e) => return Ok(Err(e)),
+                    };
+                    let import_id = start_import(pool, user_id, confirmed)
+                        .await
+                        .context("Failed to record the import.")?;
+                    tracing::Span::current()
+                        .record("import_id", tracing::field::display(import_id));
+                    import = Some((import_id, columns));
+                    continue;
+                }
+            };
+            batch.push(ImportRow::new(columns, row_number, &record));
+            if batch.len() == IMPORT_BATCH_SIZE {
+                let rows = std::mem::replace(&mut batch, Vec::with_capacity(IMPORT_BATCH_SIZE));
+                import_batch(pool, base_url, hmac_secret, import_id, confirmed, rows).await?;
+            }
+        }
+        if chunk.is_none() {
+            break;
+        }
+    }
+    let import_id = match import {
+        Some((import_id, _)) => import_id,
+        None => return Ok(Err("The CSV file is empty.".into())),
+    };
+    if !batch.is_empty() {
+        import_batch(pool, base_url, hmac_secret, import_id, confirmed, batch).await?;
+    }
+    finish_import(pool, import_id)
+        .await
+        .context("Failed to mark the import as finished.")?;
+    Ok(Ok(import_id))
+}
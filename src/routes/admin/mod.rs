@@ -1,7 +1,21 @@
 mod dashboard;
+mod domain_rules;
+mod imports;
 mod newsletters;
 mod password;
+mod subscribers;
+mod suppressions;
+mod templates;
+mod topics;
+mod welcome_email;
 
 pub use dashboard::admin_dashboard;
+pub use domain_rules::*;
+pub use imports::*;
 pub use newsletters::*;
 pub use password::*;
+pub use subscribers::*;
+pub use suppressions::*;
+pub use templates::*;
+pub use topics::*;
+pub use welcome_email::*;
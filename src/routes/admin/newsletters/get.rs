@@ -1,16 +1,64 @@
+use crate::email_templates::{get_email_templates, template_options};
+use crate::newsletter_template::Placeholder;
+use crate::topics::{get_topics, topic_checkboxes};
+use crate::utils::e500;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
 use std::fmt::Write;
+use uuid::Uuid;
+
+/// What the form is filled with: nothing at first, what the admin typed
+/// when they preview the issue.
+#[derive(Default)]
+pub(super) struct FormContent<'a> {
+    pub title: &'a str,
+    pub content_markdown: &'a str,
+    pub content_text: &'a str,
+    pub content_html: &'a str,
+    pub topics: &'a [Uuid],
+    pub template_id: Option<Uuid>,
+}
 
 pub async fn newsletter_form(
+    pool: web::Data<PgPool>,
     flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut html_message = String::new();
-    let idempotency_key = uuid::Uuid::new_v4();
     for message in flash_messages.iter() {
-        let _ = write!(html_message, "<p><i>{}</i></p>", message.content());
+        let _ = write!(
+            html_message,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(message.content())
+        );
     }
+    newsletter_page(
+        &pool,
+        &html_message,
+        &FormContent::default(),
+        &Uuid::new_v4().to_string(),
+        "",
+    )
+    .await
+}
+
+pub(super) async fn newsletter_page(
+    pool: &PgPool,
+    html_message: &str,
+    content: &FormContent<'_>,
+    idempotency_key: &str,
+    preview: &str,
+) -> Result<HttpResponse, actix_web::Error> {
+    let topics = get_topics(pool).await.map_err(e500)?;
+    let topics = topic_checkboxes(&topics, content.topics);
+    let templates = get_email_templates(pool).await.map_err(e500)?;
+    let templates = template_options(&templates, content.template_id);
+    let placeholders = Placeholder::ALL
+        .iter()
+        .map(|p| format!("<code>{{{{ {} }}}}</code>", p.name()))
+        .collect::<Vec<_>>()
+        .join(", ");
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -23,24 +71,44 @@ pub async fn newsletter_form(
 </head>
 <body>
 {html_message}
+{preview}
 <form method="post" action="/admin/newsletters">
     <div>
-        <label>Title: <input type="text" placeholder="Enter title" name="title" /></label>
+        <label>Title: <input type="text" placeholder="Enter title" name="title" value="{title}" /></label>
+    </div>
+    <p>Write the issue in Markdown, or leave it empty and fill in both the text and HTML
+    versions yourself. The content can be personalized with {placeholders}.</p>
+    <div>
+        <label>Markdown</label>
+        <textarea name="content_markdown">{content_markdown}</textarea>
     </div>
     <div>
         <label>Text Content</label>
-        <textarea name="content_text"></textarea>
+        <textarea name="content_text">{content_text}</textarea>
     </div>
     <div>
         <label>Html Context</label>
-        <textarea name="content_html"></textarea>
+        <textarea name="content_html">{content_html}</textarea>
+    </div>
+    <div>
+        <label>Template: <select name="template_id">{templates}</select></label>
     </div>
-    <input hidden type="text" name="idempotency_key" value="{idempotency_key}"
+    <fieldset>
+        <legend>Send to (leave all unticked to send to every subscriber)</legend>
+        {topics}
+    </fieldset>
+    <input hidden type="text" name="idempotency_key" value="{idempotency_key}" />
+    <button type="submit" formaction="/admin/newsletters/preview">Preview</button>
     <button type="submit">Send</button>
 </form>
 <p><a href="/admin/dashboard">&lt;- Back</a></p>
 </body>
 </html>
     "#,
+            title = htmlescape::encode_attribute(content.title),
+            content_markdown = htmlescape::encode_minimal(content.content_markdown),
+            content_text = htmlescape::encode_minimal(content.content_text),
+            content_html = htmlescape::encode_minimal(content.content_html),
+            idempotency_key = htmlescape::encode_attribute(idempotency_key),
         )))
 }
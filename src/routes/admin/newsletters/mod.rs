@@ -1,5 +1,10 @@
 mod get;
 mod post;
+mod preview;
 
 pub use get::newsletter_form;
 pub use post::publish_newsletter;
+pub(crate) use post::{
+    enqueue_delivery_tasks, insert_newsletter_issue, newsletter_warnings, validate_newsletter,
+};
+pub use preview::preview_newsletter;
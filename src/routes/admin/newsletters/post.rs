@@ -1,6 +1,12 @@
 use crate::authentication::UserId;
+use crate::domain::SubscriptionStatus;
+use crate::email_html::{prepare_html, size_warning};
+use crate::email_templates::get_email_template_or_default;
 use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
-use crate::utils::{e400, e500, see_other};
+use crate::markdown::render_markdown;
+use crate::newsletter_template::{Template, TemplateValues};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e400, e500, see_other, HtmlForm};
 use actix_web::web;
 use actix_web::web::ReqData;
 use actix_web::HttpResponse;
@@ -12,56 +18,63 @@ use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
 pub struct NewsletterFormData {
-    title: String,
-    content_text: String,
-    content_html: String,
-    idempotency_key: String,
+    pub(super) title: String,
+    /// When given, the text and HTML content are rendered from it instead
+    /// of being typed in.
+    #[serde(default)]
+    pub(super) content_markdown: String,
+    pub(super) content_text: String,
+    pub(super) content_html: String,
+    pub(super) idempotency_key: String,
+    /// The topics to publish the issue to; none means every subscriber.
+    #[serde(default)]
+    pub(super) topics: Vec<Uuid>,
+    /// The email template to wrap the issue in; none means the default one.
+    #[serde(default)]
+    pub(super) template_id: Option<Uuid>,
 }
 
-struct NewsletterData {
-    title: String,
-    content_text: String,
-    content_html: String,
-}
-
-impl NewsletterData {
-    fn from(title: String, content_text: String, content_html: String) -> Self {
-        Self {
-            title,
-            content_text,
-            content_html,
-        }
-    }
+/// The issue as it gets stored.
+pub(crate) struct NewsletterData {
+    pub(crate) title: String,
+    pub(crate) content_text: String,
+    pub(crate) content_html: String,
+    pub(crate) content_markdown: Option<String>,
+    pub(crate) template_id: Option<Uuid>,
+    /// About what was removed from the HTML content.
+    pub(crate) warnings: Vec<String>,
 }
 
 #[derive(Error, Debug)]
-enum NewsletterDataError {
+pub(crate) enum NewsletterDataError {
     #[error("{0}")]
     ValidationError(String),
 }
 
 #[tracing::instrument(
     name = "Publish a newsletter",
-    skip(db_pool, form),
+    skip(db_pool, base_url, form),
     fields(user_id=%&*user_id)
 )]
 pub async fn publish_newsletter(
     db_pool: web::Data<PgPool>,
-    form: web::Form<NewsletterFormData>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    form: HtmlForm<NewsletterFormData>,
     user_id: ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
+    let newsletter_data = match validate_form_data(&form.0) {
+        Ok(newsletter_data) => newsletter_data,
+        Err(e) => {
+            FlashMessage::error(format!("{}", e)).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
     let NewsletterFormData {
         idempotency_key,
-        title,
-        content_text,
-        content_html,
+        topics,
+        ..
     } = form.0;
-    let newsletter_data = NewsletterData::from(title, content_text, content_html);
-    if let Err(e) = validate_form_data(&newsletter_data) {
-        FlashMessage::error(format!("{}", e.to_string())).send();
-        return Ok(see_other("/admin/newsletters"));
-    }
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
     let mut transaction = match try_processing(&db_pool, &idempotency_key, *user_id)
         .await
@@ -73,21 +86,22 @@ pub async fn publish_newsletter(
             return Ok(response);
         }
     };
-    let issue_id = insert_newsletter_issue(
-        &mut transaction,
-        newsletter_data.title.as_str(),
-        newsletter_data.content_text.as_str(),
-        newsletter_data.content_text.as_str(),
-    )
-    .await
-    .context("Failed to store newsletter issue details.")
-    .map_err(e500)?;
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    let issue_id = insert_newsletter_issue(&mut transaction, &newsletter_data)
+        .await
+        .context("Failed to store newsletter issue details.")
+        .map_err(e500)?;
+    enqueue_delivery_tasks(&mut transaction, issue_id, &topics)
         .await
         .context("Failed to enqueue delivery tasks")
         .map_err(e500)?;
 
     success_message().send();
+    for warning in newsletter_warnings(&db_pool, &base_url, &newsletter_data)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::warning(warning).send();
+    }
     let response = see_other("/admin/dashboard");
     let response = save_response(transaction, &idempotency_key, *user_id, response)
         .await
@@ -95,35 +109,112 @@ pub async fn publish_newsletter(
     Ok(response)
 }
 
+/// What was removed from the HTML content, and whether the email, wrapped in
+/// its template, is big enough to be clipped.
+pub(crate) async fn newsletter_warnings(
+    pool: &PgPool,
+    base_url: &ApplicationBaseUrl,
+    newsletter_data: &NewsletterData,
+) -> Result<Vec<String>, sqlx::Error> {
+    let template = get_email_template_or_default(pool, newsletter_data.template_id).await?;
+    let values = TemplateValues::sample(
+        &base_url.0,
+        &newsletter_data.title,
+        &template.postal_address,
+    );
+    let (html, _) = template.wrap(
+        &values,
+        &newsletter_data.content_html,
+        &newsletter_data.content_text,
+    );
+    let mut warnings = newsletter_data.warnings.clone();
+    warnings.extend(size_warning(&html));
+    Ok(warnings)
+}
+
 fn success_message() -> FlashMessage {
     FlashMessage::info("Newsletters were sent to subscribers!")
 }
 
-fn validate_form_data(data: &NewsletterData) -> Result<(), NewsletterDataError> {
-    if data.title.trim().len() == 0 {
+pub(super) fn validate_form_data(
+    form: &NewsletterFormData,
+) -> Result<NewsletterData, NewsletterDataError> {
+    validate_newsletter(
+        &form.title,
+        &form.content_markdown,
+        &form.content_text,
+        &form.content_html,
+        form.template_id,
+    )
+}
+
+/// Issues are written either in Markdown, or as both a text and an HTML
+/// version.
+pub(crate) fn validate_newsletter(
+    title: &str,
+    content_markdown: &str,
+    content_text: &str,
+    content_html: &str,
+    template_id: Option<Uuid>,
+) -> Result<NewsletterData, NewsletterDataError> {
+    if title.trim().is_empty() {
         return Err(NewsletterDataError::ValidationError(
             "Title is required field".to_string(),
         ));
     }
-    if data.content_text.trim().len() == 0 {
-        return Err(NewsletterDataError::ValidationError(
-            "Content text field is required".to_string(),
-        ));
+    let (content_text, content_html, content_markdown) = if content_markdown.trim().is_empty() {
+        if content_text.trim().is_empty() {
+            return Err(NewsletterDataError::ValidationError(
+                "Content text field is required".to_string(),
+            ));
+        }
+        if content_html.trim().is_empty() {
+            return Err(NewsletterDataError::ValidationError(
+                "Content html field is required".to_string(),
+            ));
+        }
+        (content_text.to_string(), content_html.to_string(), None)
+    } else {
+        if !content_text.trim().is_empty() || !content_html.trim().is_empty() {
+            return Err(NewsletterDataError::ValidationError(
+                "Write the issue either in Markdown or as text and HTML, not both".to_string(),
+            ));
+        }
+        let rendered = render_markdown(content_markdown);
+        (
+            rendered.text,
+            rendered.html,
+            Some(content_markdown.to_string()),
+        )
+    };
+    let prepared = prepare_html(&content_html);
+    let content_html = prepared.html;
+    if let Err(e) = Template::parse(&content_text) {
+        return Err(NewsletterDataError::ValidationError(format!(
+            "Text content: {}",
+            e
+        )));
     }
-    if data.content_html.trim().len() == 0 {
-        return Err(NewsletterDataError::ValidationError(
-            "Content html field is required".to_string(),
-        ));
+    if let Err(e) = Template::parse(&content_html) {
+        return Err(NewsletterDataError::ValidationError(format!(
+            "HTML content: {}",
+            e
+        )));
     }
-    Ok(())
+    Ok(NewsletterData {
+        title: title.to_string(),
+        content_text,
+        content_html,
+        content_markdown,
+        template_id,
+        warnings: prepared.warnings,
+    })
 }
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub(crate) async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
-    title: &str,
-    text_content: &str,
-    html_content: &str,
+    newsletter_data: &NewsletterData,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     let query = sqlx::query!(
@@ -133,24 +224,47 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
+            content_markdown,
+            template_id,
             published_at
         )
-        VALUES ($1, $2, $3, $4, now())
+        -- A template deleted since the form was shown means the default one.
+        VALUES (
+            $1, $2, $3, $4, $5,
+            (SELECT template_id FROM email_templates WHERE template_id = $6),
+            now()
+        )
         "#,
         newsletter_issue_id,
-        title,
-        text_content,
-        html_content
+        newsletter_data.title,
+        newsletter_data.content_text,
+        newsletter_data.content_html,
+        newsletter_data.content_markdown,
+        newsletter_data.template_id,
     );
     transaction.execute(query).await?;
     Ok(newsletter_issue_id)
 }
 
+/// Queues the issue for the confirmed subscribers of any of `topic_ids`, or
+/// for all of them when no topic is given.
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub(crate) async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
+    topic_ids: &[Uuid],
 ) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_topics (newsletter_issue_id, topic_id)
+        SELECT $1, topic_id
+        FROM topics
+        WHERE topic_id = ANY($2)
+        "#,
+        newsletter_issue_id,
+        topic_ids,
+    );
+    transaction.execute(query).await?;
     let query = sqlx::query!(
         r#"
         INSERT INTO issue_delivery_queue (
@@ -159,9 +273,25 @@ async fn enqueue_delivery_tasks(
         )
         SELECT $1, email
         FROM subscriptions
-        WHERE status = 'confirmed'
+        WHERE status = $3
+        AND NOT EXISTS (
+            SELECT 1
+            FROM email_suppressions
+            WHERE email_suppressions.email = lower(subscriptions.email)
+        )
+        AND (
+            cardinality($2::uuid[]) = 0
+            OR EXISTS (
+                SELECT 1
+                FROM subscription_topics
+                WHERE subscription_topics.subscriber_id = subscriptions.id
+                AND subscription_topics.topic_id = ANY($2)
+            )
+        )
         "#,
-        newsletter_issue_id
+        newsletter_issue_id,
+        topic_ids,
+        SubscriptionStatus::Confirmed as SubscriptionStatus,
     );
     transaction.execute(query).await?;
     Ok(())
@@ -0,0 +1,77 @@
+use super::get::{newsletter_page, FormContent};
+use super::post::{validate_form_data, NewsletterFormData};
+use crate::email_html::size_warning;
+use crate::email_templates::get_email_template_or_default;
+use crate::newsletter_template::{Template, TemplateValues};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, HtmlForm};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+/// Shows the issue as a made-up subscriber would receive it, above the form
+/// filled in with what the admin typed, so that they can carry on editing
+/// or send it.
+pub async fn preview_newsletter(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    form: HtmlForm<NewsletterFormData>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.0;
+    let template = get_email_template_or_default(pool.get_ref(), form.template_id)
+        .await
+        .map_err(e500)?;
+    let preview = match validate_form_data(&form) {
+        Ok(newsletter_data) => {
+            let values = TemplateValues::sample(
+                &base_url.0,
+                &newsletter_data.title,
+                &template.postal_address,
+            );
+            // Both parse, or validation would have failed.
+            let text = Template::parse(&newsletter_data.content_text)
+                .map(|t| t.render_text(&values))
+                .unwrap_or_default();
+            let html = Template::parse(&newsletter_data.content_html)
+                .map(|t| t.render_html(&values))
+                .unwrap_or_default();
+            let (html, text) = template.wrap(&values, &html, &text);
+            let warnings: String = newsletter_data
+                .warnings
+                .iter()
+                .cloned()
+                .chain(size_warning(&html))
+                .map(|w| format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&w)))
+                .collect();
+            format!(
+                r#"<h2>Preview for {name} &lt;{email}&gt;</h2>
+{warnings}
+<iframe title="HTML content" srcdoc="{html}"></iframe>
+<pre>{text}</pre>
+<hr />"#,
+                name = htmlescape::encode_minimal(&values.subscriber_name),
+                email = htmlescape::encode_minimal(&values.subscriber_email),
+                html = htmlescape::encode_attribute(&html),
+                text = htmlescape::encode_minimal(&text),
+            )
+        }
+        Err(e) => format!(
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(&e.to_string())
+        ),
+    };
+    newsletter_page(
+        &pool,
+        "",
+        &FormContent {
+            title: &form.title,
+            content_markdown: &form.content_markdown,
+            content_text: &form.content_text,
+            content_html: &form.content_html,
+            topics: &form.topics,
+            template_id: form.template_id,
+        },
+        &form.idempotency_key,
+        &preview,
+    )
+    .await
+}
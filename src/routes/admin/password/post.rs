@@ -40,11 +40,11 @@ pub async fn change_password(
                 FlashMessage::error("The current password is incorrect.").send();
                 Ok(see_other("/admin/password"))
             }
-            AuthError::UnexpectedError(_) => Err(e500(e).into()),
+            AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
     if let Err(e) = validate_password(form.0.new_password) {
-        FlashMessage::error(format!("{}", e.to_string())).send();
+        FlashMessage::error(format!("{}", e)).send();
         return Ok(see_other("/admin/password"));
     }
     change_password_in_db(&pool, *user_id, form.0.new_password_check)
@@ -0,0 +1,51 @@
+use crate::admin_audit::{record_admin_action, AdminAction};
+use crate::authentication::UserId;
+use crate::subscriber_data::get_subscriber_data;
+use crate::utils::e500;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Everything held on a subscriber as a JSON download, to answer access
+/// requests that come in through other channels than the subscriber's link.
+#[tracing::instrument(
+    name = "Export a subscriber's data",
+    skip(pool),
+    fields(user_id=%&*user_id)
+)]
+pub async fn subscriber_data_json(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let data = match get_subscriber_data(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(data) => data,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    record_admin_action(
+        &mut transaction,
+        **user_id,
+        AdminAction::ExportData,
+        subscriber_id,
+        &data.subscription.email,
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "subscriber-{}.json",
+                subscriber_id
+            ))],
+        })
+        .json(data))
+}
@@ -0,0 +1,341 @@
+use crate::admin_audit::get_audit_log;
+use crate::domain::SubscriptionStatus;
+use crate::email_events::get_email_events;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct SubscriberDetails {
+    email: String,
+    name: String,
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+    unsubscribed_at: Option<DateTime<Utc>>,
+    source_url: Option<String>,
+    referrer: Option<String>,
+    utm_source: Option<String>,
+    utm_medium: Option<String>,
+    utm_campaign: Option<String>,
+    utm_term: Option<String>,
+    utm_content: Option<String>,
+    consent_version: Option<String>,
+}
+
+struct TokenRecord {
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+struct DeliveryRecord {
+    title: String,
+    subscriber_email: String,
+    outcome: String,
+    attempted_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber_details(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDetails>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT
+            email,
+            name,
+            status AS "status: SubscriptionStatus",
+            subscribed_at,
+            confirmed_at,
+            unsubscribed_at,
+            source_url,
+            referrer,
+            utm_source,
+            utm_medium,
+            utm_campaign,
+            utm_term,
+            utm_content,
+            consent_version
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_tokens(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<TokenRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        TokenRecord,
+        r#"
+        SELECT created_at, expires_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        ORDER BY created_at DESC
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_deliveries(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<DeliveryRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT n.title, d.subscriber_email, d.outcome, d.attempted_at
+        FROM issue_deliveries d
+        JOIN newsletter_issues n ON n.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.attempted_at DESC
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_topic_names(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+    let topics = sqlx::query!(
+        r#"
+        SELECT t.name
+        FROM subscription_topics st
+        JOIN topics t ON t.topic_id = st.topic_id
+        WHERE st.subscriber_id = $1
+        ORDER BY t.name
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| row.name)
+    .collect();
+    Ok(topics)
+}
+
+pub async fn subscriber_details(
+    pool: web::Data<PgPool>,
+    subscriber_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_message = String::new();
+    for message in flash_messages.iter() {
+        let _ = write!(
+            html_message,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(message.content())
+        );
+    }
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = match get_subscriber_details(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let topics = get_topic_names(&pool, subscriber_id).await.map_err(e500)?;
+    let tokens = get_tokens(&pool, subscriber_id).await.map_err(e500)?;
+    let deliveries = get_deliveries(&pool, subscriber_id).await.map_err(e500)?;
+    let audit_log = get_audit_log(pool.get_ref(), subscriber_id)
+        .await
+        .map_err(e500)?;
+    let email_events = get_email_events(pool.get_ref(), subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    let now = Utc::now();
+    let mut tokens_html = String::new();
+    for token in &tokens {
+        let state = if token.expires_at > now {
+            "active"
+        } else {
+            "expired"
+        };
+        let _ = write!(
+            tokens_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            token.created_at.format("%Y-%m-%d %H:%M"),
+            token.expires_at.format("%Y-%m-%d %H:%M"),
+            state,
+        );
+    }
+    if tokens.is_empty() {
+        tokens_html.push_str(r#"<tr><td colspan="3">No confirmation tokens.</td></tr>"#);
+    }
+    let mut deliveries_html = String::new();
+    for delivery in &deliveries {
+        let _ = write!(
+            deliveries_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&delivery.title),
+            htmlescape::encode_minimal(&delivery.subscriber_email),
+            delivery.outcome,
+            delivery.attempted_at.format("%Y-%m-%d %H:%M"),
+        );
+    }
+    if deliveries.is_empty() {
+        deliveries_html.push_str(r#"<tr><td colspan="4">No issues delivered yet.</td></tr>"#);
+    }
+    let mut email_events_html = String::new();
+    for event in &email_events {
+        let _ = write!(
+            email_events_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            event.kind,
+            htmlescape::encode_minimal(event.bounce_type.as_deref().unwrap_or_default()),
+            htmlescape::encode_minimal(&event.email),
+            htmlescape::encode_minimal(event.description.as_deref().unwrap_or_default()),
+            event.occurred_at.format("%Y-%m-%d %H:%M"),
+        );
+    }
+    if email_events.is_empty() {
+        email_events_html.push_str(r#"<tr><td colspan="5">No bounces or complaints.</td></tr>"#);
+    }
+    let mut audit_log_html = String::new();
+    for entry in &audit_log {
+        let _ = write!(
+            audit_log_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            entry.action,
+            htmlescape::encode_minimal(&entry.username),
+            entry.performed_at.format("%Y-%m-%d %H:%M"),
+        );
+    }
+    if audit_log.is_empty() {
+        audit_log_html.push_str(r#"<tr><td colspan="3">No changes made by admins.</td></tr>"#);
+    }
+    let mut actions_html = String::new();
+    if subscriber
+        .status
+        .can_transition_to(SubscriptionStatus::Confirmed)
+    {
+        let _ = write!(
+            actions_html,
+            r#"<form method="post" action="/admin/subscribers/{subscriber_id}/confirm" style="display: inline">
+    <button type="submit">Confirm</button>
+</form>"#
+        );
+    }
+    if subscriber
+        .status
+        .can_transition_to(SubscriptionStatus::Unsubscribed)
+    {
+        let _ = write!(
+            actions_html,
+            r#"<form method="post" action="/admin/subscribers/{subscriber_id}/unsubscribe" style="display: inline">
+    <button type="submit">Unsubscribe</button>
+</form>"#
+        );
+    }
+    let _ = write!(
+        actions_html,
+        r#"<form method="post" action="/admin/subscribers/{subscriber_id}/delete" style="display: inline"
+    onsubmit="return confirm('Delete this subscriber and everything queued for them?');">
+    <button type="submit">Delete</button>
+</form>
+<form method="post" action="/admin/subscribers/{subscriber_id}/erase" style="display: inline"
+    onsubmit="return confirm('Erase everything held on this subscriber? This cannot be undone.');">
+    <button type="submit">Erase all data</button>
+</form>
+<a href="/admin/subscribers/{subscriber_id}/data">Download all data (JSON)</a>"#
+    );
+    let format_at = |at: Option<DateTime<Utc>>| match at {
+        Some(at) => at.format("%Y-%m-%d %H:%M").to_string(),
+        None => "-".to_string(),
+    };
+    let mut source_html = String::new();
+    for (label, value) in [
+        ("Signup page", &subscriber.source_url),
+        ("Referrer", &subscriber.referrer),
+        ("UTM source", &subscriber.utm_source),
+        ("UTM medium", &subscriber.utm_medium),
+        ("UTM campaign", &subscriber.utm_campaign),
+        ("UTM term", &subscriber.utm_term),
+        ("UTM content", &subscriber.utm_content),
+        ("Consent version", &subscriber.consent_version),
+    ] {
+        if let Some(value) = value {
+            let _ = write!(
+                source_html,
+                "<dt>{}</dt><dd>{}</dd>",
+                label,
+                htmlescape::encode_minimal(value)
+            );
+        }
+    }
+    let topics = if topics.is_empty() {
+        "None".to_string()
+    } else {
+        htmlescape::encode_minimal(&topics.join(", "))
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>Subscriber</title>
+</head>
+<body>
+{html_message}
+<dl>
+    <dt>Email</dt><dd>{email}</dd>
+    <dt>Name</dt><dd>{name}</dd>
+    <dt>Status</dt><dd>{status}</dd>
+    <dt>Subscribed at</dt><dd>{subscribed_at}</dd>
+    <dt>Confirmed at</dt><dd>{confirmed_at}</dd>
+    <dt>Unsubscribed at</dt><dd>{unsubscribed_at}</dd>
+    <dt>Topics</dt><dd>{topics}</dd>
+    {source_html}
+</dl>
+<p>{actions_html}</p>
+<h2>Confirmation tokens</h2>
+<table>
+    <tr><th>Issued at</th><th>Expires at</th><th>State</th></tr>
+    {tokens_html}
+</table>
+<h2>Delivery history</h2>
+<table>
+    <tr><th>Issue</th><th>Sent to</th><th>Outcome</th><th>At</th></tr>
+    {deliveries_html}
+</table>
+<h2>Bounces and complaints</h2>
+<table>
+    <tr><th>Event</th><th>Type</th><th>Address</th><th>Description</th><th>At</th></tr>
+    {email_events_html}
+</table>
+<h2>Audit trail</h2>
+<table>
+    <tr><th>Action</th><th>By</th><th>At</th></tr>
+    {audit_log_html}
+</table>
+<p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>
+    "#,
+            email = htmlescape::encode_minimal(&subscriber.email),
+            name = htmlescape::encode_minimal(&subscriber.name),
+            status = subscriber.status,
+            subscribed_at = subscriber.subscribed_at.format("%Y-%m-%d %H:%M"),
+            confirmed_at = format_at(subscriber.confirmed_at),
+            unsubscribed_at = format_at(subscriber.unsubscribed_at),
+        )))
+}
@@ -0,0 +1,171 @@
+use super::filters::{ParsedFilters, SubscriberFilters};
+use crate::domain::SubscriptionStatus;
+use crate::utils::csv_field;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tracing::Instrument;
+
+/// Rows are buffered up to roughly this many bytes before being handed to the
+/// response body.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportParameters {
+    format: ExportFormat,
+}
+
+#[derive(serde::Serialize)]
+struct ExportRow<'a> {
+    email: &'a str,
+    name: &'a str,
+    status: &'a str,
+    subscribed_at: String,
+    confirmed_at: Option<String>,
+    topics: &'a [String],
+}
+
+fn format_timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Streams every subscriber matching the filters of the list view. The rows
+/// are read from a cursor in a background task and written to the response as
+/// they come, so the export never sits in memory as a whole.
+#[tracing::instrument(name = "Export subscribers", skip_all)]
+pub async fn export_subscribers(
+    pool: web::Data<PgPool>,
+    parameters: web::Query<ExportParameters>,
+    query: web::Query<SubscriberFilters>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filters = query.parse()?;
+    let format = parameters.format;
+    let (sender, receiver) = mpsc::channel(4);
+    tokio::spawn(
+        async move {
+            if let Err(e) = stream_subscribers(&pool, filters, format, &sender).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to export subscribers"
+                );
+                let _ = sender.send(Err(e)).await;
+            }
+        }
+        .in_current_span(),
+    );
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    let (content_type, filename) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "subscribers.csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "subscribers.ndjson"),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename.into())],
+        })
+        .streaming(body))
+}
+
+/// Returns early, without an error, if the client goes away mid-export.
+async fn stream_subscribers(
+    pool: &PgPool,
+    filters: ParsedFilters,
+    format: ExportFormat,
+    sender: &mpsc::Sender<Result<Bytes, anyhow::Error>>,
+) -> Result<(), anyhow::Error> {
+    let mut chunk = String::new();
+    if let ExportFormat::Csv = format {
+        chunk.push_str("email,name,status,subscribed_at,confirmed_at,topics\n");
+    }
+    let mut rows = sqlx::query!(
+        r#"
+        SELECT
+            s.email,
+            s.name,
+            s.status AS "status: SubscriptionStatus",
+            s.subscribed_at,
+            s.confirmed_at,
+            ARRAY(
+                SELECT t.name
+                FROM subscription_topics st
+                JOIN topics t ON t.topic_id = st.topic_id
+                WHERE st.subscriber_id = s.id
+                ORDER BY t.name
+            ) AS "topics!"
+        FROM subscriptions s
+        WHERE
+            ($1::text IS NULL OR s.email ILIKE $1 OR s.name ILIKE $1) AND
+            ($2::subscription_status IS NULL OR s.status = $2) AND
+            ($3::date IS NULL OR s.subscribed_at >= $3::date) AND
+            ($4::date IS NULL OR s.subscribed_at < $4::date + 1)
+        ORDER BY s.subscribed_at, s.id
+        "#,
+        filters.search,
+        filters.status as Option<SubscriptionStatus>,
+        filters.subscribed_from,
+        filters.subscribed_to,
+    )
+    .fetch(pool);
+    while let Some(row) = rows
+        .try_next()
+        .await
+        .context("Failed to fetch the subscribers to export.")?
+    {
+        let row = ExportRow {
+            email: &row.email,
+            name: &row.name,
+            status: row.status.as_str(),
+            subscribed_at: format_timestamp(row.subscribed_at),
+            confirmed_at: row.confirmed_at.map(format_timestamp),
+            topics: &row.topics,
+        };
+        match format {
+            ExportFormat::Csv => {
+                chunk.push_str(&csv_field(row.email));
+                chunk.push(',');
+                chunk.push_str(&csv_field(row.name));
+                chunk.push(',');
+                chunk.push_str(row.status);
+                chunk.push(',');
+                chunk.push_str(&row.subscribed_at);
+                chunk.push(',');
+                chunk.push_str(row.confirmed_at.as_deref().unwrap_or_default());
+                chunk.push(',');
+                chunk.push_str(&csv_field(&row.topics.join(";")));
+                chunk.push('\n');
+            }
+            ExportFormat::Ndjson => {
+                let line = serde_json::to_string(&row).context("Failed to serialize a row.")?;
+                chunk.push_str(&line);
+                chunk.push('\n');
+            }
+        }
+        if chunk.len() >= CHUNK_SIZE {
+            let bytes = Bytes::from(std::mem::take(&mut chunk));
+            if sender.send(Ok(bytes)).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        let _ = sender.send(Ok(Bytes::from(chunk))).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use crate::domain::SubscriptionStatus;
+use crate::utils::e400;
+use chrono::NaiveDate;
+
+/// Filters shared by the subscriber list and the exports, as they appear in
+/// the query string.
+#[derive(serde::Deserialize)]
+pub struct SubscriberFilters {
+    #[serde(default)]
+    pub q: String,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub subscribed_from: String,
+    #[serde(default)]
+    pub subscribed_to: String,
+}
+
+/// Validated filters, ready to be bound to a query. `None` matches everything.
+pub struct ParsedFilters {
+    pub search: Option<String>,
+    pub status: Option<SubscriptionStatus>,
+    pub subscribed_from: Option<NaiveDate>,
+    pub subscribed_to: Option<NaiveDate>,
+}
+
+impl SubscriberFilters {
+    pub fn parse(&self) -> Result<ParsedFilters, actix_web::Error> {
+        let status = if self.status.is_empty() {
+            None
+        } else {
+            Some(SubscriptionStatus::parse(&self.status).map_err(e400)?)
+        };
+        Ok(ParsedFilters {
+            search: search_pattern(&self.q),
+            status,
+            subscribed_from: parse_date("subscribed_from", &self.subscribed_from)?,
+            subscribed_to: parse_date("subscribed_to", &self.subscribed_to)?,
+        })
+    }
+
+    /// The filters encoded back into a query string, to build links that
+    /// keep them.
+    pub fn query_string(&self) -> String {
+        format!(
+            "q={}&status={}&subscribed_from={}&subscribed_to={}",
+            urlencoding::encode(&self.q),
+            urlencoding::encode(&self.status),
+            urlencoding::encode(&self.subscribed_from),
+            urlencoding::encode(&self.subscribed_to),
+        )
+    }
+}
+
+fn parse_date(field: &str, value: &str) -> Result<Option<NaiveDate>, actix_web::Error> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(Some)
+        .map_err(|_| e400(format!("{} must be a date formatted as YYYY-MM-DD", field)))
+}
+
+/// `q` is matched anywhere in the email or name, wildcards included literally.
+fn search_pattern(q: &str) -> Option<String> {
+    let q = q.trim();
+    if q.is_empty() {
+        return None;
+    }
+    let escaped = q
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    Some(format!("%{}%", escaped))
+}
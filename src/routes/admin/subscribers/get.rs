@@ -0,0 +1,181 @@
+use super::filters::SubscriberFilters;
+use crate::domain::SubscriptionStatus;
+use crate::utils::{e400, e500};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+const PAGE_SIZE: i64 = 50;
+
+/// Pages are fetched with a keyset cursor, the `subscribed_at` (in
+/// microseconds) and id of the last row shown.
+#[derive(serde::Deserialize)]
+pub struct PageCursor {
+    after: Option<i64>,
+    after_id: Option<Uuid>,
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>,
+}
+
+pub async fn subscribers_list(
+    pool: web::Data<PgPool>,
+    query: web::Query<SubscriberFilters>,
+    cursor: web::Query<PageCursor>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_message = String::new();
+    for message in flash_messages.iter() {
+        let _ = write!(
+            html_message,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(message.content())
+        );
+    }
+    let query = query.into_inner();
+    let filters = query.parse()?;
+    let after = match (cursor.after, cursor.after_id) {
+        (Some(after), Some(after_id)) => Some((
+            DateTime::from_timestamp_micros(after).ok_or_else(|| e400("Invalid page cursor"))?,
+            after_id,
+        )),
+        _ => None,
+    };
+
+    let mut subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status AS "status: SubscriptionStatus", subscribed_at
+        FROM subscriptions
+        WHERE
+            ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1) AND
+            ($2::subscription_status IS NULL OR status = $2) AND
+            ($3::date IS NULL OR subscribed_at >= $3::date) AND
+            ($4::date IS NULL OR subscribed_at < $4::date + 1) AND
+            ($5::timestamptz IS NULL OR (subscribed_at, id) < ($5, $6::uuid))
+        ORDER BY subscribed_at DESC, id DESC
+        LIMIT $7
+        "#,
+        filters.search,
+        filters.status as Option<SubscriptionStatus>,
+        filters.subscribed_from,
+        filters.subscribed_to,
+        after.map(|(at, _)| at),
+        after.map(|(_, id)| id),
+        PAGE_SIZE + 1,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    let has_next_page = subscribers.len() as i64 > PAGE_SIZE;
+    subscribers.truncate(PAGE_SIZE as usize);
+
+    let mut rows_html = String::new();
+    for subscriber in &subscribers {
+        let _ = write!(
+            rows_html,
+            r#"<tr><td><a href="/admin/subscribers/{id}">{email}</a></td><td>{name}</td><td>{status}</td><td>{subscribed_at}</td></tr>"#,
+            id = subscriber.id,
+            email = htmlescape::encode_minimal(&subscriber.email),
+            name = htmlescape::encode_minimal(&subscriber.name),
+            status = subscriber.status,
+            subscribed_at = subscriber.subscribed_at.format("%Y-%m-%d %H:%M"),
+        );
+    }
+    let filters = query.query_string();
+    let mut pagination_html = String::new();
+    if after.is_some() {
+        let _ = write!(
+            pagination_html,
+            r#"<a href="/admin/subscribers?{}">First page</a> "#,
+            htmlescape::encode_minimal(&filters)
+        );
+    }
+    if let (true, Some(last)) = (has_next_page, subscribers.last()) {
+        let _ = write!(
+            pagination_html,
+            r#"<a href="/admin/subscribers?{}&amp;after={}&amp;after_id={}">Next page</a>"#,
+            htmlescape::encode_minimal(&filters),
+            last.subscribed_at.timestamp_micros(),
+            last.id,
+        );
+    }
+    let mut status_options = String::new();
+    for option in SubscriptionStatus::ALL {
+        let selected = if option.as_str() == query.status {
+            "selected"
+        } else {
+            ""
+        };
+        let _ = write!(
+            status_options,
+            r#"<option value="{option}" {selected}>{option}</option>"#
+        );
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>Subscribers</title>
+</head>
+<body>
+{html_message}
+<form method="get" action="/admin/subscribers">
+    <label>Search: <input type="search" name="q" value="{q}" placeholder="Email or name" /></label>
+    <label>Status:
+        <select name="status">
+            <option value="">Any</option>
+            {status_options}
+        </select>
+    </label>
+    <label>Subscribed from: <input type="date" name="subscribed_from" value="{subscribed_from}" /></label>
+    <label>to: <input type="date" name="subscribed_to" value="{subscribed_to}" /></label>
+    <button type="submit">Filter</button>
+</form>
+<table>
+    <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th></tr>
+    {rows_html}
+</table>
+<p>{pagination_html}</p>
+<p>
+    Export these subscribers as
+    <a href="/admin/subscribers/export?format=csv&amp;{export_filters}">CSV</a> or
+    <a href="/admin/subscribers/export?format=ndjson&amp;{export_filters}">NDJSON</a>
+</p>
+<p><a href="/admin/imports">Import subscribers from a CSV file</a></p>
+<h2>Add a subscriber</h2>
+<form method="post" action="/admin/subscribers">
+    <label>Name: <input type="text" name="name" /></label>
+    <label>Email: <input type="email" name="email" /></label>
+    <label>Status:
+        <select name="status">
+            <option value="pending_confirmation">Pending: send a confirmation email</option>
+            <option value="confirmed">Confirmed</option>
+        </select>
+    </label>
+    <button type="submit">Add subscriber</button>
+</form>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+    "#,
+            export_filters = htmlescape::encode_minimal(&filters),
+            q = htmlescape::encode_minimal(&query.q),
+            subscribed_from = htmlescape::encode_minimal(&query.subscribed_from),
+            subscribed_to = htmlescape::encode_minimal(&query.subscribed_to),
+        )))
+}
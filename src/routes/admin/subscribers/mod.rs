@@ -0,0 +1,15 @@
+mod data;
+mod detail;
+mod export;
+mod filters;
+mod get;
+mod post;
+
+pub use data::subscriber_data_json;
+pub use detail::subscriber_details;
+pub use export::export_subscribers;
+pub use get::subscribers_list;
+pub use post::{
+    add_subscriber, admin_confirm_subscriber, admin_erase_subscriber, admin_unsubscribe_subscriber,
+    delete_subscriber,
+};
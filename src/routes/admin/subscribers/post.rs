@@ -0,0 +1,288 @@
+use crate::admin_audit::{record_admin_action, AdminAction};
+use crate::authentication::UserId;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::routes::{
+    confirm_subscriber, generate_subscription_token, insert_subscriber, mark_unsubscribed,
+    send_confirmation_email, store_subscription_token,
+};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::subscriber_data::{delete_subscriber_data, erase_subscriber, ERASED_EMAIL};
+use crate::topics::set_subscriber_topics;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct NewSubscriberFormData {
+    name: String,
+    email: String,
+    /// Either `confirmed` or `pending_confirmation`, in which case a
+    /// confirmation email is sent.
+    status: String,
+}
+
+struct LockedSubscriber {
+    email: String,
+    status: SubscriptionStatus,
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn lock_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<LockedSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        LockedSubscriber,
+        r#"
+        SELECT email, status AS "status: SubscriptionStatus"
+        FROM subscriptions
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+#[tracing::instrument(
+    name = "Add a subscriber from the admin area",
+    skip(form, pool, base_url, hmac_secret),
+    fields(user_id=%&*user_id, subscriber_email=%form.email)
+)]
+pub async fn add_subscriber(
+    form: web::Form<NewSubscriberFormData>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.into_inner();
+    let new_subscriber = match (
+        SubscriberName::parse(form.name),
+        SubscriberEmail::parse(form.email),
+    ) {
+        (Ok(name), Ok(email)) => NewSubscriber { email, name },
+        (Err(e), _) | (_, Err(e)) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+    let confirmed = match SubscriptionStatus::parse(&form.status) {
+        Ok(SubscriptionStatus::Confirmed) => true,
+        Ok(SubscriptionStatus::PendingConfirmation) => false,
+        _ => {
+            FlashMessage::error("Pick the status of the new subscriber.").send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let subscriber_id = match insert_subscriber(&mut transaction, &new_subscriber).await {
+        Ok(subscriber_id) => subscriber_id,
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            FlashMessage::error(format!("{} is already subscribed.", new_subscriber.email)).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    set_subscriber_topics(&mut transaction, subscriber_id, &[])
+        .await
+        .map_err(e500)?;
+    if confirmed {
+        confirm_subscriber(&mut transaction, subscriber_id)
+            .await
+            .map_err(e500)?;
+    } else {
+        let subscription_token = generate_subscription_token();
+        store_subscription_token(&mut transaction, &subscriber_id, &subscription_token)
+            .await
+            .map_err(e500)?;
+        send_confirmation_email(
+            &mut transaction,
+            subscriber_id,
+            &new_subscriber.email,
+            &base_url,
+            &hmac_secret,
+            &subscription_token,
+        )
+        .await
+        .map_err(e500)?;
+    }
+    record_admin_action(
+        &mut transaction,
+        **user_id,
+        AdminAction::CreateSubscriber,
+        subscriber_id,
+        new_subscriber.email.as_ref(),
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    FlashMessage::info(format!("{} has been added.", new_subscriber.email)).send();
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+#[tracing::instrument(
+    name = "Force-confirm a subscriber",
+    skip(pool),
+    fields(user_id=%&*user_id)
+)]
+pub async fn admin_confirm_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let subscriber = match lock_subscriber(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if subscriber.status == SubscriptionStatus::Confirmed {
+        FlashMessage::info(format!("{} is already confirmed.", subscriber.email)).send();
+    } else if let Err(e) = subscriber
+        .status
+        .transition_to(SubscriptionStatus::Confirmed)
+    {
+        FlashMessage::error(e.to_string()).send();
+    } else {
+        confirm_subscriber(&mut transaction, subscriber_id)
+            .await
+            .map_err(e500)?;
+        record_admin_action(
+            &mut transaction,
+            **user_id,
+            AdminAction::ForceConfirm,
+            subscriber_id,
+            &subscriber.email,
+        )
+        .await
+        .map_err(e500)?;
+        transaction.commit().await.map_err(e500)?;
+        FlashMessage::info(format!("{} has been confirmed.", subscriber.email)).send();
+    }
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber from the admin area",
+    skip(pool),
+    fields(user_id=%&*user_id)
+)]
+pub async fn admin_unsubscribe_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let subscriber = match lock_subscriber(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if subscriber.status == SubscriptionStatus::Unsubscribed {
+        FlashMessage::info(format!("{} is already unsubscribed.", subscriber.email)).send();
+    } else if let Err(e) = subscriber
+        .status
+        .transition_to(SubscriptionStatus::Unsubscribed)
+    {
+        FlashMessage::error(e.to_string()).send();
+    } else {
+        mark_unsubscribed(&mut transaction, subscriber_id)
+            .await
+            .map_err(e500)?;
+        record_admin_action(
+            &mut transaction,
+            **user_id,
+            AdminAction::Unsubscribe,
+            subscriber_id,
+            &subscriber.email,
+        )
+        .await
+        .map_err(e500)?;
+        transaction.commit().await.map_err(e500)?;
+        FlashMessage::info(format!("{} has been unsubscribed.", subscriber.email)).send();
+    }
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+#[tracing::instrument(
+    name = "Delete a subscriber",
+    skip(pool),
+    fields(user_id=%&*user_id)
+)]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let subscriber = match lock_subscriber(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    delete_subscriber_data(&mut transaction, subscriber_id, &subscriber.email)
+        .await
+        .map_err(e500)?;
+    record_admin_action(
+        &mut transaction,
+        **user_id,
+        AdminAction::Delete,
+        subscriber_id,
+        &subscriber.email,
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    FlashMessage::info(format!("{} has been deleted.", subscriber.email)).send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+#[tracing::instrument(
+    name = "Erase a subscriber",
+    skip(pool),
+    fields(user_id=%&*user_id)
+)]
+pub async fn admin_erase_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let email = match erase_subscriber(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(email) => email,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    record_admin_action(
+        &mut transaction,
+        **user_id,
+        AdminAction::Erase,
+        subscriber_id,
+        ERASED_EMAIL,
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    FlashMessage::info(format!("All data held on {} has been erased.", email)).send();
+    Ok(see_other("/admin/subscribers"))
+}
@@ -0,0 +1,74 @@
+use crate::suppression_list::get_suppressions;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn suppressions_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_message = String::new();
+    for message in flash_messages.iter() {
+        let _ = write!(
+            html_message,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(message.content())
+        );
+    }
+    let suppressions = get_suppressions(pool.get_ref()).await.map_err(e500)?;
+    let mut suppressions_html = String::new();
+    for suppression in &suppressions {
+        let _ = write!(
+            suppressions_html,
+            r#"<tr><td>{email}</td><td>{reason}</td><td>{note}</td><td>{created_at}</td><td>
+    <form method="post" action="/admin/suppressions/delete" style="display: inline">
+        <input hidden type="text" name="email" value="{email_attribute}" />
+        <button type="submit">Remove</button>
+    </form>
+</td></tr>"#,
+            email = htmlescape::encode_minimal(&suppression.email),
+            reason = suppression.reason,
+            note = htmlescape::encode_minimal(suppression.note.as_deref().unwrap_or_default()),
+            created_at = suppression.created_at.format("%Y-%m-%d %H:%M"),
+            email_attribute = htmlescape::encode_attribute(&suppression.email),
+        );
+    }
+    if suppressions.is_empty() {
+        suppressions_html.push_str(r#"<tr><td colspan="5">No suppressed addresses.</td></tr>"#);
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>Suppressed addresses</title>
+</head>
+<body>
+{html_message}
+<p>Nothing is sent to suppressed addresses: no issues, no confirmation or welcome emails.
+Addresses that bounce for good or complain about spam are added automatically.</p>
+<table>
+    <tr><th>Address</th><th>Reason</th><th>Note</th><th>Since</th><th></th></tr>
+    {suppressions_html}
+</table>
+<form method="post" action="/admin/suppressions">
+    <div>
+        <label>Address: <input type="text" placeholder="ursula@example.com" name="email" /></label>
+    </div>
+    <div>
+        <label>Note: <input type="text" placeholder="Asked by phone not to be contacted" name="note" /></label>
+    </div>
+    <button type="submit">Suppress</button>
+</form>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+    "#,
+        )))
+}
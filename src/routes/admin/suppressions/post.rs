@@ -0,0 +1,81 @@
+use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::routes::drop_pending_mail;
+use crate::suppression_list::{remove_suppression, suppress_address, SuppressionReason};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct SuppressionFormData {
+    email: String,
+    #[serde(default)]
+    note: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeleteSuppressionFormData {
+    email: String,
+}
+
+/// Whatever was still waiting to be sent to the address is dropped too.
+#[tracing::instrument(
+    name = "Suppress an email address",
+    skip(pool, form),
+    fields(user_id=%&*user_id, email=%form.email)
+)]
+pub async fn add_suppression(
+    pool: web::Data<PgPool>,
+    form: web::Form<SuppressionFormData>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.email.trim().to_string()) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+    let note = Some(form.note.trim()).filter(|note| !note.is_empty());
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let added = suppress_address(
+        &mut *transaction,
+        email.as_ref(),
+        SuppressionReason::Manual,
+        note,
+    )
+    .await
+    .map_err(e500)?;
+    drop_pending_mail(&mut transaction, email.as_ref())
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    if added {
+        FlashMessage::info(format!("{} has been suppressed.", email)).send();
+    } else {
+        FlashMessage::info(format!("{} was already suppressed.", email)).send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[tracing::instrument(
+    name = "Remove an email address from the suppression list",
+    skip(pool, form),
+    fields(user_id=%&*user_id, email=%form.email)
+)]
+pub async fn delete_suppression(
+    pool: web::Data<PgPool>,
+    form: web::Form<DeleteSuppressionFormData>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if remove_suppression(pool.get_ref(), &form.email)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info(format!("{} is no longer suppressed.", form.email)).send();
+    } else {
+        FlashMessage::error(format!("{} was not suppressed.", form.email)).send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
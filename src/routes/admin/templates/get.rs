@@ -0,0 +1,152 @@
+use crate::email_templates::{get_email_template, get_email_templates};
+use crate::newsletter_template::Placeholder;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+fn flash_messages_html(flash_messages: &IncomingFlashMessages) -> String {
+    let mut html_message = String::new();
+    for message in flash_messages.iter() {
+        let _ = write!(
+            html_message,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(message.content())
+        );
+    }
+    html_message
+}
+
+pub async fn templates_list(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let html_message = flash_messages_html(&flash_messages);
+    let templates = get_email_templates(pool.get_ref()).await.map_err(e500)?;
+    let mut templates_html = String::new();
+    for template in &templates {
+        let default = if template.is_default {
+            "<i>Default</i>".to_string()
+        } else {
+            format!(
+                r#"<form method="post" action="/admin/templates/{id}/default" style="display: inline">
+        <button type="submit">Make default</button>
+    </form>
+    <form method="post" action="/admin/templates/{id}/delete" style="display: inline">
+        <button type="submit">Delete</button>
+    </form>"#,
+                id = template.template_id,
+            )
+        };
+        let postal_address = if template.postal_address.trim().is_empty() {
+            "<b>No postal address yet</b>".to_string()
+        } else {
+            htmlescape::encode_minimal(&template.postal_address)
+        };
+        let _ = write!(
+            templates_html,
+            r#"<tr><td><a href="/admin/templates/{}">{}</a></td><td>{}</td><td>
+    {}
+</td></tr>"#,
+            template.template_id,
+            htmlescape::encode_minimal(&template.name),
+            postal_address,
+            default,
+        );
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>Email templates</title>
+</head>
+<body>
+{html_message}
+<p>Newsletter issues are wrapped in the template they were sent with; confirmation emails
+and issues sent without one use the default template. Most countries require bulk email
+to carry the sender's postal address.</p>
+<table>
+    <tr><th>Name</th><th>Postal address</th><th></th></tr>
+    {templates_html}
+</table>
+<form method="post" action="/admin/templates">
+    <div>
+        <label>Name: <input type="text" placeholder="Enter name" name="name" /></label>
+    </div>
+    <button type="submit">New template</button>
+</form>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+    "#,
+        )))
+}
+
+pub async fn template_form(
+    template_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let html_message = flash_messages_html(&flash_messages);
+    let template = match get_email_template(pool.get_ref(), *template_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(template) => template,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let placeholders = Placeholder::ALL
+        .iter()
+        .map(|p| format!("<code>{{{{ {} }}}}</code>", p.name()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>Email template</title>
+</head>
+<body>
+{html_message}
+<p>Each layout must have one <code>{{{{ content }}}}</code> slot, where the body of the
+email goes, and a footer with <code>{{{{ unsubscribe_url }}}}</code> and
+<code>{{{{ postal_address }}}}</code>. Layouts can also use {placeholders}.</p>
+<form method="post" action="/admin/templates/{template_id}">
+    <div>
+        <label>Name: <input type="text" placeholder="Enter name" name="name" value="{name}" /></label>
+    </div>
+    <div>
+        <label>Postal address: <input type="text" placeholder="1 Main Street, Springfield" name="postal_address" value="{postal_address}" /></label>
+    </div>
+    <div>
+        <label>Html Layout</label>
+        <textarea name="html_layout">{html_layout}</textarea>
+    </div>
+    <div>
+        <label>Text Layout</label>
+        <textarea name="text_layout">{text_layout}</textarea>
+    </div>
+    <button type="submit">Save</button>
+</form>
+<p><a href="/admin/templates">&lt;- Back</a></p>
+</body>
+</html>
+    "#,
+            template_id = template.template_id,
+            name = htmlescape::encode_attribute(&template.name),
+            postal_address = htmlescape::encode_attribute(&template.postal_address),
+            html_layout = htmlescape::encode_minimal(&template.html_layout),
+            text_layout = htmlescape::encode_minimal(&template.text_layout),
+        )))
+}
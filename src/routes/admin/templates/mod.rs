@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{template_form, templates_list};
+pub use post::{add_template, delete_template, make_default_template, update_template};
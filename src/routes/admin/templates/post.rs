@@ -0,0 +1,167 @@
+use crate::authentication::UserId;
+use crate::email_templates::{
+    delete_email_template, get_email_template, insert_email_template, make_default_email_template,
+    update_email_template, validate_layout, EmailTemplate,
+};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct NewTemplateFormData {
+    name: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TemplateFormData {
+    name: String,
+    postal_address: String,
+    html_layout: String,
+    text_layout: String,
+}
+
+/// The new template is a copy of the default one, to be edited from there.
+#[tracing::instrument(
+    name = "Add an email template",
+    skip(pool, form),
+    fields(user_id=%&*user_id, template_name=%form.name)
+)]
+pub async fn add_template(
+    pool: web::Data<PgPool>,
+    form: web::Form<NewTemplateFormData>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("Name is required field").send();
+        return Ok(see_other("/admin/templates"));
+    }
+    match insert_email_template(pool.get_ref(), name).await {
+        Ok(template_id) => {
+            FlashMessage::info(format!("The template {} has been added.", name)).send();
+            Ok(see_other(&format!("/admin/templates/{}", template_id)))
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            FlashMessage::error(format!("There already is a template named {}.", name)).send();
+            Ok(see_other("/admin/templates"))
+        }
+        Err(e) => Err(e500(e)),
+    }
+}
+
+#[tracing::instrument(
+    name = "Update an email template",
+    skip(pool, form),
+    fields(user_id=%&*user_id)
+)]
+pub async fn update_template(
+    template_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    form: web::Form<TemplateFormData>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let template_id = template_id.into_inner();
+    let location = format!("/admin/templates/{}", template_id);
+    let template = match get_email_template(pool.get_ref(), template_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(template) => template,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let form = form.into_inner();
+    let name = form.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("Name is required field").send();
+        return Ok(see_other(&location));
+    }
+    if let Err(e) = validate_layout(&form.html_layout) {
+        FlashMessage::error(format!("HTML layout: {}", e)).send();
+        return Ok(see_other(&location));
+    }
+    if let Err(e) = validate_layout(&form.text_layout) {
+        FlashMessage::error(format!("Text layout: {}", e)).send();
+        return Ok(see_other(&location));
+    }
+    let updated = EmailTemplate {
+        name: name.to_string(),
+        postal_address: form.postal_address.trim().to_string(),
+        html_layout: form.html_layout,
+        text_layout: form.text_layout,
+        ..template
+    };
+    match update_email_template(pool.get_ref(), &updated).await {
+        Ok(()) => {
+            FlashMessage::info(format!("The template {} has been saved.", updated.name)).send();
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            FlashMessage::error(format!(
+                "There already is a template named {}.",
+                updated.name
+            ))
+            .send();
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    Ok(see_other(&location))
+}
+
+#[tracing::instrument(
+    name = "Make an email template the default one",
+    skip(pool),
+    fields(user_id=%&*user_id)
+)]
+pub async fn make_default_template(
+    template_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let template_id = template_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let template = match get_email_template(&mut *transaction, template_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(template) => template,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    make_default_email_template(&mut transaction, template_id)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info(format!("{} is now the default template.", template.name)).send();
+    Ok(see_other("/admin/templates"))
+}
+
+/// Issues that were sent with the template are wrapped in the default one
+/// from then on, e.g. in the archive.
+#[tracing::instrument(
+    name = "Delete an email template",
+    skip(pool),
+    fields(user_id=%&*user_id)
+)]
+pub async fn delete_template(
+    template_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let template_id = template_id.into_inner();
+    let template = match get_email_template(pool.get_ref(), template_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(template) => template,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if delete_email_template(pool.get_ref(), template_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info(format!("The template {} has been deleted.", template.name)).send();
+    } else {
+        FlashMessage::error("The default template can't be deleted.").send();
+    }
+    Ok(see_other("/admin/templates"))
+}
@@ -0,0 +1,62 @@
+use crate::topics::get_topics;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn topics_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_message = String::new();
+    for message in flash_messages.iter() {
+        let _ = write!(
+            html_message,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(message.content())
+        );
+    }
+    let topics = get_topics(pool.get_ref()).await.map_err(e500)?;
+    let mut topics_html = String::new();
+    for topic in topics {
+        let _ = write!(
+            topics_html,
+            "<li>{} <i>{}</i></li>",
+            htmlescape::encode_minimal(&topic.name),
+            htmlescape::encode_minimal(&topic.description),
+        );
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>Topics</title>
+</head>
+<body>
+{html_message}
+<p>Subscribers pick the topics they are interested in when signing up,
+and each issue can be sent to some topics only.</p>
+<ul>
+    {topics_html}
+</ul>
+<form method="post" action="/admin/topics">
+    <div>
+        <label>Name: <input type="text" placeholder="Enter name" name="name" /></label>
+    </div>
+    <div>
+        <label>Description: <input type="text" name="description" /></label>
+    </div>
+    <button type="submit">Add topic</button>
+</form>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+    "#,
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::topics_form;
+pub use post::add_topic;
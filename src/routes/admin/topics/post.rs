@@ -0,0 +1,39 @@
+use crate::authentication::UserId;
+use crate::topics::insert_topic;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct TopicFormData {
+    name: String,
+    description: String,
+}
+
+#[tracing::instrument(
+    name = "Add a topic",
+    skip(pool, form),
+    fields(user_id=%&*user_id, topic_name=%form.name)
+)]
+pub async fn add_topic(
+    pool: web::Data<PgPool>,
+    form: web::Form<TopicFormData>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("Name is required field").send();
+        return Ok(see_other("/admin/topics"));
+    }
+    match insert_topic(pool.get_ref(), name, form.description.trim()).await {
+        Ok(_) => {
+            FlashMessage::info(format!("The topic {} has been added.", name)).send();
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            FlashMessage::error(format!("There already is a topic named {}.", name)).send();
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    Ok(see_other("/admin/topics"))
+}
@@ -0,0 +1,58 @@
+use crate::utils::e500;
+use crate::welcome_email::get_welcome_email;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn welcome_email_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut html_message = String::new();
+    for message in flash_messages.iter() {
+        let _ = write!(html_message, "<p><i>{}</i></p>", message.content());
+    }
+    let welcome_email = get_welcome_email(pool.get_ref()).await.map_err(e500)?;
+    let checked = if welcome_email.enabled { "checked" } else { "" };
+    let subject = htmlescape::encode_attribute(&welcome_email.subject);
+    let text_content = htmlescape::encode_minimal(&welcome_email.text_content);
+    let html_content = htmlescape::encode_minimal(&welcome_email.html_content);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>Welcome email</title>
+</head>
+<body>
+{html_message}
+<p>This email is sent to every subscriber right after they confirm their subscription.
+Links to the newsletter archive and to the unsubscribe page are appended automatically.</p>
+<form method="post" action="/admin/welcome_email">
+    <div>
+        <label><input type="checkbox" name="enabled" value="on" {checked} /> Send a welcome email</label>
+    </div>
+    <div>
+        <label>Subject: <input type="text" placeholder="Enter subject" name="subject" value="{subject}" /></label>
+    </div>
+    <div>
+        <label>Text Content</label>
+        <textarea name="content_text">{text_content}</textarea>
+    </div>
+    <div>
+        <label>Html Content</label>
+        <textarea name="content_html">{html_content}</textarea>
+    </div>
+    <button type="submit">Save</button>
+</form>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+    "#,
+        )))
+}
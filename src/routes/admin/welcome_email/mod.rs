@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::welcome_email_form;
+pub use post::update_welcome_email;
@@ -0,0 +1,73 @@
+use crate::authentication::UserId;
+use crate::utils::{e500, see_other};
+use crate::welcome_email::{update_welcome_email as update_welcome_email_in_db, WelcomeEmail};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use thiserror::Error;
+
+#[derive(serde::Deserialize)]
+pub struct WelcomeEmailFormData {
+    enabled: Option<String>,
+    subject: String,
+    content_text: String,
+    content_html: String,
+}
+
+impl From<WelcomeEmailFormData> for WelcomeEmail {
+    fn from(value: WelcomeEmailFormData) -> Self {
+        Self {
+            enabled: value.enabled.is_some(),
+            subject: value.subject,
+            text_content: value.content_text,
+            html_content: value.content_html,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+enum WelcomeEmailValidationError {
+    #[error("{0}")]
+    ValidationError(String),
+}
+
+#[tracing::instrument(
+    name = "Update the welcome email",
+    skip(pool, form),
+    fields(user_id=%&*user_id)
+)]
+pub async fn update_welcome_email(
+    pool: web::Data<PgPool>,
+    form: web::Form<WelcomeEmailFormData>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let welcome_email: WelcomeEmail = form.0.into();
+    if let Err(e) = validate_welcome_email(&welcome_email) {
+        FlashMessage::error(e.to_string()).send();
+        return Ok(see_other("/admin/welcome_email"));
+    }
+    update_welcome_email_in_db(pool.get_ref(), &welcome_email)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("The welcome email has been saved.").send();
+    Ok(see_other("/admin/welcome_email"))
+}
+
+fn validate_welcome_email(data: &WelcomeEmail) -> Result<(), WelcomeEmailValidationError> {
+    if data.subject.trim().is_empty() {
+        return Err(WelcomeEmailValidationError::ValidationError(
+            "Subject is required field".to_string(),
+        ));
+    }
+    if data.text_content.trim().is_empty() {
+        return Err(WelcomeEmailValidationError::ValidationError(
+            "Content text field is required".to_string(),
+        ));
+    }
+    if data.html_content.trim().is_empty() {
+        return Err(WelcomeEmailValidationError::ValidationError(
+            "Content html field is required".to_string(),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use crate::email_templates::get_email_template_or_default;
+use crate::newsletter_template::{Template, TemplateValues};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub fn archived_issue_link(base_url: &ApplicationBaseUrl, issue_id: Uuid) -> String {
+    format!("{}/archive/{}", base_url.0, issue_id)
+}
+
+struct ArchivedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: String,
+}
+
+/// Only issues sent to every subscriber are public: the ones sent to the
+/// subscribers of some topics only are left out.
+#[tracing::instrument(skip_all)]
+async fn get_archived_issues(pool: &PgPool) -> Result<Vec<ArchivedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT newsletter_issue_id, title, published_at
+        FROM newsletter_issues i
+        WHERE NOT EXISTS (
+            SELECT 1
+            FROM newsletter_issue_topics t
+            WHERE t.newsletter_issue_id = i.newsletter_issue_id
+        )
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve archived issues")?;
+    Ok(issues)
+}
+
+pub async fn archive(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let issues = get_archived_issues(&pool).await.map_err(e500)?;
+    let mut issues_html = String::new();
+    for issue in issues {
+        let _ = write!(
+            issues_html,
+            r#"<li><a href="/archive/{}">{}</a> <i>{}</i></li>"#,
+            issue.newsletter_issue_id,
+            htmlescape::encode_minimal(&issue.title),
+            htmlescape::encode_minimal(&issue.published_at),
+        );
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>Newsletter archive</title>
+</head>
+<body>
+    <p>Past issues</p>
+    <ul>
+        {issues_html}
+    </ul>
+</body>
+</html>
+    "#,
+        )))
+}
+
+/// The archive is public, so the placeholders that are about a subscriber
+/// are left blank. Issues sent to some topics only are not found.
+pub async fn archived_issue(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    issue_id: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT title, html_content, template_id
+        FROM newsletter_issues i
+        WHERE
+            newsletter_issue_id = $1
+            AND NOT EXISTS (
+                SELECT 1
+                FROM newsletter_issue_topics t
+                WHERE t.newsletter_issue_id = i.newsletter_issue_id
+            )
+        "#,
+        *issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    let issue = match issue {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let template = get_email_template_or_default(pool.get_ref(), issue.template_id)
+        .await
+        .map_err(e500)?;
+    let values = TemplateValues {
+        subscriber_name: String::new(),
+        subscriber_email: String::new(),
+        issue_title: issue.title.clone(),
+        issue_web_url: archived_issue_link(&base_url, *issue_id),
+        preferences_url: String::new(),
+        unsubscribe_url: String::new(),
+        postal_address: template.postal_address,
+    };
+    // Issues published before placeholders existed may not parse.
+    let content = match Template::parse(&issue.html_content) {
+        Ok(template) => template.render_html(&values),
+        Err(_) => issue.html_content,
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html" charset="UTF-8">
+    <title>{title}</title>
+</head>
+<body>
+    {content}
+    <p><a href="/archive">&lt;- All issues</a></p>
+</body>
+</html>
+    "#,
+            title = htmlescape::encode_minimal(&issue.title),
+        )))
+}
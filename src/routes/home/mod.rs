@@ -1,8 +1,59 @@
-use actix_web::HttpResponse;
-use actix_web::http::header::ContentType;
+use crate::spam_protection::form_token;
+use crate::startup::HmacSecret;
+use crate::topics::{get_topics, topic_checkboxes};
+use crate::utils::e500;
+use actix_web::http::header::{self, ContentType};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use sqlx::PgPool;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
+/// Shown next to the signup form. Bump the version whenever the text
+/// changes: subscribers are recorded with the one they agreed to.
+pub const CONSENT_TEXT: &str = "By subscribing you agree to receive our newsletter by email. \
+    You can unsubscribe at any time with the link at the bottom of every issue.";
+pub const CONSENT_VERSION: &str = "2025-02-19";
+
+/// Campaign parameters of the link that led to the home page, passed on to
+/// the signup.
+#[derive(serde::Deserialize)]
+pub struct UtmParameters {
+    #[serde(default)]
+    utm_source: String,
+    #[serde(default)]
+    utm_medium: String,
+    #[serde(default)]
+    utm_campaign: String,
+    #[serde(default)]
+    utm_term: String,
+    #[serde(default)]
+    utm_content: String,
+}
+
+pub async fn home(
+    request: HttpRequest,
+    utm: web::Query<UtmParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let topics = get_topics(pool.get_ref()).await.map_err(e500)?;
+    let referrer = request
+        .headers()
+        .get(header::REFERER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+        .body(format!(
+            include_str!("home.html"),
+            form_token = form_token(&hmac_secret, Utc::now()),
+            topics = topic_checkboxes(&topics, &[]),
+            consent_text = CONSENT_TEXT,
+            consent_version = CONSENT_VERSION,
+            referrer = htmlescape::encode_attribute(referrer),
+            utm_source = htmlescape::encode_attribute(&utm.utm_source),
+            utm_medium = htmlescape::encode_attribute(&utm.utm_medium),
+            utm_campaign = htmlescape::encode_attribute(&utm.utm_campaign),
+            utm_term = htmlescape::encode_attribute(&utm.utm_term),
+            utm_content = htmlescape::encode_attribute(&utm.utm_content),
+        )))
 }
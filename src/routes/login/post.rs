@@ -35,10 +35,10 @@ pub async fn login(
     session: TypedSession,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     let credentials: Credentials = form.0.into();
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(user_id));
             session.renew();
             session
                 .insert_user_id(user_id)
@@ -1,14 +1,21 @@
 mod admin;
+mod archive;
 mod health_check;
-mod helpers;
+pub(crate) mod helpers;
 mod home;
 mod login;
 mod logout;
 mod newsletters;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data;
+mod subscriptions_preferences;
+mod subscriptions_resend;
+mod subscriptions_unsubscribe;
+mod webhooks;
 
 pub use admin::*;
+pub use archive::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
@@ -16,3 +23,8 @@ pub use logout::*;
 pub use newsletters::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_data::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_resend::*;
+pub use subscriptions_unsubscribe::*;
+pub use webhooks::*;
@@ -1,8 +1,10 @@
 use crate::authentication;
 use crate::authentication::{AuthError, Credentials};
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
 use crate::routes::helpers::chain_error_fmt;
+use crate::routes::{
+    enqueue_delivery_tasks, insert_newsletter_issue, newsletter_warnings, validate_newsletter,
+};
+use crate::startup::ApplicationBaseUrl;
 use actix_web::http::header::{HeaderMap, HeaderValue};
 use actix_web::http::{header, StatusCode};
 use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
@@ -10,47 +12,30 @@ use anyhow::Context;
 use base64::Engine;
 use secrecy::Secret;
 use sqlx::PgPool;
-
-pub struct ConfirmedSubscriber {
-    pub email: SubscriberEmail,
-}
-
-#[tracing::instrument(name = "Getting confirmed subscribers", skip(pool))]
-pub async fn get_confirmed_subscribers(
-    pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let confirmed_subscribers = sqlx::query!(
-        r#"
-            SELECT email
-            FROM subscriptions
-            WHERE status = 'confirmed'
-        "#
-    )
-    .fetch_all(pool)
-    .await?
-    .into_iter()
-    .map(|row| match SubscriberEmail::parse(row.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
-        Err(error) => Err(anyhow::anyhow!(error)),
-    })
-    .collect();
-    Ok(confirmed_subscribers)
-}
+use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
 pub struct BodyData {
     title: String,
     content: Content,
+    /// The topics to publish the issue to; none means every subscriber.
+    #[serde(default)]
+    topics: Vec<Uuid>,
 }
 
+/// Either a Markdown source, which the text and HTML bodies are rendered
+/// from, or both bodies.
 #[derive(serde::Deserialize)]
-pub struct Content {
-    text: String,
-    html: String,
+#[serde(untagged)]
+pub enum Content {
+    Markdown { markdown: String },
+    TextAndHtml { text: String, html: String },
 }
 
 #[derive(thiserror::Error)]
 pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
     #[error("Authentication")]
     AuthError(#[source] anyhow::Error),
     #[error(transparent)]
@@ -66,6 +51,7 @@ impl std::fmt::Debug for PublishError {
 impl ResponseError for PublishError {
     fn status_code(&self) -> StatusCode {
         match self {
+            PublishError::ValidationError(_) => StatusCode::BAD_REQUEST,
             PublishError::AuthError(_) => StatusCode::UNAUTHORIZED,
             PublishError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
@@ -73,6 +59,9 @@ impl ResponseError for PublishError {
 
     fn error_response(&self) -> HttpResponse {
         match self {
+            PublishError::ValidationError(message) => {
+                HttpResponse::build(self.status_code()).body(message.clone())
+            }
             PublishError::UnexpectedError(_) => HttpResponse::new(self.status_code()),
             PublishError::AuthError(_) => {
                 let mut response = HttpResponse::new(self.status_code());
@@ -86,57 +75,68 @@ impl ResponseError for PublishError {
     }
 }
 
+/// Queues the issue for the confirmed subscribers of the given topics, like
+/// the admin form does.
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(body, db_pool, email_client, request),
+    skip(body, db_pool, base_url, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn api_publish_newsletter(
     body: web::Json<BodyData>,
     db_pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
     request: HttpRequest,
 ) -> Result<HttpResponse, PublishError> {
     let credentials = basic_authentication(request.headers()).map_err(PublishError::AuthError)?;
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
     let user_id = authentication::validate_credentials(credentials, &db_pool)
         .await
         .map_err(|err| match err {
             AuthError::InvalidCredentials(_) => PublishError::AuthError(err.into()),
             AuthError::UnexpectedError(_) => PublishError::UnexpectedError(err.into()),
         })?;
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-    let subscribers = get_confirmed_subscribers(&db_pool)
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    let BodyData {
+        title,
+        content,
+        topics,
+    } = body.into_inner();
+    let newsletter_data = match &content {
+        Content::Markdown { markdown } => validate_newsletter(&title, markdown, "", "", None),
+        Content::TextAndHtml { text, html } => validate_newsletter(&title, "", text, html, None),
+    }
+    .map_err(|e| PublishError::ValidationError(e.to_string()))?;
+
+    let mut transaction = db_pool
+        .begin()
         .await
-        .map_err(PublishError::UnexpectedError)?;
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.content.text,
-                        &body.content.html,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })
-                    .map_err(PublishError::UnexpectedError)?;
-            }
-            Err(error) => {
-                tracing::warn!(
-                    error.cause_chain = ?error,
-                    "Skipping an invalid subscriber email. Their stored contact details are invalid"
-                )
-            }
-        }
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let issue_id = insert_newsletter_issue(&mut transaction, &newsletter_data)
+        .await
+        .context("Failed to store newsletter issue details.")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id, &topics)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a newsletter issue.")?;
+
+    // There is no one to show the warnings to but the logs.
+    for warning in newsletter_warnings(&db_pool, &base_url, &newsletter_data)
+        .await
+        .context("Failed to check the newsletter issue for content issues.")?
+    {
+        tracing::warn!(
+            warning,
+            "Publishing a newsletter issue with HTML content issues."
+        );
     }
     Ok(HttpResponse::Ok().finish())
 }
 
-fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+pub(crate) fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
     let header_value = headers
         .get("Authorization")
         .context("The 'Auhtorization' header was missing")?
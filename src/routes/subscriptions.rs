@@ -1,33 +1,121 @@
 use std::fmt::Formatter;
-use actix_web::{web, HttpResponse, ResponseError};
-use actix_web::http::StatusCode;
+use actix_web::{web, Either, HttpRequest, HttpResponse, ResponseError};
+use actix_web::http::{header, StatusCode};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
 use anyhow::Context;
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::email_client::EmailClient;
+use crate::domain::{
+    InvalidStatusTransition, NewSubscriber, SignupSource, SubscriberEmail, SubscriberName,
+    SubscriptionStatus,
+};
+use crate::email_domain_rules::DomainRules;
+use crate::email_outbox::enqueue_email;
+use crate::email_templates::get_email_template_or_default;
+use crate::newsletter_template::TemplateValues;
 use crate::routes::helpers::chain_error_fmt;
-use crate::startup::ApplicationBaseUrl;
+use crate::routes::{preferences_link, unsubscribe_link};
+use crate::spam_protection::{form_token, SpamCheckError, SpamProtection};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::suppression_list::is_suppressed;
+use crate::topics::set_subscriber_topics;
+use crate::utils::HtmlForm;
+
+/// How long a confirmation link stays valid after it has been issued.
+pub const SUBSCRIPTION_TOKEN_TTL_HOURS: i64 = 24;
+/// Minimum delay between two confirmation emails sent to the same address.
+pub const CONFIRMATION_RESEND_COOLDOWN_SECONDS: i64 = 120;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
-    name: String
+    name: String,
+    /// Honeypot: hidden from people, so only bots fill it in.
+    #[serde(default)]
+    website: String,
+    /// Signed time at which the signup form was rendered.
+    form_token: Option<String>,
+    /// The topics to subscribe to; none means all of them.
+    #[serde(default)]
+    topics: Vec<Uuid>,
+    /// The page the form is on, when it can't be told from the `Referer`
+    /// header, e.g. for forms embedded with JavaScript.
+    source_url: Option<String>,
+    referrer: Option<String>,
+    utm_source: Option<String>,
+    utm_medium: Option<String>,
+    utm_campaign: Option<String>,
+    utm_term: Option<String>,
+    utm_content: Option<String>,
+    /// The version of the consent text shown next to the form.
+    consent_version: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+    type Error = FieldError;
 
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
+        let name = SubscriberName::parse(value.name).map_err(FieldError::invalid_name)?;
+        let email = SubscriberEmail::parse(value.email).map_err(FieldError::invalid_email)?;
         Ok(Self { email, name})
     }
 }
 
+impl FormData {
+    fn take_signup_source(&mut self, request: &HttpRequest) -> SignupSource {
+        let source_url = self.source_url.take().or_else(|| {
+            request
+                .headers()
+                .get(header::REFERER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        });
+        SignupSource {
+            source_url,
+            referrer: self.referrer.take(),
+            utm_source: self.utm_source.take(),
+            utm_medium: self.utm_medium.take(),
+            utm_campaign: self.utm_campaign.take(),
+            utm_term: self.utm_term.take(),
+            utm_content: self.utm_content.take(),
+            consent_version: self.consent_version.take(),
+        }
+        .normalized()
+    }
+}
+
+/// A validation failure on a single field of the subscription payload,
+/// returned to the client as `{"field": .., "code": .., "message": ..}`.
+#[derive(Debug, serde::Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn invalid_name(message: String) -> Self {
+        Self { field: "name", code: "invalid_name", message }
+    }
+
+    pub fn invalid_email(message: String) -> Self {
+        Self { field: "email", code: "invalid_email", message }
+    }
+
+    pub fn blocked_domain(message: String) -> Self {
+        Self { field: "email", code: "blocked_domain", message }
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
 pub struct StoreTokenError(sqlx::Error);
 
 impl std::fmt::Debug for StoreTokenError {
@@ -55,7 +143,7 @@ impl std::fmt::Display for StoreTokenError {
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
     #[error("{0}")]
-    ValidationError(String),
+    ValidationError(FieldError),
     #[error(transparent)]
     UnexepctedError(#[from] anyhow::Error),
 }
@@ -74,12 +162,15 @@ impl ResponseError for SubscribeError {
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code())
-            .json(self.to_string())
+        match self {
+            SubscribeError::ValidationError(e) => HttpResponse::build(self.status_code()).json(e),
+            SubscribeError::UnexepctedError(_) => HttpResponse::build(self.status_code())
+                .json(self.to_string()),
+        }
     }
 }
 
-fn generate_subscription_token() -> String {
+pub(crate) fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -87,6 +178,12 @@ fn generate_subscription_token() -> String {
         .collect()
 }
 
+/// Only the SHA-256 digest of a subscription token is persisted, so that
+/// read access to the database is not enough to confirm subscriptions.
+pub fn hash_subscription_token(subscription_token: &str) -> String {
+    hex::encode(Sha256::digest(subscription_token.as_bytes()))
+}
+
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(form, transaction)
@@ -99,29 +196,265 @@ pub async fn insert_subscriber(
     let query = sqlx::query!(
         r#"
         INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        VALUES ($1, $2, $3, $4, $5)
         "#,
         subscriber_id,
         form.email.as_ref(),
         form.name.as_ref(),
         Utc::now(),
+        SubscriptionStatus::PendingConfirmation as SubscriptionStatus,
     );
 
     transaction.execute(query).await?;
     Ok(subscriber_id)
 }
 
+/// Inserts the subscriber unless the address is already on the list, in
+/// which case `None` is returned. When another transaction is inserting the
+/// same address, this waits for it rather than failing on the unique index.
+#[tracing::instrument(
+    name = "Saving new subscriber details in the database unless they exist",
+    skip(form, transaction)
+)]
+pub async fn insert_subscriber_unless_listed(
+    transaction: &mut Transaction<'_, Postgres>,
+    form: &NewSubscriber,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        RETURNING id
+        "#,
+        Uuid::new_v4(),
+        form.email.as_ref(),
+        form.name.as_ref(),
+        Utc::now(),
+        SubscriptionStatus::PendingConfirmation as SubscriptionStatus,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
 
+/// Overwrites the source of earlier signups: what counts is the form the
+/// pending confirmation was asked for from.
+#[tracing::instrument(
+    name = "Record where a subscriber signed up from",
+    skip(transaction)
+)]
+pub async fn record_signup_source(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    source: &SignupSource,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            source_url = $2,
+            referrer = $3,
+            utm_source = $4,
+            utm_medium = $5,
+            utm_campaign = $6,
+            utm_term = $7,
+            utm_content = $8,
+            consent_version = $9
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        source.source_url,
+        source.referrer,
+        source.utm_source,
+        source.utm_medium,
+        source.utm_campaign,
+        source.utm_term,
+        source.utm_content,
+        source.consent_version,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum StatusChangeError {
+    #[error("There is no such subscriber.")]
+    UnknownSubscriber,
+    #[error(transparent)]
+    InvalidTransition(#[from] InvalidStatusTransition),
+    #[error("Failed to change the status of the subscription.")]
+    UnexpectedError(#[from] sqlx::Error),
+}
+
+impl std::fmt::Debug for StatusChangeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        chain_error_fmt(self, f)
+    }
+}
+
+pub struct StatusChange {
+    pub email: String,
+    pub from: SubscriptionStatus,
+}
+
+/// Every change of status goes through here, so that none of them gets
+/// around `SubscriptionStatus::can_transition_to`. Asking for the current
+/// status changes nothing: callers can tell from `StatusChange::from`.
+#[tracing::instrument(
+    name = "Change the status of a subscription",
+    skip(transaction)
+)]
+pub async fn change_subscription_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    to: SubscriptionStatus,
+) -> Result<StatusChange, StatusChangeError> {
+    let current = sqlx::query!(
+        r#"
+        SELECT email, status AS "status: SubscriptionStatus"
+        FROM subscriptions
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        subscriber_id
+    )
+        .fetch_optional(&mut **transaction)
+        .await?
+        .ok_or(StatusChangeError::UnknownSubscriber)?;
+    if current.status == to {
+        return Ok(StatusChange { email: current.email, from: to });
+    }
+    current.status.transition_to(to)?;
+    // Starting over forgets when the previous subscription was confirmed
+    // or ended.
+    let query = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            status = $2::subscription_status,
+            confirmed_at = CASE $2::subscription_status
+                WHEN 'confirmed' THEN now()
+                WHEN 'pending_confirmation' THEN NULL
+                ELSE confirmed_at
+            END,
+            unsubscribed_at = CASE $2::subscription_status
+                WHEN 'unsubscribed' THEN now()
+                WHEN 'pending_confirmation' THEN NULL
+                ELSE unsubscribed_at
+            END
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        to as SubscriptionStatus,
+    );
+    transaction.execute(query).await?;
+    Ok(StatusChange { email: current.email, from: current.status })
+}
+
+pub(crate) struct ExistingSubscriber {
+    pub id: Uuid,
+    pub status: SubscriptionStatus,
+    pub last_token_issued_at: Option<DateTime<Utc>>,
+}
+
+impl ExistingSubscriber {
+    /// Whether a confirmation email was sent to this subscriber too recently
+    /// to send another one.
+    pub fn is_rate_limited(&self) -> bool {
+        match self.last_token_issued_at {
+            Some(issued_at) => {
+                Utc::now() - issued_at < Duration::seconds(CONFIRMATION_RESEND_COOLDOWN_SECONDS)
+            }
+            None => false,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Get subscriber by email",
+    skip(transaction)
+)]
+pub(crate) async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT id, status AS "status: SubscriptionStatus"
+        FROM subscriptions
+        WHERE lower(email) = lower($1)
+        FOR UPDATE
+        "#,
+        email.as_ref()
+    )
+        .fetch_optional(&mut **transaction)
+        .await?;
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(None),
+    };
+    // Read once the row is locked, in a statement of its own: the snapshot
+    // of the locking one predates tokens stored by whoever held the lock.
+    let last_token_issued_at = sqlx::query_scalar!(
+        r#"
+        SELECT MAX(created_at)
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        "#,
+        subscriber.id
+    )
+        .fetch_one(&mut **transaction)
+        .await?;
+    Ok(Some(ExistingSubscriber {
+        id: subscriber.id,
+        status: subscriber.status,
+        last_token_issued_at,
+    }))
+}
+
+#[tracing::instrument(
+    name = "Restart double opt-in for a returning subscriber",
+    skip(transaction, form)
+)]
+pub async fn restart_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    form: &NewSubscriber,
+) -> Result<(), StatusChangeError> {
+    change_subscription_status(transaction, subscriber_id, SubscriptionStatus::PendingConfirmation)
+        .await?;
+    let query = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = $2
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        form.name.as_ref(),
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// The confirmation email is wrapped in the default email template, like
+/// issues sent without one.
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber)
+    skip(transaction, recipient, base_url, hmac_secret)
 )]
 pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    recipient: &SubscriberEmail,
     base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), sqlx::Error> {
+    if is_suppressed(&mut **transaction, recipient.as_ref()).await? {
+        tracing::warn!("Not sending a confirmation email to an address on the suppression list.");
+        return Ok(());
+    }
+    let subject = "Welcome!";
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url.0,
@@ -136,14 +469,34 @@ pub async fn send_confirmation_email(
         "Welcome to our newsletter! Visit {} to confirm your subscription.",
         confirmation_link
     );
-    email_client
-        .send_email(
-            &new_subscriber.email,
-            "Welcome!",
-            html_body.as_str(),
-            text_body.as_str(),
-        )
-        .await
+    let subscriber_name = sqlx::query_scalar!(
+        r#"SELECT name FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+        .fetch_one(&mut **transaction)
+        .await?;
+    let template = get_email_template_or_default(&mut **transaction, None).await?;
+    let values = TemplateValues {
+        subscriber_name,
+        subscriber_email: recipient.as_ref().to_string(),
+        issue_title: subject.to_string(),
+        issue_web_url: format!("{}/archive", base_url.0),
+        preferences_url: preferences_link(base_url, hmac_secret, subscriber_id),
+        unsubscribe_url: unsubscribe_link(base_url, hmac_secret, subscriber_id),
+        postal_address: template.postal_address.clone(),
+    };
+    let (html_body, text_body) = template.wrap(&values, &html_body, &text_body);
+    // The email goes through the outbox: it is only sent by the background
+    // worker once the transaction has been committed.
+    enqueue_email(
+        transaction,
+        recipient,
+        subject,
+        html_body.as_str(),
+        text_body.as_str(),
+    )
+        .await?;
+    Ok(())
 }
 
 #[tracing::instrument(
@@ -151,10 +504,17 @@ pub async fn send_confirmation_email(
     skip(transaction, subscription_token)
 )]
 pub async fn store_subscription_token(transaction: &mut Transaction<'_, Postgres>, subscriber_id: &Uuid, subscription_token: &str) -> Result<(), StoreTokenError> {
+    let created_at = Utc::now();
+    let expires_at = created_at + Duration::hours(SUBSCRIPTION_TOKEN_TTL_HOURS);
     let query = sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)"#,
-        subscription_token,
-        subscriber_id
+        r#"
+        INSERT INTO subscription_tokens (subscription_token_hash, subscriber_id, created_at, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        hash_subscription_token(subscription_token),
+        subscriber_id,
+        created_at,
+        expires_at,
     );
     transaction
         .execute(query)
@@ -165,28 +525,97 @@ pub async fn store_subscription_token(transaction: &mut Transaction<'_, Postgres
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, connection_pool, email_client),
+    skip(request, body, connection_pool, spam_protection, hmac_secret, domain_rules),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name,
+        subscriber_email = tracing::field::Empty,
+        subscriber_name = tracing::field::Empty,
     )
 )]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    request: HttpRequest,
+    // Signup forms embedded with JavaScript post JSON, plain HTML forms
+    // post url-encoded data: both are accepted.
+    body: Either<web::Json<FormData>, HtmlForm<FormData>>,
     connection_pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
-    base_url: web::Data<ApplicationBaseUrl>
+    base_url: web::Data<ApplicationBaseUrl>,
+    spam_protection: web::Data<SpamProtection>,
+    hmac_secret: web::Data<HmacSecret>,
+    domain_rules: web::Data<DomainRules>,
 ) -> Result<HttpResponse, SubscribeError>{
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let mut form = match body {
+        Either::Left(json) => json.into_inner(),
+        Either::Right(form) => form.0,
+    };
+    tracing::Span::current()
+        .record("subscriber_email", tracing::field::display(&form.email))
+        .record("subscriber_name", tracing::field::display(&form.name));
+    let spam_check = spam_protection.check_form(&hmac_secret, &form.website, form.form_token.as_deref());
+    if let Some(response) = reject_spam(spam_check)? {
+        return Ok(response);
+    }
+    let topics = std::mem::take(&mut form.topics);
+    let signup_source = form.take_signup_source(&request);
+    let new_subscriber: NewSubscriber = form
+        .try_into()
+        .map_err(SubscribeError::ValidationError)?;
+    domain_rules
+        .check(connection_pool.get_ref(), &new_subscriber.email)
+        .await
+        .context("Failed to check the email domain rules.")?
+        .map_err(|e| SubscribeError::ValidationError(FieldError::blocked_domain(e)))?;
+    let ip = spam_protection
+        .client_ip(&request)
+        .map(|ip| ip.to_string())
+        .unwrap_or_default();
+    let spam_check = spam_protection.check_rate_limits(&ip, &new_subscriber.email).await;
+    if let Some(response) = reject_spam(spam_check)? {
+        return Ok(response);
+    }
+
     let mut transaction = connection_pool.begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool.")?;
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    // Inserting first, rather than looking the address up first, so that two
+    // concurrent signups for a new address can't both try to insert it.
+    let inserted = insert_subscriber_unless_listed(&mut transaction, &new_subscriber)
         .await
-        .context(
-            "Failed to insert new subscriber in the database."
-        )?;
+        .context("Failed to insert new subscriber in the database.")?;
+
+    // Every branch answers with the same response, so that the endpoint
+    // can't be used to find out which addresses are on the list.
+    let subscriber_id = match inserted {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            let subscriber = get_subscriber_by_email(&mut transaction, &new_subscriber.email)
+                .await
+                .context("Failed to look up the subscriber in the database.")?
+                .context("The subscriber was deleted while subscribing again.")?;
+            match subscriber.status {
+                SubscriptionStatus::Confirmed => return Ok(HttpResponse::Ok().finish()),
+                SubscriptionStatus::PendingConfirmation => {
+                    if subscriber.is_rate_limited() {
+                        tracing::warn!("A confirmation email was requested too soon after the previous one.");
+                        return Ok(HttpResponse::Ok().finish());
+                    }
+                    subscriber.id
+                }
+                _ => {
+                    restart_subscription(&mut transaction, subscriber.id, &new_subscriber)
+                        .await
+                        .context("Failed to restart the subscription in the database.")?;
+                    subscriber.id
+                }
+            }
+        }
+    };
+
+    set_subscriber_topics(&mut transaction, subscriber_id, &topics)
+        .await
+        .context("Failed to store the subscriber's topics in the database.")?;
+    record_signup_source(&mut transaction, subscriber_id, &signup_source)
+        .await
+        .context("Failed to store where the subscriber signed up from.")?;
 
     let subscription_token = generate_subscription_token();
     store_subscription_token(&mut transaction, &subscriber_id, &subscription_token)
@@ -194,17 +623,45 @@ pub async fn subscribe(
         .context("Failed to store subscription token in the database.")?;
 
     send_confirmation_email(
-        &email_client,
-        new_subscriber,
+        &mut transaction,
+        subscriber_id,
+        &new_subscriber.email,
         base_url.as_ref(),
+        hmac_secret.as_ref(),
         subscription_token.as_str(),
     )
         .await
-        .context("Failed to send a confirmation email.")?;
+        .context("Failed to enqueue a confirmation email.")?;
 
     transaction.commit()
         .await
         .context("Failed to commit SQL transaction.")?;
 
     Ok(HttpResponse::Ok().finish())
-}
\ No newline at end of file
+}
+
+/// Spam is answered exactly like a successful subscription, so that bots
+/// don't learn which of their attempts got through.
+fn reject_spam(check: Result<(), SpamCheckError>) -> Result<Option<HttpResponse>, anyhow::Error> {
+    match check {
+        Ok(()) => Ok(None),
+        Err(SpamCheckError::UnexpectedError(e)) => Err(e),
+        Err(e) => {
+            tracing::warn!(reason = %e, "Rejected a subscription attempt.");
+            Ok(Some(HttpResponse::Ok().finish()))
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct FormTokenResponse {
+    form_token: String,
+}
+
+/// For signup forms that are not rendered by us, e.g. embedded with
+/// JavaScript: they fetch a token when the form is shown.
+pub async fn subscription_form_token(hmac_secret: web::Data<HmacSecret>) -> HttpResponse {
+    HttpResponse::Ok().json(FormTokenResponse {
+        form_token: form_token(&hmac_secret, Utc::now()),
+    })
+}
@@ -0,0 +1,159 @@
+use actix_web::{HttpResponse, web};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
+use crate::domain::{SubscriberEmail, SubscriptionStatus};
+use crate::routes::{
+    change_subscription_status, hash_subscription_token, preferences_link, unsubscribe_link,
+    StatusChange, StatusChangeError,
+};
+use crate::startup::{ApplicationBaseUrl, ConfirmationRedirectUrl, HmacSecret};
+use crate::utils::see_other;
+use crate::welcome_email::enqueue_welcome_email;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    subscription_token: String,
+}
+
+pub struct SubscriptionTokenRecord {
+    pub subscriber_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub status: SubscriptionStatus,
+}
+
+#[tracing::instrument(
+    name = "Retrieve subscriber_id by token",
+    skip(db_pool, subscription_token)
+)]
+pub async fn get_subscriber_id_from_token(db_pool: &PgPool, subscription_token: &str) -> Result<Option<SubscriptionTokenRecord>, sqlx::Error> {
+   let result = sqlx::query_as!(
+       SubscriptionTokenRecord,
+       r#"
+       SELECT t.subscriber_id, t.expires_at, s.status AS "status: SubscriptionStatus"
+       FROM subscription_tokens t
+       JOIN subscriptions s ON s.id = t.subscriber_id
+       WHERE t.subscription_token_hash = $1
+       "#,
+       hash_subscription_token(subscription_token)
+   )
+       .fetch_optional(db_pool)
+       .await
+       .map_err(|e| {
+           tracing::error!("Failed to execute query: {:?}", e);
+           e
+       })?;
+
+   Ok(result)
+}
+
+/// Confirming a subscriber who already is changes nothing, which callers
+/// can tell from `StatusChange::from`.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(transaction, subscriber_id)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<StatusChange, StatusChangeError> {
+    let change = change_subscription_status(transaction, subscriber_id, SubscriptionStatus::Confirmed)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to confirm the subscriber: {:?}", e);
+            e
+        })?;
+    Ok(change)
+}
+
+#[tracing::instrument(
+    name = "Confirm subscription and welcome the subscriber",
+    skip(db_pool, base_url, hmac_secret)
+)]
+async fn confirm_and_welcome(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let change = confirm_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to mark the subscriber as confirmed.")?;
+    // Another click on the link got there first.
+    if change.from != SubscriptionStatus::PendingConfirmation {
+        return Ok(());
+    }
+    let email = SubscriberEmail::parse(change.email).map_err(|e| anyhow::anyhow!(e))?;
+    enqueue_welcome_email(
+        &mut transaction,
+        &email,
+        &format!("{}/archive", base_url.0),
+        &preferences_link(base_url, hmac_secret, subscriber_id),
+        &unsubscribe_link(base_url, hmac_secret, subscriber_id),
+    )
+        .await
+        .context("Failed to enqueue the welcome email.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+    Ok(())
+}
+
+fn landing_page(status_code: StatusCode, body: &'static str) -> HttpResponse {
+    HttpResponse::build(status_code)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+fn confirmed_response(redirect_url: &ConfirmationRedirectUrl, body: &'static str) -> HttpResponse {
+    match &redirect_url.0 {
+        Some(url) => see_other(url),
+        None => landing_page(StatusCode::OK, body),
+    }
+}
+
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(db_pool, parameters, redirect_url, base_url, hmac_secret)
+)]
+pub async fn confirm(
+    db_pool: web::Data<PgPool>,
+    parameters: web::Query<Parameters>,
+    redirect_url: web::Data<ConfirmationRedirectUrl>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let record = match get_subscriber_id_from_token(&db_pool, parameters.subscription_token.as_str()).await {
+        Ok(record) => record,
+        Err(_) => return landing_page(StatusCode::INTERNAL_SERVER_ERROR, include_str!("error.html")),
+    };
+    match record {
+        Some(record) if record.status == SubscriptionStatus::Confirmed => {
+            confirmed_response(&redirect_url, include_str!("already_confirmed.html"))
+        }
+        Some(record) if record.status != SubscriptionStatus::PendingConfirmation => {
+            landing_page(StatusCode::UNAUTHORIZED, include_str!("invalid.html"))
+        }
+        Some(record) if record.expires_at < Utc::now() => {
+            landing_page(StatusCode::UNAUTHORIZED, include_str!("expired.html"))
+        }
+        Some(record) => {
+            match confirm_and_welcome(&db_pool, record.subscriber_id, &base_url, &hmac_secret).await {
+                Err(e) => {
+                    tracing::error!(error.cause_chain = ?e, "Failed to confirm a subscriber");
+                    landing_page(StatusCode::INTERNAL_SERVER_ERROR, include_str!("error.html"))
+                }
+                Ok(()) => confirmed_response(&redirect_url, include_str!("confirmed.html")),
+            }
+        }
+        None => landing_page(StatusCode::UNAUTHORIZED, include_str!("invalid.html")),
+    }
+}
@@ -0,0 +1,315 @@
+use crate::domain::SubscriberEmail;
+use crate::email_outbox::enqueue_email;
+use crate::routes::{get_subscriber_by_email, subscriber_mac, verify_subscriber_token};
+use crate::spam_protection::{form_token, SpamCheckError, SpamProtection};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::subscriber_data::{erase_subscriber, get_subscriber_data};
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use hmac::Mac;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Links to someone's data give access to all of it, so unlike the other
+/// links we send they stop working after a while.
+pub const DATA_LINK_TTL_HOURS: i64 = 24;
+
+/// Another data link is only sent to an address this long after the
+/// previous one.
+pub const DATA_LINK_COOLDOWN_SECONDS: i64 = 120;
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestFormData {
+    email: String,
+    /// Honeypot, see `SpamProtection::check_form`.
+    #[serde(default)]
+    website: String,
+    form_token: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DataLinkParameters {
+    subscriber_id: Uuid,
+    /// Unix timestamp, in seconds.
+    expires: i64,
+    token: String,
+}
+
+fn data_link_purpose(expires: i64) -> String {
+    format!("data:{}", expires)
+}
+
+pub fn data_link(
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Uuid,
+    expires: i64,
+) -> String {
+    let token = hex::encode(
+        subscriber_mac(hmac_secret, &data_link_purpose(expires), subscriber_id)
+            .finalize()
+            .into_bytes(),
+    );
+    format!(
+        "{}/subscriptions/data/manage?subscriber_id={}&expires={}&token={}",
+        base_url.0, subscriber_id, expires, token
+    )
+}
+
+fn verify_data_link(hmac_secret: &HmacSecret, parameters: &DataLinkParameters) -> bool {
+    parameters.expires > Utc::now().timestamp()
+        && verify_subscriber_token(
+            hmac_secret,
+            &data_link_purpose(parameters.expires),
+            parameters.subscriber_id,
+            &parameters.token,
+        )
+}
+
+fn landing_page(status_code: StatusCode, body: String) -> HttpResponse {
+    HttpResponse::build(status_code)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+fn error_page(e: anyhow::Error) -> HttpResponse {
+    tracing::error!(error.cause_chain = ?e, "Failed to handle a data request");
+    landing_page(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        include_str!("error.html").into(),
+    )
+}
+
+fn invalid_link_page() -> HttpResponse {
+    landing_page(
+        StatusCode::UNAUTHORIZED,
+        include_str!("invalid.html").into(),
+    )
+}
+
+fn request_page(status_code: StatusCode, hmac_secret: &HmacSecret, message: &str) -> HttpResponse {
+    landing_page(
+        status_code,
+        format!(
+            include_str!("request.html"),
+            message = message,
+            form_token = form_token(hmac_secret, Utc::now()),
+        ),
+    )
+}
+
+fn request_sent_page() -> HttpResponse {
+    landing_page(
+        StatusCode::OK,
+        format!(
+            include_str!("request_sent.html"),
+            ttl_hours = DATA_LINK_TTL_HOURS
+        ),
+    )
+}
+
+pub async fn data_request_form(hmac_secret: web::Data<HmacSecret>) -> HttpResponse {
+    request_page(StatusCode::OK, &hmac_secret, "")
+}
+
+/// The form is public and sends an email: it is held to the same spam checks
+/// and rate limits as the signup form.
+#[tracing::instrument(
+    name = "Request access to a subscriber's data",
+    skip(request, form, pool, base_url, hmac_secret, spam_protection),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn request_data(
+    request: HttpRequest,
+    form: web::Form<DataRequestFormData>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    spam_protection: web::Data<SpamProtection>,
+) -> HttpResponse {
+    let form = form.into_inner();
+    let spam_check =
+        spam_protection.check_form(&hmac_secret, &form.website, form.form_token.as_deref());
+    if let Some(response) = reject_spam(spam_check) {
+        return response;
+    }
+    let email = match SubscriberEmail::parse(form.email) {
+        Ok(email) => email,
+        Err(e) => {
+            return request_page(
+                StatusCode::BAD_REQUEST,
+                &hmac_secret,
+                &format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&e)),
+            )
+        }
+    };
+    let ip = spam_protection
+        .client_ip(&request)
+        .map(|ip| ip.to_string())
+        .unwrap_or_default();
+    let spam_check = spam_protection.check_rate_limits(&ip, &email).await;
+    if let Some(response) = reject_spam(spam_check) {
+        return response;
+    }
+    if let Err(e) = send_data_link(&pool, &base_url, &hmac_secret, &email).await {
+        return error_page(e);
+    }
+    // The same page is shown whether or not the address is on the list.
+    request_sent_page()
+}
+
+/// Rejected requests get the same page as the others.
+fn reject_spam(check: Result<(), SpamCheckError>) -> Option<HttpResponse> {
+    match check {
+        Ok(()) => None,
+        Err(SpamCheckError::UnexpectedError(e)) => Some(error_page(e)),
+        Err(e) => {
+            tracing::warn!(reason = %e, "Rejected a data request.");
+            Some(request_sent_page())
+        }
+    }
+}
+
+async fn send_data_link(
+    pool: &PgPool,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+    email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let subscriber = match get_subscriber_by_email(&mut transaction, email)
+        .await
+        .context("Failed to look up the subscriber in the database.")?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(()),
+    };
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET data_link_sent_at = now()
+        WHERE
+            id = $1
+            AND (data_link_sent_at IS NULL OR data_link_sent_at < $2)
+        "#,
+        subscriber.id,
+        Utc::now() - Duration::seconds(DATA_LINK_COOLDOWN_SECONDS),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to record when the data link was sent.")?;
+    if updated.rows_affected() == 0 {
+        tracing::warn!("A data link was requested too soon after the previous one.");
+        return Ok(());
+    }
+    let expires = (Utc::now() + Duration::hours(DATA_LINK_TTL_HOURS)).timestamp();
+    let link = data_link(base_url, hmac_secret, subscriber.id, expires);
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> to download the data we hold on you or to have it erased. \
+        The link is valid for {} hours.",
+        link, DATA_LINK_TTL_HOURS
+    );
+    let text_body = format!(
+        "Visit {} to download the data we hold on you or to have it erased. \
+        The link is valid for {} hours.",
+        link, DATA_LINK_TTL_HOURS
+    );
+    enqueue_email(
+        &mut transaction,
+        email,
+        "Your data with us",
+        &html_body,
+        &text_body,
+    )
+    .await
+    .context("Failed to enqueue the email with the data link.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Data request page", skip_all)]
+pub async fn manage_data(
+    parameters: web::Query<DataLinkParameters>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if !verify_data_link(&hmac_secret, &parameters) {
+        return invalid_link_page();
+    }
+    landing_page(
+        StatusCode::OK,
+        format!(
+            include_str!("manage.html"),
+            subscriber_id = parameters.subscriber_id,
+            expires = parameters.expires,
+            token = htmlescape::encode_attribute(&parameters.token),
+        ),
+    )
+}
+
+#[tracing::instrument(
+    name = "Download a subscriber's data",
+    skip(parameters, pool, hmac_secret),
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn download_data(
+    parameters: web::Query<DataLinkParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if !verify_data_link(&hmac_secret, &parameters) {
+        return invalid_link_page();
+    }
+    match get_subscriber_data(&pool, parameters.subscriber_id).await {
+        Ok(Some(data)) => HttpResponse::Ok()
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename("my-data.json".into())],
+            })
+            .json(data),
+        // Erased since the link was sent.
+        Ok(None) => invalid_link_page(),
+        Err(e) => error_page(e),
+    }
+}
+
+#[tracing::instrument(
+    name = "Erase a subscriber's data on their request",
+    skip(form, pool, hmac_secret),
+    fields(subscriber_id = %form.subscriber_id)
+)]
+pub async fn erase_data(
+    form: web::Form<DataLinkParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if !verify_data_link(&hmac_secret, &form) {
+        return invalid_link_page();
+    }
+    match erase(&pool, form.subscriber_id).await {
+        // Erasing twice is as good as erasing once.
+        Ok(()) => landing_page(StatusCode::OK, include_str!("erased.html").into()),
+        Err(e) => error_page(e),
+    }
+}
+
+async fn erase(pool: &PgPool, subscriber_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    erase_subscriber(&mut transaction, subscriber_id).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+    Ok(())
+}
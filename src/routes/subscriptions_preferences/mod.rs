@@ -0,0 +1,528 @@
+use crate::domain::{SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::email_domain_rules::DomainRules;
+use crate::email_outbox::enqueue_email;
+use crate::routes::{
+    generate_subscription_token, hash_subscription_token, subscriber_mac, unsubscribe_token,
+    verify_subscriber_token, SUBSCRIPTION_TOKEN_TTL_HOURS,
+};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::topics::{get_subscriber_topics, get_topics, set_subscriber_topics, topic_checkboxes};
+use crate::utils::HtmlForm;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use hmac::Mac;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesFormData {
+    subscriber_id: Uuid,
+    token: String,
+    name: String,
+    email: String,
+    #[serde(default)]
+    topics: Vec<Uuid>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeParameters {
+    token: String,
+}
+
+struct SubscriberPreferences {
+    name: String,
+    email: String,
+    status: SubscriptionStatus,
+}
+
+struct EmailChangeRequest {
+    subscriber_id: Uuid,
+    new_email: String,
+    expires_at: DateTime<Utc>,
+}
+
+pub fn preferences_token(hmac_secret: &HmacSecret, subscriber_id: Uuid) -> String {
+    hex::encode(
+        subscriber_mac(hmac_secret, "preferences", subscriber_id)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+pub fn preferences_link(
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Uuid,
+) -> String {
+    format!(
+        "{}/subscriptions/preferences?subscriber_id={}&token={}",
+        base_url.0,
+        subscriber_id,
+        preferences_token(hmac_secret, subscriber_id)
+    )
+}
+
+fn landing_page(status_code: StatusCode, body: String) -> HttpResponse {
+    HttpResponse::build(status_code)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+fn error_page(e: anyhow::Error) -> HttpResponse {
+    tracing::error!(error.cause_chain = ?e, "Failed to handle subscription preferences");
+    landing_page(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        include_str!("error.html").into(),
+    )
+}
+
+#[tracing::instrument(name = "Get subscriber preferences", skip(pool))]
+async fn get_subscriber_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberPreferences>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberPreferences,
+        r#"
+        SELECT name, email, status AS "status: SubscriptionStatus"
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Looks the subscriber up, unless the link is forged or they have
+/// unsubscribed since it was sent.
+async fn authorize(
+    pool: &PgPool,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Uuid,
+    token: &str,
+) -> Result<Option<SubscriberPreferences>, anyhow::Error> {
+    if !verify_subscriber_token(hmac_secret, "preferences", subscriber_id, token) {
+        return Ok(None);
+    }
+    let subscriber = get_subscriber_preferences(pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the subscriber.")?;
+    Ok(subscriber.filter(|s| {
+        matches!(
+            s.status,
+            SubscriptionStatus::Confirmed | SubscriptionStatus::PendingConfirmation
+        )
+    }))
+}
+
+async fn render_preferences(
+    pool: &PgPool,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Uuid,
+    name: &str,
+    email: &str,
+    message: &str,
+) -> Result<String, anyhow::Error> {
+    let topics = get_topics(pool)
+        .await
+        .context("Failed to retrieve topics.")?;
+    let selected = get_subscriber_topics(pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the subscriber's topics.")?;
+    let message = if message.is_empty() {
+        String::new()
+    } else {
+        format!("<p><i>{}</i></p>", htmlescape::encode_minimal(message))
+    };
+    Ok(format!(
+        include_str!("preferences.html"),
+        message = message,
+        subscriber_id = subscriber_id,
+        token = preferences_token(hmac_secret, subscriber_id),
+        name = htmlescape::encode_minimal(name),
+        email = htmlescape::encode_minimal(email),
+        topics = topic_checkboxes(&topics, &selected),
+        unsubscribe_token = unsubscribe_token(hmac_secret, subscriber_id),
+    ))
+}
+
+#[tracing::instrument(name = "Subscription preferences form", skip_all)]
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let subscriber_id = parameters.subscriber_id;
+    let subscriber = match authorize(&pool, &hmac_secret, subscriber_id, &parameters.token).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => {
+            return landing_page(
+                StatusCode::UNAUTHORIZED,
+                include_str!("invalid.html").into(),
+            )
+        }
+        Err(e) => return error_page(e),
+    };
+    match render_preferences(
+        &pool,
+        &hmac_secret,
+        subscriber_id,
+        &subscriber.name,
+        &subscriber.email,
+        "",
+    )
+    .await
+    {
+        Ok(page) => landing_page(StatusCode::OK, page),
+        Err(e) => error_page(e),
+    }
+}
+
+#[tracing::instrument(
+    name = "Update subscription preferences",
+    skip(form, pool, hmac_secret, base_url, domain_rules),
+    fields(subscriber_id = %form.0.subscriber_id)
+)]
+pub async fn update_preferences(
+    form: HtmlForm<PreferencesFormData>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    domain_rules: web::Data<DomainRules>,
+) -> HttpResponse {
+    let form = form.0;
+    let subscriber_id = form.subscriber_id;
+    let subscriber = match authorize(&pool, &hmac_secret, subscriber_id, &form.token).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => {
+            return landing_page(
+                StatusCode::UNAUTHORIZED,
+                include_str!("invalid.html").into(),
+            )
+        }
+        Err(e) => return error_page(e),
+    };
+    let (status_code, message) = match save_preferences(
+        &pool,
+        &base_url,
+        &domain_rules,
+        subscriber_id,
+        &subscriber.email,
+        &form,
+    )
+    .await
+    {
+        Ok(Ok(message)) => (StatusCode::OK, message),
+        Ok(Err(validation_error)) => (StatusCode::BAD_REQUEST, validation_error),
+        Err(e) => return error_page(e),
+    };
+    match render_preferences(
+        &pool,
+        &hmac_secret,
+        subscriber_id,
+        &form.name,
+        &subscriber.email,
+        &message,
+    )
+    .await
+    {
+        Ok(page) => landing_page(status_code, page),
+        Err(e) => error_page(e),
+    }
+}
+
+/// Returns the message to show the subscriber, or a validation error.
+async fn save_preferences(
+    pool: &PgPool,
+    base_url: &ApplicationBaseUrl,
+    domain_rules: &DomainRules,
+    subscriber_id: Uuid,
+    current_email: &str,
+    form: &PreferencesFormData,
+) -> Result<Result<String, String>, anyhow::Error> {
+    let name = match SubscriberName::parse(form.name.clone()) {
+        Ok(name) => name,
+        Err(e) => return Ok(Err(e)),
+    };
+    let email = match SubscriberEmail::parse(form.email.clone()) {
+        Ok(email) => email,
+        Err(e) => return Ok(Err(e)),
+    };
+    let email_changed = email.as_ref().to_lowercase() != current_email.to_lowercase();
+    if email_changed {
+        let domain_check = domain_rules
+            .check(pool, &email)
+            .await
+            .context("Failed to check the email domain rules.")?;
+        if let Err(e) = domain_check {
+            return Ok(Err(e));
+        }
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    update_name(&mut transaction, subscriber_id, &name)
+        .await
+        .context("Failed to update the subscriber's name.")?;
+    set_subscriber_topics(&mut transaction, subscriber_id, &form.topics)
+        .await
+        .context("Failed to update the subscriber's topics.")?;
+    let mut message = "Your preferences have been saved.".to_string();
+    if email_changed {
+        let token = generate_subscription_token();
+        store_email_change_request(&mut transaction, subscriber_id, &email, &token)
+            .await
+            .context("Failed to store the email change request.")?;
+        send_email_change_confirmation(&mut transaction, &email, base_url, &token)
+            .await
+            .context("Failed to enqueue the email change confirmation.")?;
+        message.push_str(&format!(
+            " We sent a link to {} to confirm your new address.",
+            email
+        ));
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+    Ok(Ok(message))
+}
+
+#[tracing::instrument(skip(transaction, name))]
+async fn update_name(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    name: &SubscriberName,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = $2
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        name.as_ref(),
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Only the latest request counts: asking for another address replaces it.
+#[tracing::instrument(skip(transaction, token))]
+async fn store_email_change_request(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    new_email: &SubscriberEmail,
+    token: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM email_change_requests
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+    let created_at = Utc::now();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO email_change_requests (token_hash, subscriber_id, new_email, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        hash_subscription_token(token),
+        subscriber_id,
+        new_email.as_ref(),
+        created_at,
+        created_at + Duration::hours(SUBSCRIPTION_TOKEN_TTL_HOURS),
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction, token))]
+async fn send_email_change_confirmation(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipient: &SubscriberEmail,
+    base_url: &ApplicationBaseUrl,
+    token: &str,
+) -> Result<(), sqlx::Error> {
+    let confirmation_link = format!(
+        "{}/subscriptions/preferences/confirm_email?token={}",
+        base_url.0, token
+    );
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> to receive our newsletter at this address.",
+        confirmation_link
+    );
+    let text_body = format!(
+        "Visit {} to receive our newsletter at this address.",
+        confirmation_link
+    );
+    enqueue_email(
+        transaction,
+        recipient,
+        "Confirm your new email address",
+        &html_body,
+        &text_body,
+    )
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Show an email address change confirmation form", skip_all)]
+pub async fn confirm_email_change_form(
+    parameters: web::Query<EmailChangeParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let request = sqlx::query!(
+        r#"
+        SELECT new_email
+        FROM email_change_requests
+        WHERE token_hash = $1 AND expires_at >= now()
+        "#,
+        hash_subscription_token(&parameters.token)
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the email change request.");
+    match request {
+        // The change takes an explicit POST so that link scanners
+        // prefetching the email don't confirm it.
+        Ok(Some(request)) => landing_page(
+            StatusCode::OK,
+            format!(
+                include_str!("confirm_email.html"),
+                new_email = htmlescape::encode_minimal(&request.new_email),
+                token = htmlescape::encode_attribute(&parameters.token),
+            ),
+        ),
+        Ok(None) => landing_page(
+            StatusCode::UNAUTHORIZED,
+            include_str!("email_change_invalid.html").into(),
+        ),
+        Err(e) => error_page(e),
+    }
+}
+
+#[tracing::instrument(name = "Confirm an email address change", skip_all)]
+pub async fn confirm_email_change(
+    form: web::Form<EmailChangeParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    match apply_email_change(&pool, &form.token).await {
+        Ok(EmailChangeOutcome::Applied) => {
+            landing_page(StatusCode::OK, include_str!("email_confirmed.html").into())
+        }
+        Ok(EmailChangeOutcome::InvalidToken) => landing_page(
+            StatusCode::UNAUTHORIZED,
+            include_str!("email_change_invalid.html").into(),
+        ),
+        Ok(EmailChangeOutcome::AddressTaken) => landing_page(
+            StatusCode::CONFLICT,
+            include_str!("email_taken.html").into(),
+        ),
+        Err(e) => error_page(e),
+    }
+}
+
+enum EmailChangeOutcome {
+    Applied,
+    InvalidToken,
+    AddressTaken,
+}
+
+async fn apply_email_change(
+    pool: &PgPool,
+    token: &str,
+) -> Result<EmailChangeOutcome, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let request = sqlx::query_as!(
+        EmailChangeRequest,
+        r#"
+        DELETE FROM email_change_requests
+        WHERE token_hash = $1
+        RETURNING subscriber_id, new_email, expires_at
+        "#,
+        hash_subscription_token(token)
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the email change request.")?;
+    let request = match request {
+        Some(request) if request.expires_at >= Utc::now() => request,
+        Some(_) => {
+            // Expired requests are cleaned up all the same.
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction.")?;
+            return Ok(EmailChangeOutcome::InvalidToken);
+        }
+        None => return Ok(EmailChangeOutcome::InvalidToken),
+    };
+    let old_email = sqlx::query!(
+        r#"
+        SELECT email
+        FROM subscriptions
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        request.subscriber_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to retrieve the subscriber.")?
+    .email;
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET email = $2
+        WHERE id = $1
+        "#,
+        request.subscriber_id,
+        request.new_email,
+    )
+    .execute(&mut *transaction)
+    .await;
+    match updated {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Ok(EmailChangeOutcome::AddressTaken)
+        }
+        Err(e) => return Err(anyhow::Error::new(e).context("Failed to update the email address.")),
+    }
+    // Issues still waiting to be delivered follow the subscriber.
+    let query = sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET subscriber_email = $2
+        WHERE subscriber_email = $1
+        "#,
+        old_email,
+        request.new_email,
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to move queued issues to the new address.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+    Ok(EmailChangeOutcome::Applied)
+}
@@ -0,0 +1,69 @@
+use crate::domain::{SubscriberEmail, SubscriptionStatus};
+use crate::routes::{
+    generate_subscription_token, get_subscriber_by_email, send_confirmation_email,
+    store_subscription_token, FieldError, SubscribeError,
+};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct ResendFormData {
+    email: String,
+}
+
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(form, pool, base_url, hmac_secret),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<ResendFormData>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, SubscribeError> {
+    let email = SubscriberEmail::parse(form.0.email)
+        .map_err(|e| SubscribeError::ValidationError(FieldError::invalid_email(e)))?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let subscriber = get_subscriber_by_email(&mut transaction, &email)
+        .await
+        .context("Failed to look up the subscriber in the database.")?;
+    // The response is the same whatever happens next, so that the endpoint
+    // can't be used to find out which addresses are on the list.
+    let subscriber = match subscriber {
+        Some(subscriber) if subscriber.status == SubscriptionStatus::PendingConfirmation => {
+            subscriber
+        }
+        _ => return Ok(HttpResponse::Ok().finish()),
+    };
+    if subscriber.is_rate_limited() {
+        tracing::warn!("A confirmation email was requested too soon after the previous one.");
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    let subscription_token = generate_subscription_token();
+    store_subscription_token(&mut transaction, &subscriber.id, &subscription_token)
+        .await
+        .context("Failed to store subscription token in the database.")?;
+    send_confirmation_email(
+        &mut transaction,
+        subscriber.id,
+        &email,
+        base_url.as_ref(),
+        hmac_secret.as_ref(),
+        subscription_token.as_str(),
+    )
+    .await
+    .context("Failed to enqueue a confirmation email.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
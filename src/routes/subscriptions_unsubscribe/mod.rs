@@ -0,0 +1,192 @@
+use crate::domain::SubscriptionStatus;
+use crate::routes::{change_subscription_status, StatusChangeError};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+/// Links sent to subscribers carry a token derived from their id with
+/// `HmacSecret`, so they never expire and don't have to be stored. `purpose`
+/// keeps a token for one kind of link from being used for another.
+pub(crate) fn subscriber_mac(
+    hmac_secret: &HmacSecret,
+    purpose: &str,
+    subscriber_id: Uuid,
+) -> Hmac<sha2::Sha256> {
+    let mut mac =
+        Hmac::<sha2::Sha256>::new_from_slice(hmac_secret.0.expose_secret().as_bytes()).unwrap();
+    mac.update(format!("{purpose}:{subscriber_id}").as_bytes());
+    mac
+}
+
+pub(crate) fn verify_subscriber_token(
+    hmac_secret: &HmacSecret,
+    purpose: &str,
+    subscriber_id: Uuid,
+    token: &str,
+) -> bool {
+    match hex::decode(token) {
+        Ok(tag) => subscriber_mac(hmac_secret, purpose, subscriber_id)
+            .verify_slice(&tag)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+pub fn unsubscribe_token(hmac_secret: &HmacSecret, subscriber_id: Uuid) -> String {
+    hex::encode(
+        subscriber_mac(hmac_secret, "unsubscribe", subscriber_id)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+pub fn unsubscribe_link(
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Uuid,
+) -> String {
+    format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&token={}",
+        base_url.0,
+        subscriber_id,
+        unsubscribe_token(hmac_secret, subscriber_id)
+    )
+}
+
+fn verify_unsubscribe_token(hmac_secret: &HmacSecret, parameters: &UnsubscribeParameters) -> bool {
+    verify_subscriber_token(
+        hmac_secret,
+        "unsubscribe",
+        parameters.subscriber_id,
+        &parameters.token,
+    )
+}
+
+fn landing_page(status_code: StatusCode, body: String) -> HttpResponse {
+    HttpResponse::build(status_code)
+        .content_type(ContentType::html())
+        .body(body)
+}
+
+#[tracing::instrument(name = "Unsubscribe form", skip_all)]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if !verify_unsubscribe_token(&hmac_secret, &parameters) {
+        return landing_page(
+            StatusCode::UNAUTHORIZED,
+            include_str!("invalid.html").into(),
+        );
+    }
+    // Unsubscribing takes an explicit POST so that link scanners prefetching
+    // the email don't unsubscribe people behind their back.
+    landing_page(
+        StatusCode::OK,
+        format!(
+            include_str!("unsubscribe.html"),
+            subscriber_id = parameters.subscriber_id,
+            token = htmlescape::encode_attribute(&parameters.token),
+        ),
+    )
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(form, db_pool, hmac_secret),
+    fields(subscriber_id = %form.subscriber_id)
+)]
+pub async fn unsubscribe(
+    form: web::Form<UnsubscribeParameters>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if !verify_unsubscribe_token(&hmac_secret, &form) {
+        return landing_page(
+            StatusCode::UNAUTHORIZED,
+            include_str!("invalid.html").into(),
+        );
+    }
+    match unsubscribe_subscriber(&db_pool, form.subscriber_id).await {
+        Ok(()) => landing_page(StatusCode::OK, include_str!("unsubscribed.html").into()),
+        Err(e) => {
+            tracing::error!(error.cause_chain = ?e, "Failed to unsubscribe a subscriber");
+            landing_page(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                include_str!("error.html").into(),
+            )
+        }
+    }
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(pool))]
+pub async fn unsubscribe_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(), StatusChangeError> {
+    let mut transaction = pool.begin().await?;
+    mark_unsubscribed(&mut transaction, subscriber_id).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Also drops whatever was still waiting to be sent to the subscriber.
+/// Unknown subscribers, and subscriptions that already ended in a way an
+/// unsubscription can't override, are left alone.
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(transaction))]
+pub(crate) async fn mark_unsubscribed(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), StatusChangeError> {
+    let email = match change_subscription_status(
+        transaction,
+        subscriber_id,
+        SubscriptionStatus::Unsubscribed,
+    )
+    .await
+    {
+        Ok(change) => change.email,
+        Err(StatusChangeError::UnknownSubscriber | StatusChangeError::InvalidTransition(_)) => {
+            return Ok(())
+        }
+        Err(e) => return Err(e),
+    };
+    drop_pending_mail(transaction, &email).await?;
+    Ok(())
+}
+
+/// Removes the issues and emails still waiting to be sent to an address.
+#[tracing::instrument(skip(transaction))]
+pub(crate) async fn drop_pending_mail(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE lower(subscriber_email) = lower($1)
+        "#,
+        email
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM email_outbox
+        WHERE lower(recipient) = lower($1)
+        "#,
+        email
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use crate::configuration::WebhookSettings;
+use crate::domain::SubscriptionStatus;
+use crate::email_events::{record_email_event, EmailEventKind, NewEmailEvent};
+use crate::routes::helpers::chain_error_fmt;
+use crate::routes::{
+    basic_authentication, change_subscription_status, drop_pending_mail, StatusChangeError,
+};
+use crate::suppression_list::{suppress_address, SuppressionReason};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+/// The fields we use out of Postmark's bounce and spam complaint webhooks.
+/// Other kinds of webhooks share `RecordType` and nothing else.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PostmarkEvent {
+    record_type: String,
+    #[serde(rename = "ID")]
+    id: Option<i64>,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    r#type: Option<String>,
+    email: Option<String>,
+    description: Option<String>,
+    /// Whether Postmark stopped sending to the address. It does so for hard
+    /// bounces and complaints, not for bounces that may go away on their own.
+    #[serde(default)]
+    inactive: bool,
+    bounced_at: Option<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication")]
+    AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    InvalidPayload(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        chain_error_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            WebhookError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            WebhookError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::new(self.status_code());
+        if let WebhookError::AuthError(_) = self {
+            let header_value = HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap();
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, header_value);
+        }
+        response
+    }
+}
+
+/// Both sides are hashed first, so that how long the comparison takes says
+/// nothing about the expected value.
+fn same_secret(a: &str, b: &str) -> bool {
+    Sha256::digest(a.as_bytes()) == Sha256::digest(b.as_bytes())
+}
+
+fn authenticate(request: &HttpRequest, settings: &WebhookSettings) -> Result<(), WebhookError> {
+    let credentials = basic_authentication(request.headers()).map_err(WebhookError::AuthError)?;
+    let username_matches = same_secret(&credentials.username, &settings.username);
+    let password_matches = same_secret(
+        credentials.password.expose_secret(),
+        settings.password.expose_secret(),
+    );
+    if username_matches && password_matches {
+        Ok(())
+    } else {
+        Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid webhook credentials."
+        )))
+    }
+}
+
+/// Records bounces and spam complaints. The subscription of a complaining
+/// address, or of one Postmark gave up on, ends there and then: nothing
+/// queued for it gets sent and the address goes on the suppression list.
+/// Webhooks we have no use for are acknowledged and ignored, so that
+/// Postmark doesn't retry them.
+#[tracing::instrument(
+    name = "Handle a Postmark webhook",
+    skip_all,
+    fields(record_type=tracing::field::Empty, subscriber_email=tracing::field::Empty)
+)]
+pub async fn postmark_webhook(
+    body: web::Bytes,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings: web::Data<WebhookSettings>,
+) -> Result<HttpResponse, WebhookError> {
+    authenticate(&request, &settings)?;
+    let event: PostmarkEvent = serde_json::from_slice(&body)
+        .context("The webhook payload is not valid JSON.")
+        .map_err(WebhookError::InvalidPayload)?;
+    tracing::Span::current().record("record_type", tracing::field::display(&event.record_type));
+    let kind = match event.record_type.as_str() {
+        "Bounce" => EmailEventKind::Bounce,
+        "SpamComplaint" => EmailEventKind::SpamComplaint,
+        _ => return Ok(HttpResponse::Ok().finish()),
+    };
+    let (postmark_id, email, bounced_at) = match (&event.id, &event.email, &event.bounced_at) {
+        (Some(id), Some(email), Some(bounced_at)) => (*id, email.as_str(), bounced_at),
+        _ => {
+            return Err(WebhookError::InvalidPayload(anyhow::anyhow!(
+                "The webhook payload lacks the ID, Email or BouncedAt fields."
+            )))
+        }
+    };
+    tracing::Span::current().record("subscriber_email", tracing::field::display(email));
+    let occurred_at = DateTime::parse_from_rfc3339(bounced_at)
+        .context("BouncedAt is not a valid timestamp.")
+        .map_err(WebhookError::InvalidPayload)?
+        .with_timezone(&Utc);
+    let new_status = match kind {
+        EmailEventKind::SpamComplaint => Some(SubscriptionStatus::Complained),
+        EmailEventKind::Bounce if event.inactive => Some(SubscriptionStatus::Bounced),
+        EmailEventKind::Bounce => None,
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let subscriber_id = sqlx::query!(
+        r#"
+        SELECT id
+        FROM subscriptions
+        WHERE lower(email) = lower($1)
+        "#,
+        email
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up the subscriber.")?
+    .map(|r| r.id);
+    let recorded = record_email_event(
+        &mut transaction,
+        &NewEmailEvent {
+            postmark_id,
+            kind,
+            email,
+            subscriber_id,
+            bounce_type: event.r#type.as_deref(),
+            description: event.description.as_deref(),
+            message_id: event.message_id.as_deref(),
+            occurred_at,
+        },
+    )
+    .await
+    .context("Failed to record the email event.")?;
+    if !recorded {
+        tracing::info!("The email event had already been recorded.");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    if let Some(new_status) = new_status {
+        if let Some(subscriber_id) = subscriber_id {
+            match change_subscription_status(&mut transaction, subscriber_id, new_status).await {
+                Ok(_) => {}
+                // E.g. a bounce for someone who had unsubscribed already.
+                Err(StatusChangeError::InvalidTransition(e)) => {
+                    tracing::info!("Kept the subscription as it was: {}", e)
+                }
+                Err(e) => {
+                    return Err(anyhow::Error::new(e)
+                        .context("Failed to update the subscription.")
+                        .into())
+                }
+            }
+        }
+        let reason = match kind {
+            EmailEventKind::Bounce => SuppressionReason::Bounce,
+            EmailEventKind::SpamComplaint => SuppressionReason::SpamComplaint,
+        };
+        let note = event.description.as_deref().filter(|d| !d.is_empty());
+        suppress_address(&mut *transaction, email, reason, note)
+            .await
+            .context("Failed to add the address to the suppression list.")?;
+        drop_pending_mail(&mut transaction, email)
+            .await
+            .context("Failed to drop the emails waiting to be sent.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,177 @@
+use crate::configuration::SpamProtectionSettings;
+use crate::domain::SubscriberEmail;
+use crate::routes::helpers::chain_error_fmt;
+use crate::startup::HmacSecret;
+use actix_web::HttpRequest;
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, Secret};
+use std::net::IpAddr;
+
+/// Signup form tokens older than this are refused, so that a token scraped
+/// once can't be replayed forever.
+pub const FORM_TOKEN_TTL_HOURS: i64 = 24;
+
+/// Why a subscription attempt was turned down. None of these are reported to
+/// the client: the attempt is logged and answered as if it had succeeded.
+#[derive(thiserror::Error)]
+pub enum SpamCheckError {
+    #[error("The honeypot field was filled in.")]
+    HoneypotFilled,
+    #[error("The form token is missing, forged or expired.")]
+    InvalidFormToken,
+    #[error("The form was submitted too soon after being rendered.")]
+    SubmittedTooFast,
+    #[error("Too many subscription attempts from this IP address.")]
+    TooManyFromIp,
+    #[error("Too many subscription attempts for this email domain.")]
+    TooManyForDomain,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SpamCheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        chain_error_fmt(self, f)
+    }
+}
+
+fn form_token_mac(hmac_secret: &HmacSecret, issued_at: i64) -> Hmac<sha2::Sha256> {
+    let mut mac =
+        Hmac::<sha2::Sha256>::new_from_slice(hmac_secret.0.expose_secret().as_bytes()).unwrap();
+    mac.update(format!("subscription_form:{issued_at}").as_bytes());
+    mac
+}
+
+/// Signup forms carry the time they were rendered at, signed with
+/// `HmacSecret` so that bots can't make it up: `<unix timestamp>.<tag>`.
+pub fn form_token(hmac_secret: &HmacSecret, issued_at: DateTime<Utc>) -> String {
+    let issued_at = issued_at.timestamp();
+    let tag = form_token_mac(hmac_secret, issued_at)
+        .finalize()
+        .into_bytes();
+    format!("{}.{}", issued_at, hex::encode(tag))
+}
+
+fn parse_form_token(hmac_secret: &HmacSecret, form_token: &str) -> Option<DateTime<Utc>> {
+    let (issued_at, tag) = form_token.split_once('.')?;
+    let issued_at: i64 = issued_at.parse().ok()?;
+    let tag = hex::decode(tag).ok()?;
+    form_token_mac(hmac_secret, issued_at)
+        .verify_slice(&tag)
+        .ok()?;
+    DateTime::from_timestamp(issued_at, 0)
+}
+
+pub struct SpamProtection {
+    settings: SpamProtectionSettings,
+    redis: ConnectionManager,
+}
+
+impl SpamProtection {
+    pub async fn new(
+        settings: SpamProtectionSettings,
+        redis_uri: &Secret<String>,
+    ) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri.expose_secret().as_str())?;
+        let redis = client.get_connection_manager().await?;
+        Ok(Self { settings, redis })
+    }
+
+    /// Checks the parts of the submission only a bot would get wrong: a
+    /// filled in honeypot, a missing or forged form token, or a form sent
+    /// faster than anyone could type.
+    pub fn check_form(
+        &self,
+        hmac_secret: &HmacSecret,
+        honeypot: &str,
+        form_token: Option<&str>,
+    ) -> Result<(), SpamCheckError> {
+        if !honeypot.is_empty() {
+            return Err(SpamCheckError::HoneypotFilled);
+        }
+        let issued_at = form_token
+            .and_then(|token| parse_form_token(hmac_secret, token))
+            .ok_or(SpamCheckError::InvalidFormToken)?;
+        let elapsed = Utc::now() - issued_at;
+        if elapsed > Duration::hours(FORM_TOKEN_TTL_HOURS) {
+            return Err(SpamCheckError::InvalidFormToken);
+        }
+        if elapsed < Duration::seconds(self.settings.min_submit_seconds) {
+            return Err(SpamCheckError::SubmittedTooFast);
+        }
+        Ok(())
+    }
+
+    /// The address rate limits are counted against: the peer's, unless the
+    /// peer is a trusted proxy. Then it is the rightmost `X-Forwarded-For`
+    /// entry that isn't a trusted proxy, since whatever is to its left was
+    /// sent by the client and can be made up.
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let mut client_ip = request.peer_addr()?.ip();
+        let forwarded_for: Vec<&str> = request
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        for hop in forwarded_for.into_iter().rev() {
+            if !self.settings.trusted_proxies.contains(&client_ip) {
+                break;
+            }
+            match hop.trim().parse() {
+                Ok(ip) => client_ip = ip,
+                Err(_) => break,
+            }
+        }
+        Some(client_ip)
+    }
+
+    /// Counts the attempt against both the client's IP address and the
+    /// domain of the address being subscribed, over a fixed window.
+    pub async fn check_rate_limits(
+        &self,
+        ip: &str,
+        email: &SubscriberEmail,
+    ) -> Result<(), SpamCheckError> {
+        let ip_attempts = self.record_attempt(&format!("subscribe:ip:{ip}")).await?;
+        if ip_attempts > self.settings.max_subscriptions_per_ip {
+            return Err(SpamCheckError::TooManyFromIp);
+        }
+        let domain = email
+            .as_ref()
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_lowercase())
+            .unwrap_or_default();
+        let domain_attempts = self
+            .record_attempt(&format!("subscribe:domain:{domain}"))
+            .await?;
+        if domain_attempts > self.settings.max_subscriptions_per_domain {
+            return Err(SpamCheckError::TooManyForDomain);
+        }
+        Ok(())
+    }
+
+    async fn record_attempt(&self, key: &str) -> Result<u64, anyhow::Error> {
+        let key = format!("{}{}", self.settings.redis_key_prefix, key);
+        let mut redis = self.redis.clone();
+        // Creating the key with its expiry first, in the same transaction
+        // as the increment, so that no key is ever left without one.
+        let (attempts,): (u64,) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(0)
+            .arg("EX")
+            .arg(self.settings.rate_limit_window_seconds)
+            .arg("NX")
+            .ignore()
+            .incr(&key, 1)
+            .query_async(&mut redis)
+            .await
+            .context("Failed to count a subscription attempt in Redis.")?;
+        Ok(attempts)
+    }
+}
@@ -1,10 +1,22 @@
 use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::configuration::{DatabaseSettings, Settings, SpamProtectionSettings, WebhookSettings};
 use crate::email_client::EmailClient;
+use crate::email_domain_rules::DomainRules;
 use crate::routes::{
-    admin_dashboard, api_publish_newsletter, change_password, change_password_form, confirm,
-    health_check, home, log_out, login, login_form, newsletter_form, publish_newsletter, subscribe,
+    add_domain_rule, add_subscriber, add_suppression, add_template, add_topic,
+    admin_confirm_subscriber, admin_dashboard, admin_erase_subscriber,
+    admin_unsubscribe_subscriber, api_publish_newsletter, archive, archived_issue, change_password,
+    change_password_form, confirm, confirm_email_change, confirm_email_change_form,
+    data_request_form, delete_subscriber, delete_suppression, delete_template, domain_rules_form,
+    download_data, erase_data, export_subscribers, health_check, home, import_details, import_form,
+    import_rejections_csv, import_subscribers, log_out, login, login_form, make_default_template,
+    manage_data, newsletter_form, postmark_webhook, preferences_form, preview_newsletter,
+    publish_newsletter, remove_domain_rule, request_data, resend_confirmation, subscribe,
+    subscriber_data_json, subscriber_details, subscribers_list, subscription_form_token,
+    suppressions_form, template_form, templates_list, topics_form, unsubscribe, unsubscribe_form,
+    update_preferences, update_template, update_welcome_email, welcome_email_form,
 };
+use crate::spam_protection::SpamProtection;
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
@@ -45,6 +57,10 @@ impl Application {
             configuration.application.base_url,
             configuration.application.hmac_secret,
             configuration.redis_uri,
+            configuration.application.confirmation_redirect_url,
+            configuration.spam_protection,
+            DomainRules::from_files(&configuration.domain_rules)?,
+            configuration.webhooks,
         )
         .await?;
 
@@ -63,10 +79,14 @@ impl Application {
 #[derive(Debug)]
 pub struct ApplicationBaseUrl(pub String);
 
+#[derive(Debug)]
+pub struct ConfirmationRedirectUrl(pub Option<String>);
+
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new().connect_lazy_with(configuration.with_db())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     tcp_listener: TcpListener,
     connection_pool: PgPool,
@@ -74,6 +94,10 @@ pub async fn run(
     base_url: String,
     hmac_secret: Secret<String>,
     redis_uri: Secret<String>,
+    confirmation_redirect_url: Option<String>,
+    spam_protection: SpamProtectionSettings,
+    domain_rules: DomainRules,
+    webhooks: WebhookSettings,
 ) -> Result<Server, anyhow::Error> {
     let connection_pool = web::Data::new(connection_pool);
     let email_client = web::Data::new(email_client);
@@ -83,8 +107,13 @@ pub async fn run(
     let message_framework = FlashMessagesFramework::builder(message_store).build();
 
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let spam_protection = web::Data::new(SpamProtection::new(spam_protection, &redis_uri).await?);
 
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let domain_rules = web::Data::new(domain_rules);
+    let webhooks = web::Data::new(webhooks);
+    let confirmation_redirect_url =
+        web::Data::new(ConfirmationRedirectUrl(confirmation_redirect_url));
     let address = tcp_listener.local_addr().expect("Can't get address");
     let server = HttpServer::new(move || {
         App::new()
@@ -99,8 +128,42 @@ pub async fn run(
             .route("/login", web::post().to(login))
             .route("/logout", web::post().to(log_out))
             .route("/health_check", web::get().to(health_check))
+            .route("/archive", web::get().to(archive))
+            .route("/archive/{issue_id}", web::get().to(archived_issue))
             .route("/subscriptions", web::post().to(subscribe))
+            .route(
+                "/subscriptions/form_token",
+                web::get().to(subscription_form_token),
+            )
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/resend", web::post().to(resend_confirmation))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(preferences_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(update_preferences),
+            )
+            .route(
+                "/subscriptions/preferences/confirm_email",
+                web::get().to(confirm_email_change_form),
+            )
+            .route(
+                "/subscriptions/preferences/confirm_email",
+                web::post().to(confirm_email_change),
+            )
+            .route("/subscriptions/data", web::get().to(data_request_form))
+            .route("/subscriptions/data", web::post().to(request_data))
+            .route("/subscriptions/data/manage", web::get().to(manage_data))
+            .route("/subscriptions/data/download", web::get().to(download_data))
+            .route("/subscriptions/data/erase", web::post().to(erase_data))
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
             .route("/newsletters", web::post().to(api_publish_newsletter))
             .service(
                 web::scope("/admin")
@@ -109,11 +172,72 @@ pub async fn run(
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
                     .route("/newsletters", web::get().to(newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter)),
+                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route("/newsletters/preview", web::post().to(preview_newsletter))
+                    .route("/welcome_email", web::get().to(welcome_email_form))
+                    .route("/welcome_email", web::post().to(update_welcome_email))
+                    .route("/subscribers", web::get().to(subscribers_list))
+                    .route("/subscribers", web::post().to(add_subscriber))
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(subscriber_details),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/confirm",
+                        web::post().to(admin_confirm_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/unsubscribe",
+                        web::post().to(admin_unsubscribe_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post().to(delete_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/erase",
+                        web::post().to(admin_erase_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/data",
+                        web::get().to(subscriber_data_json),
+                    )
+                    .route("/imports", web::get().to(import_form))
+                    .route("/imports", web::post().to(import_subscribers))
+                    .route("/imports/{import_id}", web::get().to(import_details))
+                    .route(
+                        "/imports/{import_id}/rejections.csv",
+                        web::get().to(import_rejections_csv),
+                    )
+                    .route("/topics", web::get().to(topics_form))
+                    .route("/topics", web::post().to(add_topic))
+                    .route("/domain_rules", web::get().to(domain_rules_form))
+                    .route("/domain_rules", web::post().to(add_domain_rule))
+                    .route("/domain_rules/delete", web::post().to(remove_domain_rule))
+                    .route("/suppressions", web::get().to(suppressions_form))
+                    .route("/suppressions", web::post().to(add_suppression))
+                    .route("/suppressions/delete", web::post().to(delete_suppression))
+                    .route("/templates", web::get().to(templates_list))
+                    .route("/templates", web::post().to(add_template))
+                    .route("/templates/{template_id}", web::get().to(template_form))
+                    .route("/templates/{template_id}", web::post().to(update_template))
+                    .route(
+                        "/templates/{template_id}/default",
+                        web::post().to(make_default_template),
+                    )
+                    .route(
+                        "/templates/{template_id}/delete",
+                        web::post().to(delete_template),
+                    ),
             )
             .app_data(connection_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(confirmation_redirect_url.clone())
+            .app_data(spam_protection.clone())
+            .app_data(domain_rules.clone())
+            .app_data(webhooks.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
     })
     .listen(tcp_listener)?
@@ -0,0 +1,500 @@
+//! Everything stored about a subscriber, for access and erasure requests.
+//!
+//! The `idempotency` table is keyed by admin user and only holds the responses
+//! to their own form submissions, so it never refers to subscribers.
+//!
+//! Erasing a subscriber leaves their address on the suppression list, if it
+//! is there: it is what keeps us from mailing someone who asked us not to.
+
+use crate::domain::SubscriptionStatus;
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// What audit log entries keep in place of the address of an erased
+/// subscriber. Their id stays, so that the history still adds up.
+pub const ERASED_EMAIL: &str = "[erased]";
+
+#[derive(serde::Serialize)]
+pub struct SubscriberData {
+    pub subscription: SubscriptionData,
+    pub confirmation_tokens: Vec<TokenData>,
+    pub email_change_requests: Vec<EmailChangeData>,
+    pub deliveries: Vec<DeliveryData>,
+    pub queued_issues: Vec<QueuedIssueData>,
+    pub queued_emails: Vec<QueuedEmailData>,
+    pub import_rejections: Vec<ImportRejectionData>,
+    pub email_events: Vec<EmailEventData>,
+    pub suppression: Option<SuppressionData>,
+    pub admin_actions: Vec<AdminActionData>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriptionData {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: SubscriptionStatus,
+    pub subscribed_at: String,
+    pub confirmed_at: Option<String>,
+    pub unsubscribed_at: Option<String>,
+    pub source_url: Option<String>,
+    pub referrer: Option<String>,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub utm_term: Option<String>,
+    pub utm_content: Option<String>,
+    pub consent_version: Option<String>,
+    pub topics: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct TokenData {
+    pub created_at: String,
+    pub expires_at: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct EmailChangeData {
+    pub new_email: String,
+    pub created_at: String,
+    pub expires_at: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct DeliveryData {
+    pub issue: String,
+    pub email: String,
+    pub outcome: String,
+    pub attempted_at: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct QueuedIssueData {
+    pub issue: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct QueuedEmailData {
+    pub subject: String,
+    pub created_at: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ImportRejectionData {
+    pub imported_at: String,
+    pub email: String,
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct EmailEventData {
+    pub kind: String,
+    pub email: String,
+    pub bounce_type: Option<String>,
+    pub description: Option<String>,
+    pub occurred_at: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct SuppressionData {
+    pub reason: String,
+    pub note: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct AdminActionData {
+    pub action: String,
+    pub performed_at: String,
+}
+
+fn format_timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// `None` if there is no such subscriber.
+#[tracing::instrument(skip(pool))]
+pub async fn get_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberData>, anyhow::Error> {
+    let subscription = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            email,
+            name,
+            status AS "status: SubscriptionStatus",
+            subscribed_at,
+            confirmed_at,
+            unsubscribed_at,
+            source_url,
+            referrer,
+            utm_source,
+            utm_medium,
+            utm_campaign,
+            utm_term,
+            utm_content,
+            consent_version,
+            ARRAY(
+                SELECT t.name
+                FROM subscription_topics st
+                JOIN topics t ON t.topic_id = st.topic_id
+                WHERE st.subscriber_id = s.id
+                ORDER BY t.name
+            ) AS "topics!"
+        FROM subscriptions s
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscription.")?;
+    let subscription = match subscription {
+        Some(r) => SubscriptionData {
+            id: r.id,
+            email: r.email,
+            name: r.name,
+            status: r.status,
+            subscribed_at: format_timestamp(r.subscribed_at),
+            confirmed_at: r.confirmed_at.map(format_timestamp),
+            unsubscribed_at: r.unsubscribed_at.map(format_timestamp),
+            source_url: r.source_url,
+            referrer: r.referrer,
+            utm_source: r.utm_source,
+            utm_medium: r.utm_medium,
+            utm_campaign: r.utm_campaign,
+            utm_term: r.utm_term,
+            utm_content: r.utm_content,
+            consent_version: r.consent_version,
+            topics: r.topics,
+        },
+        None => return Ok(None),
+    };
+
+    let confirmation_tokens = sqlx::query!(
+        r#"
+        SELECT created_at, expires_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        ORDER BY created_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the confirmation tokens.")?
+    .into_iter()
+    .map(|r| TokenData {
+        created_at: format_timestamp(r.created_at),
+        expires_at: format_timestamp(r.expires_at),
+    })
+    .collect();
+    let email_change_requests = sqlx::query!(
+        r#"
+        SELECT new_email, created_at, expires_at
+        FROM email_change_requests
+        WHERE subscriber_id = $1
+        ORDER BY created_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the email change requests.")?
+    .into_iter()
+    .map(|r| EmailChangeData {
+        new_email: r.new_email,
+        created_at: format_timestamp(r.created_at),
+        expires_at: format_timestamp(r.expires_at),
+    })
+    .collect();
+    let deliveries = sqlx::query!(
+        r#"
+        SELECT n.title, d.subscriber_email, d.outcome, d.attempted_at
+        FROM issue_deliveries d
+        JOIN newsletter_issues n ON n.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.attempted_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery history.")?
+    .into_iter()
+    .map(|r| DeliveryData {
+        issue: r.title,
+        email: r.subscriber_email,
+        outcome: r.outcome,
+        attempted_at: format_timestamp(r.attempted_at),
+    })
+    .collect();
+    let queued_issues = sqlx::query!(
+        r#"
+        SELECT n.title
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues n ON n.newsletter_issue_id = q.newsletter_issue_id
+        WHERE q.subscriber_email = $1
+        ORDER BY n.published_at
+        "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the queued issues.")?
+    .into_iter()
+    .map(|r| QueuedIssueData { issue: r.title })
+    .collect();
+    let queued_emails = sqlx::query!(
+        r#"
+        SELECT subject, created_at
+        FROM email_outbox
+        WHERE recipient = $1
+        ORDER BY created_at
+        "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the queued emails.")?
+    .into_iter()
+    .map(|r| QueuedEmailData {
+        subject: r.subject,
+        created_at: format_timestamp(r.created_at),
+    })
+    .collect();
+    let import_rejections = sqlx::query!(
+        r#"
+        SELECT i.created_at, r.email, r.name, r.reason
+        FROM subscriber_import_rejections r
+        JOIN subscriber_imports i ON i.import_id = r.import_id
+        WHERE lower(r.email) = lower($1)
+        ORDER BY i.created_at
+        "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the import rejections.")?
+    .into_iter()
+    .map(|r| ImportRejectionData {
+        imported_at: format_timestamp(r.created_at),
+        email: r.email,
+        name: r.name,
+        reason: r.reason,
+    })
+    .collect();
+    // Events about an address the subscriber used before are theirs too.
+    let email_events = sqlx::query!(
+        r#"
+        SELECT kind, email, bounce_type, description, occurred_at
+        FROM email_events
+        WHERE subscriber_id = $1 OR lower(email) = lower($2)
+        ORDER BY occurred_at
+        "#,
+        subscriber_id,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the bounces and complaints.")?
+    .into_iter()
+    .map(|r| EmailEventData {
+        kind: r.kind,
+        email: r.email,
+        bounce_type: r.bounce_type,
+        description: r.description,
+        occurred_at: format_timestamp(r.occurred_at),
+    })
+    .collect();
+    let suppression = sqlx::query!(
+        r#"
+        SELECT reason, note, created_at
+        FROM email_suppressions
+        WHERE email = lower($1)
+        "#,
+        subscription.email
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to check the suppression list.")?
+    .map(|r| SuppressionData {
+        reason: r.reason,
+        note: r.note,
+        created_at: format_timestamp(r.created_at),
+    });
+    // The admins' usernames are theirs, not the subscriber's.
+    let admin_actions = sqlx::query!(
+        r#"
+        SELECT action, performed_at
+        FROM admin_audit_log
+        WHERE subscriber_id = $1
+        ORDER BY performed_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the admin audit log.")?
+    .into_iter()
+    .map(|r| AdminActionData {
+        action: r.action,
+        performed_at: format_timestamp(r.performed_at),
+    })
+    .collect();
+
+    Ok(Some(SubscriberData {
+        subscription,
+        confirmation_tokens,
+        email_change_requests,
+        deliveries,
+        queued_issues,
+        queued_emails,
+        import_rejections,
+        email_events,
+        suppression,
+        admin_actions,
+    }))
+}
+
+/// Removes the subscriber along with their tokens and anything still waiting
+/// to be sent to them. Topics, delivery history and pending email changes go
+/// with the subscriber through `ON DELETE CASCADE`.
+#[tracing::instrument(skip(transaction, email))]
+pub async fn delete_subscriber_data(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the subscription tokens.")?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE subscriber_email = $1
+        "#,
+        email
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the queued deliveries.")?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM email_outbox
+        WHERE recipient = $1
+        "#,
+        email
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the queued emails.")?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the subscriber.")?;
+    Ok(())
+}
+
+/// Goes further than `delete_subscriber_data`: emails sent to a pending new
+/// address, rows of rejected imports and bounces and complaints are removed
+/// as well, and the address is blanked out of the admin audit log. Returns
+/// the erased address, or `None` if there is no such subscriber.
+#[tracing::instrument(skip(transaction))]
+pub async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT email
+        FROM subscriptions
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to lock the subscriber.")?;
+    let email = match subscriber {
+        Some(r) => r.email,
+        None => return Ok(None),
+    };
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM email_outbox
+        WHERE recipient IN (
+            SELECT new_email
+            FROM email_change_requests
+            WHERE subscriber_id = $1
+        )
+        "#,
+        subscriber_id
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the emails sent to a new address.")?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM email_events
+        WHERE subscriber_id = $1 OR lower(email) = lower($2)
+        "#,
+        subscriber_id,
+        email
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the bounces and complaints.")?;
+    delete_subscriber_data(transaction, subscriber_id, &email).await?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM subscriber_import_rejections
+        WHERE lower(email) = lower($1)
+        "#,
+        email
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the rejected import rows.")?;
+    let query = sqlx::query!(
+        r#"
+        UPDATE admin_audit_log
+        SET subscriber_email = $2
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+        ERASED_EMAIL
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to pseudonymize the admin audit log.")?;
+    Ok(Some(email))
+}
@@ -0,0 +1,469 @@
+use crate::domain::{SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::routes::{
+    generate_subscription_token, send_confirmation_email, store_subscription_token,
+};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::suppression_list::suppressed_among;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use csv_core::ReadRecordResult;
+use sqlx::{Executor, PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Rows are validated and written to the database this many at a time.
+pub const IMPORT_BATCH_SIZE: usize = 500;
+
+/// Splits CSV input into records as it arrives, so that uploads are never
+/// buffered whole.
+pub struct CsvRecords {
+    reader: csv_core::Reader,
+    output: Vec<u8>,
+    output_len: usize,
+    ends: Vec<usize>,
+    ends_len: usize,
+}
+
+impl Default for CsvRecords {
+    fn default() -> Self {
+        Self {
+            reader: csv_core::Reader::new(),
+            output: vec![0; 1024],
+            output_len: 0,
+            ends: vec![0; 16],
+            ends_len: 0,
+        }
+    }
+}
+
+impl CsvRecords {
+    /// The records completed by `chunk`.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Vec<String>> {
+        if chunk.is_empty() {
+            return Vec::new();
+        }
+        self.read(chunk)
+    }
+
+    /// The last record, if the input didn't end with a line break.
+    pub fn finish(&mut self) -> Vec<Vec<String>> {
+        self.read(&[])
+    }
+
+    fn read(&mut self, mut input: &[u8]) -> Vec<Vec<String>> {
+        let mut records = Vec::new();
+        loop {
+            let (result, n_in, n_out, n_ends) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[n_in..];
+            self.output_len += n_out;
+            self.ends_len += n_ends;
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return records,
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => records.push(self.take_record()),
+            }
+        }
+    }
+
+    fn take_record(&mut self) -> Vec<String> {
+        let mut start = 0;
+        let record = self.ends[..self.ends_len]
+            .iter()
+            .map(|&end| {
+                let field = String::from_utf8_lossy(&self.output[start..end]).into_owned();
+                start = end;
+                field
+            })
+            .collect();
+        self.output_len = 0;
+        self.ends_len = 0;
+        record
+    }
+}
+
+/// Where the `email` and `name` columns are, going by the header row.
+pub struct ImportColumns {
+    email: usize,
+    name: usize,
+}
+
+impl ImportColumns {
+    pub fn from_header(header: &[String]) -> Result<Self, String> {
+        let find = |column: &str| {
+            header
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(column))
+                .ok_or_else(|| format!("The CSV file has no {} column.", column))
+        };
+        Ok(Self {
+            email: find("email")?,
+            name: find("name")?,
+        })
+    }
+}
+
+pub struct ImportRow {
+    /// Counting the header as row 1.
+    pub row_number: i64,
+    pub email: String,
+    pub name: String,
+}
+
+impl ImportRow {
+    pub fn new(columns: &ImportColumns, row_number: i64, record: &[String]) -> Self {
+        let field = |i: usize| record.get(i).map(|f| f.trim().to_string());
+        Self {
+            row_number,
+            email: field(columns.email).unwrap_or_default(),
+            name: field(columns.name).unwrap_or_default(),
+        }
+    }
+}
+
+pub struct ImportSummary {
+    pub confirmed: bool,
+    pub n_imported: i32,
+    pub n_duplicates: i32,
+    pub n_rejected: i32,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+pub struct RecentImport {
+    pub import_id: Uuid,
+    pub username: String,
+    pub n_imported: i32,
+    pub n_rejected: i32,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+pub struct ImportRejection {
+    pub row_number: i64,
+    pub email: String,
+    pub name: String,
+    pub reason: String,
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn start_import(
+    pool: &PgPool,
+    user_id: Uuid,
+    confirmed: bool,
+) -> Result<Uuid, sqlx::Error> {
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_imports (import_id, user_id, confirmed, created_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        import_id,
+        user_id,
+        confirmed,
+    )
+    .execute(pool)
+    .await?;
+    Ok(import_id)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn finish_import(pool: &PgPool, import_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriber_imports
+        SET finished_at = now()
+        WHERE import_id = $1
+        "#,
+        import_id,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Validates and stores `rows` in a single transaction. Addresses that are
+/// already subscribed are skipped, invalid rows and suppressed addresses are
+/// kept for the report.
+/// Imported subscribers get every topic and, unless they are imported as
+/// confirmed, a confirmation email.
+#[tracing::instrument(skip(pool, base_url, hmac_secret, rows), fields(n_rows = rows.len()))]
+pub async fn import_batch(
+    pool: &PgPool,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+    import_id: Uuid,
+    confirmed: bool,
+    rows: Vec<ImportRow>,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let mut parsed = Vec::new();
+    let mut rejections = Vec::new();
+    for row in rows {
+        match SubscriberEmail::parse(row.email.clone())
+            .and_then(|email| SubscriberName::parse(row.name.clone()).map(|name| (email, name)))
+        {
+            Ok(subscriber) => parsed.push((row, subscriber)),
+            Err(reason) => rejections.push(ImportRejection {
+                row_number: row.row_number,
+                email: row.email,
+                name: row.name,
+                reason,
+            }),
+        }
+    }
+    // Checking the normalized addresses, as that is how they are suppressed.
+    let emails: Vec<String> = parsed
+        .iter()
+        .map(|(_, (email, _))| email.as_ref().to_owned())
+        .collect();
+    let suppressed = suppressed_among(&mut *transaction, &emails)
+        .await
+        .context("Failed to check the addresses against the suppression list.")?;
+    let mut subscribers = Vec::new();
+    for (row, (email, name)) in parsed {
+        if suppressed.contains(&email.as_ref().to_lowercase()) {
+            rejections.push(ImportRejection {
+                row_number: row.row_number,
+                email: row.email,
+                name: row.name,
+                reason: "The address is on the suppression list.".to_string(),
+            });
+        } else {
+            subscribers.push((email, name));
+        }
+    }
+
+    let inserted = insert_subscribers(&mut transaction, &subscribers, confirmed)
+        .await
+        .context("Failed to insert the imported subscribers.")?;
+    subscribe_to_all_topics(&mut transaction, &inserted)
+        .await
+        .context("Failed to subscribe the imported subscribers to topics.")?;
+    if !confirmed {
+        for (subscriber_id, email) in &inserted {
+            let subscription_token = generate_subscription_token();
+            store_subscription_token(&mut transaction, subscriber_id, &subscription_token)
+                .await
+                .context("Failed to store a subscription token.")?;
+            send_confirmation_email(
+                &mut transaction,
+                *subscriber_id,
+                email,
+                base_url,
+                hmac_secret,
+                &subscription_token,
+            )
+            .await
+                .context("Failed to enqueue a confirmation email.")?;
+        }
+    }
+    store_rejections(&mut transaction, import_id, &rejections)
+        .await
+        .context("Failed to store the rejected rows.")?;
+    let query = sqlx::query!(
+        r#"
+        UPDATE subscriber_imports
+        SET n_imported = n_imported + $2,
+            n_duplicates = n_duplicates + $3,
+            n_rejected = n_rejected + $4
+        WHERE import_id = $1
+        "#,
+        import_id,
+        inserted.len() as i32,
+        (subscribers.len() - inserted.len()) as i32,
+        rejections.len() as i32,
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to update the import counts.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")?;
+    Ok(())
+}
+
+/// Returns the subscribers that were inserted, i.e. whose address wasn't
+/// already taken, by an existing subscriber or earlier in the batch.
+async fn insert_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscribers: &[(SubscriberEmail, SubscriberName)],
+    confirmed: bool,
+) -> Result<Vec<(Uuid, SubscriberEmail)>, anyhow::Error> {
+    let ids: Vec<Uuid> = subscribers.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = subscribers
+        .iter()
+        .map(|(email, _)| email.as_ref().to_owned())
+        .collect();
+    let names: Vec<String> = subscribers
+        .iter()
+        .map(|(_, name)| name.as_ref().to_owned())
+        .collect();
+    let status = if confirmed {
+        SubscriptionStatus::Confirmed
+    } else {
+        SubscriptionStatus::PendingConfirmation
+    };
+    let rows = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, confirmed_at)
+        SELECT id, email, name, now(), $4::subscription_status, CASE WHEN $4 = 'confirmed' THEN now() END
+        FROM UNNEST($1::uuid[], $2::text[], $3::text[]) AS imported(id, email, name)
+        ON CONFLICT DO NOTHING
+        RETURNING id, email
+        "#,
+        &ids,
+        &emails,
+        &names,
+        status as SubscriptionStatus,
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    rows.into_iter()
+        .map(|row| {
+            let email = SubscriberEmail::parse(row.email).map_err(|e| anyhow::anyhow!(e))?;
+            Ok((row.id, email))
+        })
+        .collect()
+}
+
+async fn subscribe_to_all_topics(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscribers: &[(Uuid, SubscriberEmail)],
+) -> Result<(), sqlx::Error> {
+    let ids: Vec<Uuid> = subscribers.iter().map(|(id, _)| *id).collect();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO subscription_topics (subscriber_id, topic_id)
+        SELECT imported.id, topics.topic_id
+        FROM UNNEST($1::uuid[]) AS imported(id)
+        CROSS JOIN topics
+        "#,
+        &ids,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+async fn store_rejections(
+    transaction: &mut Transaction<'_, Postgres>,
+    import_id: Uuid,
+    rejections: &[ImportRejection],
+) -> Result<(), sqlx::Error> {
+    let row_numbers: Vec<i64> = rejections.iter().map(|r| r.row_number).collect();
+    let emails: Vec<String> = rejections.iter().map(|r| r.email.clone()).collect();
+    let names: Vec<String> = rejections.iter().map(|r| r.name.clone()).collect();
+    let reasons: Vec<String> = rejections.iter().map(|r| r.reason.clone()).collect();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO subscriber_import_rejections (import_id, row_number, email, name, reason)
+        SELECT $1, row_number, email, name, reason
+        FROM UNNEST($2::bigint[], $3::text[], $4::text[], $5::text[])
+            AS rejected(row_number, email, name, reason)
+        "#,
+        import_id,
+        &row_numbers,
+        &emails,
+        &names,
+        &reasons,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_recent_imports<'a>(
+    executor: impl PgExecutor<'a>,
+) -> Result<Vec<RecentImport>, sqlx::Error> {
+    sqlx::query_as!(
+        RecentImport,
+        r#"
+        SELECT i.import_id, u.username, i.n_imported, i.n_rejected, i.created_at, i.finished_at
+        FROM subscriber_imports i
+        JOIN users u ON u.user_id = i.user_id
+        ORDER BY i.created_at DESC
+        LIMIT 20
+        "#
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn get_import_summary<'a>(
+    executor: impl PgExecutor<'a>,
+    import_id: Uuid,
+) -> Result<Option<ImportSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        ImportSummary,
+        r#"
+        SELECT confirmed, n_imported, n_duplicates, n_rejected, created_at, finished_at
+        FROM subscriber_imports
+        WHERE import_id = $1
+        "#,
+        import_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn get_import_rejections<'a>(
+    executor: impl PgExecutor<'a>,
+    import_id: Uuid,
+) -> Result<Vec<ImportRejection>, sqlx::Error> {
+    sqlx::query_as!(
+        ImportRejection,
+        r#"
+        SELECT row_number, email, name, reason
+        FROM subscriber_import_rejections
+        WHERE import_id = $1
+        ORDER BY row_number
+        "#,
+        import_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsvRecords;
+
+    fn read_all(chunks: &[&str]) -> Vec<Vec<String>> {
+        let mut reader = CsvRecords::default();
+        let mut records = Vec::new();
+        for chunk in chunks {
+            records.extend(reader.push(chunk.as_bytes()));
+        }
+        records.extend(reader.finish());
+        records
+    }
+
+    #[test]
+    fn records_split_across_chunks_are_put_back_together() {
+        let records = read_all(&["email,na", "me\nursula@exa", "mple.com,Ursula\n"]);
+        assert_eq!(
+            records,
+            vec![
+                vec!["email".to_string(), "name".to_string()],
+                vec!["ursula@example.com".to_string(), "Ursula".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn the_last_record_does_not_need_a_line_break() {
+        let records = read_all(&["email,name\n", r#""le guin, ursula@example.com",Ursula"#]);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1][0], "le guin, ursula@example.com");
+    }
+}
@@ -0,0 +1,131 @@
+//! Addresses that must not receive anything from us anymore. Every path that
+//! sends email checks the list right before sending, and the paths that queue
+//! email for later skip suppressed addresses up front.
+
+use chrono::{DateTime, Utc};
+use sqlx::PgExecutor;
+use std::collections::HashSet;
+
+/// Why an address ended up on the suppression list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SuppressionReason {
+    /// The email provider gave up on the address.
+    Bounce,
+    SpamComplaint,
+    /// Added by an admin.
+    Manual,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Bounce => "bounce",
+            SuppressionReason::SpamComplaint => "spam_complaint",
+            SuppressionReason::Manual => "manual",
+        }
+    }
+}
+
+pub struct Suppression {
+    pub email: String,
+    pub reason: String,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn is_suppressed<'a>(
+    executor: impl PgExecutor<'a>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM email_suppressions
+            WHERE email = lower($1)
+        ) AS "suppressed!"
+        "#,
+        email
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(row.suppressed)
+}
+
+/// The lowercased addresses out of `emails` that are on the list.
+#[tracing::instrument(skip_all, fields(n_emails = emails.len()))]
+pub async fn suppressed_among<'a>(
+    executor: impl PgExecutor<'a>,
+    emails: &[String],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT email
+        FROM email_suppressions
+        WHERE email IN (SELECT lower(e) FROM UNNEST($1::text[]) AS e)
+        "#,
+        emails
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|r| r.email).collect())
+}
+
+/// An address already on the list keeps the reason it was first added for.
+/// Returns `false` in that case.
+#[tracing::instrument(skip(executor, note))]
+pub async fn suppress_address<'a>(
+    executor: impl PgExecutor<'a>,
+    email: &str,
+    reason: SuppressionReason,
+    note: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO email_suppressions (email, reason, note, created_at)
+        VALUES (lower($1), $2, $3, now())
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        email,
+        reason.as_str(),
+        note,
+    )
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Returns `false` if the address was not on the list.
+#[tracing::instrument(skip(executor))]
+pub async fn remove_suppression<'a>(
+    executor: impl PgExecutor<'a>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM email_suppressions
+        WHERE email = lower($1)
+        "#,
+        email
+    )
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn get_suppressions<'a>(
+    executor: impl PgExecutor<'a>,
+) -> Result<Vec<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email, reason, note, created_at
+        FROM email_suppressions
+        ORDER BY created_at DESC, email
+        "#
+    )
+    .fetch_all(executor)
+    .await
+}
@@ -0,0 +1,118 @@
+use sqlx::{Executor, PgExecutor, Postgres, Transaction};
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// A list subscribers opt into. Issues can be published to some topics only.
+pub struct Topic {
+    pub topic_id: Uuid,
+    pub name: String,
+    pub description: String,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_topics<'a>(executor: impl PgExecutor<'a>) -> Result<Vec<Topic>, sqlx::Error> {
+    let topics = sqlx::query_as!(
+        Topic,
+        r#"
+        SELECT topic_id, name, description
+        FROM topics
+        ORDER BY name
+        "#
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(topics)
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn get_subscriber_topics<'a>(
+    executor: impl PgExecutor<'a>,
+    subscriber_id: Uuid,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let topics = sqlx::query!(
+        r#"
+        SELECT topic_id
+        FROM subscription_topics
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|row| row.topic_id)
+    .collect();
+    Ok(topics)
+}
+
+#[tracing::instrument(skip(executor, description))]
+pub async fn insert_topic<'a>(
+    executor: impl PgExecutor<'a>,
+    name: &str,
+    description: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let topic_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO topics (topic_id, name, description)
+        VALUES ($1, $2, $3)
+        "#,
+        topic_id,
+        name,
+        description,
+    )
+    .execute(executor)
+    .await?;
+    Ok(topic_id)
+}
+
+/// Replaces the topics `subscriber_id` is subscribed to. Unknown topic ids
+/// are ignored, and choosing none at all subscribes to every topic.
+#[tracing::instrument(skip(transaction))]
+pub async fn set_subscriber_topics(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    topic_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM subscription_topics
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO subscription_topics (subscriber_id, topic_id)
+        SELECT $1, topic_id
+        FROM topics
+        WHERE cardinality($2::uuid[]) = 0 OR topic_id = ANY($2)
+        "#,
+        subscriber_id,
+        topic_ids,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Checkboxes for `topics`, ticked for the ones in `selected`.
+pub fn topic_checkboxes(topics: &[Topic], selected: &[Uuid]) -> String {
+    let mut html = String::new();
+    for topic in topics {
+        let checked = if selected.contains(&topic.topic_id) {
+            "checked"
+        } else {
+            ""
+        };
+        let _ = write!(
+            html,
+            r#"<div><label><input type="checkbox" name="topics" value="{}" {}/> {}</label> <i>{}</i></div>"#,
+            topic.topic_id,
+            checked,
+            htmlescape::encode_minimal(&topic.name),
+            htmlescape::encode_minimal(&topic.description),
+        );
+    }
+    html
+}
@@ -1,5 +1,10 @@
+use actix_web::dev::Payload;
+use actix_web::error::UrlencodedError;
 use actix_web::http::header::LOCATION;
-use actix_web::HttpResponse;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::pin::Pin;
 
 pub fn e500<T>(e: T) -> actix_web::Error
 where
@@ -20,3 +25,45 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+/// Like `web::Form`, except that repeated fields, e.g. a group of checkboxes,
+/// can be collected into a `Vec`.
+pub struct HtmlForm<T>(pub T);
+
+impl<T> FromRequest for HtmlForm<T>
+where
+    T: DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let is_form = req.content_type() == "application/x-www-form-urlencoded";
+        let body = web::Bytes::from_request(req, payload);
+        Box::pin(async move {
+            if !is_form {
+                return Err(UrlencodedError::ContentType.into());
+            }
+            let body = body.await?;
+            serde_html_form::from_bytes(&body)
+                .map(HtmlForm)
+                .map_err(e400)
+        })
+    }
+}
+
+/// Quotes `value` for a CSV file when it needs to be. Values a spreadsheet
+/// would evaluate as a formula are prefixed with `'`, since most of them
+/// come from subscribers.
+pub fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
@@ -0,0 +1,90 @@
+use crate::domain::SubscriberEmail;
+use crate::email_outbox::enqueue_email;
+use sqlx::{PgExecutor, Postgres, Transaction};
+
+/// The message sent to subscribers right after they confirm their
+/// subscription. There is a single one, editable from the admin area.
+pub struct WelcomeEmail {
+    pub enabled: bool,
+    pub subject: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_welcome_email<'a>(
+    executor: impl PgExecutor<'a>,
+) -> Result<WelcomeEmail, sqlx::Error> {
+    let welcome_email = sqlx::query_as!(
+        WelcomeEmail,
+        r#"
+        SELECT enabled, subject, text_content, html_content
+        FROM welcome_email
+        "#
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(welcome_email)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn update_welcome_email<'a>(
+    executor: impl PgExecutor<'a>,
+    welcome_email: &WelcomeEmail,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE welcome_email
+        SET
+            enabled = $1,
+            subject = $2,
+            text_content = $3,
+            html_content = $4
+        "#,
+        welcome_email.enabled,
+        welcome_email.subject,
+        welcome_email.text_content,
+        welcome_email.html_content,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Queues the welcome email for `recipient`, followed by links to the
+/// newsletter archive, the subscriber's preferences and unsubscribe pages.
+#[tracing::instrument(skip(transaction, archive_url, preferences_url, unsubscribe_url))]
+pub async fn enqueue_welcome_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipient: &SubscriberEmail,
+    archive_url: &str,
+    preferences_url: &str,
+    unsubscribe_url: &str,
+) -> Result<(), sqlx::Error> {
+    let welcome_email = get_welcome_email(&mut **transaction).await?;
+    if !welcome_email.enabled {
+        return Ok(());
+    }
+    let html_content = format!(
+        "{}<hr />\
+        <p><a href=\"{archive_url}\">Browse past issues</a> | \
+        <a href=\"{preferences_url}\">Manage your subscription</a> | \
+        <a href=\"{unsubscribe_url}\">Unsubscribe</a></p>",
+        welcome_email.html_content
+    );
+    let text_content = format!(
+        "{}\n\nBrowse past issues: {archive_url}\n\
+        Manage your subscription: {preferences_url}\n\
+        Unsubscribe: {unsubscribe_url}",
+        welcome_email.text_content
+    );
+    enqueue_email(
+        transaction,
+        recipient,
+        &welcome_email.subject,
+        &html_content,
+        &text_content,
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+async fn assert_domain_is_blocked(app: &TestApp, email: &str) {
+    let response = app
+        .post_subscriptions(format!("name=le%20guin&email={}", urlencoding::encode(email)))
+        .await;
+    assert_eq!(400, response.status().as_u16(), "{} was not blocked.", email);
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["field"], "email");
+    assert_eq!(error["code"], "blocked_domain");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_domain_rules() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_domain_rule(serde_json::json!({"domain": "example.com", "rule": "block"}))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn domains_from_the_blocklist_file_are_refused() {
+    let app = spawn_app().await;
+
+    assert_domain_is_blocked(&app, "ursula@mailinator.com").await;
+    assert_domain_is_blocked(&app, "ursula@eu.Mailinator.com").await;
+}
+
+#[tokio::test]
+async fn internationalized_domains_in_the_blocklist_file_are_refused() {
+    let blocklist_file = std::env::temp_dir().join(format!("{}.txt", Uuid::new_v4()));
+    std::fs::write(&blocklist_file, "Bücher.example\n").unwrap();
+    let app = spawn_app_with(|c| {
+        c.domain_rules.blocklist_file = Some(blocklist_file.to_str().unwrap().into())
+    })
+    .await;
+
+    assert_domain_is_blocked(&app, "ursula@bücher.example").await;
+    assert_domain_is_blocked(&app, "ursula@xn--bcher-kva.example").await;
+    std::fs::remove_file(blocklist_file).unwrap();
+}
+
+#[tokio::test]
+async fn domains_blocked_from_the_admin_area_are_refused() {
+    let app = spawn_app().await;
+    login(&app).await;
+
+    let response = app
+        .post_domain_rule(serde_json::json!({"domain": "Spam.Example", "rule": "block"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/domain_rules");
+
+    let html_page = app.get_domain_rules_html().await;
+    assert!(html_page.contains("The rule for spam.example has been saved."));
+    assert!(html_page.contains("spam.example (block)"));
+    assert_domain_is_blocked(&app, "ursula@spam.example").await;
+}
+
+#[tokio::test]
+async fn allowing_a_domain_overrides_the_blocklist_file() {
+    let app = spawn_app().await;
+    login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_domain_rule(serde_json::json!({"domain": "mailinator.com", "rule": "allow"}))
+        .await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40mailinator.com".into())
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn removed_rules_no_longer_apply() {
+    let app = spawn_app().await;
+    login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_domain_rule(serde_json::json!({"domain": "spam.example", "rule": "block"}))
+        .await;
+
+    let response = app
+        .post_remove_domain_rule(serde_json::json!({"domain": "spam.example"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/domain_rules");
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40spam.example".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+}
@@ -0,0 +1,244 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use std::fmt::Write;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::SubscriptionStatus;
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+fn import_path(response: &reqwest::Response) -> String {
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response.headers().get("Location").unwrap();
+    let location = location.to_str().unwrap().to_owned();
+    assert!(location.starts_with("/admin/imports/"), "{}", location);
+    location
+}
+
+async fn subscriber_statuses(app: &TestApp) -> Vec<(String, SubscriptionStatus)> {
+    sqlx::query!(
+        r#"SELECT email, status AS "status: SubscriptionStatus" FROM subscriptions ORDER BY email"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| (row.email, row.status))
+    .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_import("confirmed", "email,name\nursula@example.com,Ursula")
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(subscriber_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn valid_rows_are_imported_and_invalid_ones_reported() {
+    let app = spawn_app().await;
+    login(&app).await;
+    app.post_admin_subscriber(serde_json::json!({
+        "name": "Octavia",
+        "email": "octavia@example.com",
+        "status": "confirmed",
+    }))
+    .await;
+
+    let csv = "\
+id,Name,Email
+1,Ursula,ursula@example.com
+2,Octavia,OCTAVIA@example.com
+3,Ursula again,Ursula@Example.com
+4,Nobody,not-an-email
+5,,ted@example.com
+6,\"Chiang, Ted\",ted.chiang@example.com";
+    let response = app.post_import("confirmed", csv).await;
+    let import_path = import_path(&response);
+
+    assert_eq!(
+        subscriber_statuses(&app).await,
+        vec![
+            (
+                "octavia@example.com".to_string(),
+                SubscriptionStatus::Confirmed
+            ),
+            (
+                "ted.chiang@example.com".to_string(),
+                SubscriptionStatus::Confirmed
+            ),
+            (
+                "ursula@example.com".to_string(),
+                SubscriptionStatus::Confirmed
+            ),
+        ]
+    );
+    let html_page = app
+        .api_client
+        .get(format!("{}{}", app.address, import_path))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("2 subscribers imported"));
+    assert!(html_page.contains("2 rows skipped"));
+    assert!(html_page.contains("2 rows rejected"));
+
+    let report = app
+        .api_client
+        .get(format!("{}{}/rejections.csv", app.address, import_path))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        report.headers().get("Content-Type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+    let report = report.text().await.unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "row,email,name,reason");
+    assert!(lines[1].starts_with("5,not-an-email,Nobody,"));
+    assert!(lines[2].starts_with("6,ted@example.com,,"));
+    assert_eq!(lines.len(), 3);
+}
+
+#[tokio::test]
+async fn imported_subscribers_can_be_sent_a_confirmation_email() {
+    let app = spawn_app().await;
+    login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "email,name\nursula@example.com,Ursula\nocta@example.com,Octavia\n";
+    let response = app.post_import("send_confirmation", csv).await;
+    import_path(&response);
+    app.dispatch_all_pending_emails().await;
+
+    for (_, status) in subscriber_statuses(&app).await {
+        assert_eq!(status, SubscriptionStatus::PendingConfirmation);
+    }
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_not_imported() {
+    let app = spawn_app().await;
+    login(&app).await;
+    app.post_suppression(serde_json::json!({"email": "ursula@example.com"}))
+        .await;
+
+    let csv = "email,name\nocta@example.com,Octavia\nUrsula@example.com,Ursula\n";
+    let response = app.post_import("confirmed", csv).await;
+    let import_path = import_path(&response);
+
+    assert_eq!(
+        subscriber_statuses(&app).await,
+        vec![(
+            "octa@example.com".to_string(),
+            SubscriptionStatus::Confirmed
+        )]
+    );
+    let report = app
+        .api_client
+        .get(format!("{}{}/rejections.csv", app.address, import_path))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(report.contains("3,Ursula@example.com,Ursula,The address is on the suppression list."));
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_recognised_with_unicode_domains() {
+    let app = spawn_app().await;
+    login(&app).await;
+    app.post_suppression(serde_json::json!({"email": "ursula@xn--bcher-kva.de"}))
+        .await;
+
+    let csv = "email,name\nursula@bücher.de,Ursula\n";
+    let response = app.post_import("confirmed", csv).await;
+    let import_path = import_path(&response);
+
+    assert!(subscriber_statuses(&app).await.is_empty());
+    let report = app
+        .api_client
+        .get(format!("{}{}/rejections.csv", app.address, import_path))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(report.contains("2,ursula@bücher.de,Ursula,The address is on the suppression list."));
+}
+
+#[tokio::test]
+async fn large_files_are_imported_in_batches() {
+    let app = spawn_app().await;
+    login(&app).await;
+
+    let mut csv = String::from("email,name\n");
+    for i in 0..1234 {
+        let _ = writeln!(csv, "subscriber{}@example.com,Subscriber {}", i, i);
+    }
+    let response = app.post_import("confirmed", &csv).await;
+    import_path(&response);
+
+    let count = sqlx::query!("SELECT COUNT(*) as count FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count.count, Some(1234));
+    let import = sqlx::query!("SELECT n_imported, finished_at FROM subscriber_imports")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(import.n_imported, 1234);
+    assert!(import.finished_at.is_some());
+}
+
+#[tokio::test]
+async fn files_without_an_email_column_are_refused() {
+    let app = spawn_app().await;
+    login(&app).await;
+
+    let response = app
+        .post_import("confirmed", "address,name\nursula@example.com,Ursula")
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/imports");
+    let html_page = app
+        .api_client
+        .get(format!("{}/admin/imports", app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The CSV file has no email column."));
+    assert!(subscriber_statuses(&app).await.is_empty());
+}
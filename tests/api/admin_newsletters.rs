@@ -5,6 +5,7 @@ use crate::newsletter::{create_confirmed_subscriber, create_unconfirmed_subscrib
 
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::SubscriptionStatus;
 
 #[tokio::test]
 async fn can_not_get_access_without_authorization() {
@@ -249,3 +250,246 @@ async fn concurrent_from_submission_is_handled_gracefully() {
     assert_eq!(response1.status(), response2.status());
     app.dispatch_all_pending_emails().await;
 }
+
+#[tokio::test]
+async fn placeholders_are_filled_in_for_each_subscriber() {
+    let app = spawn_app().await;
+    let subscriber_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula@example.com', 'Ursula <Le Guin>', now(), $2)
+        "#,
+        subscriber_id,
+        SubscriptionStatus::Confirmed as SubscriptionStatus,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+
+    app.post_newsletter(serde_json::json!({
+        "title": "Rust Weekly",
+        "content_text": "Hi {{ subscriber.name }}, {{issue.title}} is online at {{ issue.web_url }}",
+        "content_html": "<p>Hi {{ subscriber.name }}, {{issue.title}} is online</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(
+        text_body.starts_with("Hi Ursula <Le Guin>, Rust Weekly is online at http://127.0.0.1:")
+    );
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("Hi Ursula &lt;Le Guin&gt;, Rust Weekly is online"));
+}
+
+#[tokio::test]
+async fn unknown_placeholders_are_refused() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "Rust Weekly",
+            "content_text": "Hi {{ subscriber.first_name }}",
+            "content_html": "<p>Hi</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let page_html = app.get_newsletter_html().await;
+    assert!(
+        page_html.contains("Text content: {{ subscriber.first_name }} is not a known placeholder.")
+    );
+    let issues = sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, Some(0));
+}
+
+#[tokio::test]
+async fn issues_can_be_previewed_with_sample_data() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+
+    let response = app
+        .post_newsletter_preview(serde_json::json!({
+            "title": "Rust Weekly",
+            "content_text": "Hi {{ subscriber.name }}",
+            "content_html": "<p>Hi {{ subscriber.name }}</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let page_html = response.text().await.unwrap();
+    // Wrapped in the default template.
+    assert!(page_html.contains("<pre>Hi Ursula Le Guin\n\nManage your subscription: "));
+    assert!(page_html.contains(&htmlescape::encode_attribute("<p>Hi Ursula Le Guin</p>")));
+    // The form keeps what was typed, placeholders included.
+    assert!(page_html.contains(&htmlescape::encode_attribute("Rust Weekly")));
+    assert!(page_html.contains("Hi {{ subscriber.name }}</textarea>"));
+    let issues = sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, Some(0));
+}
+
+#[tokio::test]
+async fn issues_can_be_written_in_markdown() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "Rust Weekly",
+            "content_markdown": "Hi {{ subscriber.name }}, some **news**.",
+            "content_text": "",
+            "content_html": "",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let issue =
+        sqlx::query!("SELECT text_content, html_content, content_markdown FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(issue.text_content, "Hi {{ subscriber.name }}, some news.\n");
+    assert_eq!(
+        issue.html_content,
+        "<p>Hi {{ subscriber.name }}, some <strong>news</strong>.</p>\n"
+    );
+    assert_eq!(
+        issue.content_markdown.as_deref(),
+        Some("Hi {{ subscriber.name }}, some **news**.")
+    );
+}
+
+#[tokio::test]
+async fn markdown_and_hand_written_content_cant_be_mixed() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "Rust Weekly",
+            "content_markdown": "Some **news**.",
+            "content_text": "Some news.",
+            "content_html": "",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let page_html = app.get_newsletter_html().await;
+    assert!(page_html.contains("Write the issue either in Markdown or as text and HTML, not both"));
+}
+
+#[tokio::test]
+async fn the_html_content_is_sanitized_and_its_css_inlined() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "Rust Weekly",
+            "content_text": "Some news.",
+            "content_html": "<style>p { color: red }</style><p>Some news.</p>\
+                <script>alert(1)</script><iframe src=\"https://example.com\"></iframe>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&htmlescape::encode_minimal(
+        "Removed from the HTML content, as mail clients block or mangle it: \
+        <script>, <iframe>."
+    )));
+    let html_content = sqlx::query_scalar!("SELECT html_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(html_content, "<p style=\"color: red;\">Some news.</p>");
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<p style=\"color: red;\">Some news.</p><hr />"));
+}
+
+#[tokio::test]
+async fn the_preview_warns_about_emails_gmail_would_clip() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+
+    let response = app
+        .post_newsletter_preview(serde_json::json!({
+            "title": "Rust Weekly",
+            "content_text": "Some news.",
+            "content_html": format!("<p>{}</p>", "Some news. ".repeat(10_000)),
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    let page_html = response.text().await.unwrap();
+    assert!(page_html.contains("Gmail clips emails above 102KB"));
+}
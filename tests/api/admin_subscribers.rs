@@ -0,0 +1,668 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::{create_confirmed_subscriber, create_unconfirmed_subscriber};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::SubscriptionStatus;
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+async fn insert_subscriber(
+    app: &TestApp,
+    email: &str,
+    name: &str,
+    status: SubscriptionStatus,
+    days_ago: i64,
+) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        email,
+        name,
+        Utc::now() - Duration::days(days_ago),
+        status as SubscriptionStatus,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+fn next_page_query(html_page: &str) -> Option<String> {
+    let start = html_page.find(r#"<a href="/admin/subscribers?"#)?;
+    let link = &html_page[start..];
+    let end = link.find(r#"">Next page</a>"#)?;
+    let href = &link[r#"<a href="/admin/subscribers?"#.len()..end];
+    Some(href.replace("&amp;", "&"))
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_subscribers("").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_or_name() {
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula",
+        SubscriptionStatus::Confirmed,
+        1,
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "octavia@example.com",
+        "Octavia Butler",
+        SubscriptionStatus::Confirmed,
+        1,
+    )
+    .await;
+    login(&app).await;
+
+    let html_page = app.get_admin_subscribers_html("q=URSULA").await;
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("octavia@example.com"));
+
+    let html_page = app.get_admin_subscribers_html("q=butler").await;
+    assert!(html_page.contains("octavia@example.com"));
+    assert!(!html_page.contains("ursula@example.com"));
+
+    // Wildcards are matched literally.
+    let html_page = app.get_admin_subscribers_html("q=%25").await;
+    assert!(!html_page.contains("@example.com"));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status_and_subscription_date() {
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "recent@example.com",
+        "a",
+        SubscriptionStatus::Confirmed,
+        1,
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "pending@example.com",
+        "b",
+        SubscriptionStatus::PendingConfirmation,
+        1,
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "old@example.com",
+        "c",
+        SubscriptionStatus::Confirmed,
+        30,
+    )
+    .await;
+    login(&app).await;
+
+    let html_page = app
+        .get_admin_subscribers_html("status=pending_confirmation")
+        .await;
+    assert!(html_page.contains("pending@example.com"));
+    assert!(!html_page.contains("recent@example.com"));
+
+    let from = (Utc::now() - Duration::days(7)).format("%Y-%m-%d");
+    let html_page = app
+        .get_admin_subscribers_html(&format!("status=confirmed&subscribed_from={}", from))
+        .await;
+    assert!(html_page.contains("recent@example.com"));
+    assert!(!html_page.contains("old@example.com"));
+    assert!(!html_page.contains("pending@example.com"));
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+    login(&app).await;
+
+    for query in ["status=spam", "subscribed_from=yesterday"] {
+        let response = app.get_admin_subscribers(query).await;
+        assert_eq!(response.status().as_u16(), 400, "{} was accepted", query);
+    }
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    let app = spawn_app().await;
+    for i in 0..60 {
+        insert_subscriber(
+            &app,
+            &format!("user{:02}@example.com", i),
+            "a",
+            SubscriptionStatus::Confirmed,
+            i,
+        )
+        .await;
+    }
+    login(&app).await;
+
+    let first_page = app.get_admin_subscribers_html("status=confirmed").await;
+    assert_eq!(first_page.matches("@example.com").count(), 50);
+    assert!(first_page.contains("user00@example.com"));
+    let query = next_page_query(&first_page).expect("No link to the next page");
+    assert!(query.contains("status=confirmed"));
+
+    let second_page = app.get_admin_subscribers_html(&query).await;
+    assert_eq!(second_page.matches("@example.com").count(), 10);
+    assert!(second_page.contains("user59@example.com"));
+    assert!(!second_page.contains("user49@example.com"));
+    assert!(next_page_query(&second_page).is_none());
+}
+
+#[tokio::test]
+async fn the_subscriber_page_shows_the_delivery_history() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    login(&app).await;
+
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("No issues delivered yet."));
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "Issue #1",
+            "content_text": "newsletter content",
+            "content_html": "<p>newsletter content</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.dispatch_all_pending_emails().await;
+
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("Issue #1"));
+    assert!(html_page.contains("<td>sent</td>"));
+}
+
+async fn audit_log_actions(app: &TestApp, subscriber_id: Uuid) -> Vec<String> {
+    sqlx::query!(
+        "SELECT action FROM admin_audit_log WHERE subscriber_id = $1 ORDER BY performed_at",
+        subscriber_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| row.action)
+    .collect()
+}
+
+async fn subscriber_status(app: &TestApp, subscriber_id: Uuid) -> Option<SubscriptionStatus> {
+    sqlx::query!(
+        r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+    .map(|row| row.status)
+}
+
+async fn subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_change_subscribers() {
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula",
+        SubscriptionStatus::Confirmed,
+        1,
+    )
+    .await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    let response = app
+        .post_admin_subscriber(serde_json::json!({
+            "name": "Octavia",
+            "email": "octavia@example.com",
+            "status": "confirmed",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    for action in ["confirm", "unsubscribe", "delete"] {
+        let response = app
+            .post_admin_subscriber_action(subscriber_id, action)
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await,
+        Some(SubscriptionStatus::Confirmed)
+    );
+}
+
+#[tokio::test]
+async fn admins_can_add_a_confirmed_subscriber_without_sending_an_email() {
+    let app = spawn_app().await;
+    login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_admin_subscriber(serde_json::json!({
+            "name": "Ursula",
+            "email": "ursula@example.com",
+            "status": "confirmed",
+        }))
+        .await;
+    let subscriber_id = subscriber_id(&app).await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await,
+        Some(SubscriptionStatus::Confirmed)
+    );
+    assert_eq!(
+        audit_log_actions(&app, subscriber_id).await,
+        vec!["create_subscriber"]
+    );
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("ursula@example.com has been added."));
+    assert!(html_page.contains(&format!("<td>{}</td>", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn admins_can_add_a_pending_subscriber_who_gets_a_confirmation_email() {
+    let app = spawn_app().await;
+    login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_admin_subscriber(serde_json::json!({
+        "name": "Ursula",
+        "email": "ursula@example.com",
+        "status": "pending_confirmation",
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    assert_eq!(confirmation_links.html.path(), "/subscriptions/confirm");
+    assert_eq!(
+        subscriber_status(&app, subscriber_id(&app).await).await,
+        Some(SubscriptionStatus::PendingConfirmation)
+    );
+}
+
+#[tokio::test]
+async fn adding_an_existing_subscriber_is_refused() {
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula",
+        SubscriptionStatus::Confirmed,
+        1,
+    )
+    .await;
+    login(&app).await;
+
+    let response = app
+        .post_admin_subscriber(serde_json::json!({
+            "name": "Ursula",
+            "email": "Ursula@Example.com",
+            "status": "confirmed",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_admin_subscribers_html("").await;
+    assert!(html_page.contains("Ursula@example.com is already subscribed."));
+}
+
+#[tokio::test]
+async fn admins_can_force_confirm_a_pending_subscriber() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    login(&app).await;
+
+    let response = app
+        .post_admin_subscriber_action(subscriber_id, "confirm")
+        .await;
+
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await,
+        Some(SubscriptionStatus::Confirmed)
+    );
+    assert_eq!(
+        audit_log_actions(&app, subscriber_id).await,
+        vec!["force_confirm"]
+    );
+}
+
+#[tokio::test]
+async fn admins_cannot_confirm_a_subscriber_who_complained() {
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula",
+        SubscriptionStatus::Complained,
+        1,
+    )
+    .await;
+    let subscriber_id = subscriber_id(&app).await;
+    login(&app).await;
+
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(!html_page.contains(&format!("/admin/subscribers/{}/confirm", subscriber_id)));
+    let response = app
+        .post_admin_subscriber_action(subscriber_id, "confirm")
+        .await;
+
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("A subscription can&#x27;t go from complained to confirmed."));
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await,
+        Some(SubscriptionStatus::Complained)
+    );
+    assert!(audit_log_actions(&app, subscriber_id).await.is_empty());
+}
+
+#[tokio::test]
+async fn admins_can_unsubscribe_a_subscriber() {
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula",
+        SubscriptionStatus::Confirmed,
+        1,
+    )
+    .await;
+    let subscriber_id = subscriber_id(&app).await;
+    login(&app).await;
+
+    app.post_admin_subscriber_action(subscriber_id, "unsubscribe")
+        .await;
+
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await,
+        Some(SubscriptionStatus::Unsubscribed)
+    );
+    assert_eq!(
+        audit_log_actions(&app, subscriber_id).await,
+        vec!["unsubscribe"]
+    );
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_removes_their_tokens_and_queued_emails() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    login(&app).await;
+    // An email still waiting in the outbox.
+    sqlx::query!(
+        r#"
+        INSERT INTO email_outbox (email_id, recipient, subject, text_content, html_content, created_at)
+        SELECT $1, email, 'Welcome!', '', '', now() FROM subscriptions
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_admin_subscriber_action(subscriber_id, "delete")
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    assert_eq!(subscriber_status(&app, subscriber_id).await, None);
+    let tokens = sqlx::query!("SELECT COUNT(*) as count FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.count, Some(0));
+    let emails = sqlx::query!("SELECT COUNT(*) as count FROM email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(emails.count, Some(0));
+    // The audit trail outlives the subscriber.
+    assert_eq!(audit_log_actions(&app, subscriber_id).await, vec!["delete"]);
+}
+
+#[tokio::test]
+async fn admins_can_download_everything_held_on_a_subscriber() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    login(&app).await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/subscribers/{}/data",
+            app.address, subscriber_id
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscription"]["id"], subscriber_id.to_string());
+    assert_eq!(data["confirmation_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        audit_log_actions(&app, subscriber_id).await,
+        vec!["export_data"]
+    );
+}
+
+#[tokio::test]
+async fn erasing_a_subscriber_blanks_their_address_in_the_audit_trail() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    login(&app).await;
+    app.post_admin_subscriber_action(subscriber_id, "confirm")
+        .await;
+
+    let response = app
+        .post_admin_subscriber_action(subscriber_id, "erase")
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    assert_eq!(subscriber_status(&app, subscriber_id).await, None);
+    assert_eq!(
+        audit_log_actions(&app, subscriber_id).await,
+        vec!["force_confirm", "erase"]
+    );
+    let emails = sqlx::query!(
+        "SELECT DISTINCT subscriber_email FROM admin_audit_log WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].subscriber_email, "[erased]");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_the_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_subscribers_export("format=csv").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_exported_as_csv() {
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "older@example.com",
+        "Chiang, Ted",
+        SubscriptionStatus::Confirmed,
+        10,
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "newer@example.com",
+        "=cmd()",
+        SubscriptionStatus::PendingConfirmation,
+        1,
+    )
+    .await;
+    login(&app).await;
+
+    let response = app.get_subscribers_export("format=csv").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "email,name,status,subscribed_at,confirmed_at,topics"
+    );
+    assert!(lines[1].starts_with(r#"older@example.com,"Chiang, Ted",confirmed,"#));
+    assert!(lines[2].starts_with("newer@example.com,'=cmd(),pending_confirmation,"));
+    assert_eq!(lines.len(), 3);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_exported_as_ndjson_with_the_list_filters() {
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula",
+        SubscriptionStatus::Confirmed,
+        1,
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "octavia@example.com",
+        "Octavia",
+        SubscriptionStatus::Unsubscribed,
+        1,
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "old@example.com",
+        "Ursula Old",
+        SubscriptionStatus::Confirmed,
+        30,
+    )
+    .await;
+    login(&app).await;
+    let from = (Utc::now() - Duration::days(7)).format("%Y-%m-%d");
+
+    let response = app
+        .get_subscribers_export(&format!(
+            "format=ndjson&q=ursula&status=confirmed&subscribed_from={}",
+            from
+        ))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/x-ndjson"
+    );
+    let body = response.text().await.unwrap();
+    let rows: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["email"], "ursula@example.com");
+    assert_eq!(rows[0]["status"], "confirmed");
+    assert!(rows[0]["topics"].is_array());
+}
+
+#[tokio::test]
+async fn large_exports_are_streamed_in_full() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT gen_random_uuid(), 'subscriber' || i || '@example.com', 'Subscriber ' || i, now(), 'confirmed'
+        FROM generate_series(1, 2000) AS i
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    login(&app).await;
+
+    let body = app
+        .get_subscribers_export("format=ndjson")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(body.lines().count(), 2000);
+}
+
+#[tokio::test]
+async fn exporting_with_an_unknown_format_is_rejected() {
+    let app = spawn_app().await;
+    login(&app).await;
+
+    let response = app.get_subscribers_export("format=xlsx").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
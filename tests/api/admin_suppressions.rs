@@ -0,0 +1,179 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::SubscriptionStatus;
+use zero2prod::suppression_list::{suppress_address, SuppressionReason};
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+async fn insert_confirmed_subscriber(app: &TestApp) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula@example.com', 'Ursula', now(), $2)
+        "#,
+        subscriber_id,
+        SubscriptionStatus::Confirmed as SubscriptionStatus,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+fn newsletter() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_suppressions() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_suppression(serde_json::json!({"email": "ursula@example.com"}))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_listed_and_can_be_removed() {
+    let app = spawn_app().await;
+    login(&app).await;
+
+    let response = app
+        .post_suppression(serde_json::json!({
+            "email": "Ursula@Example.com",
+            "note": "Asked by phone",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("Ursula@example.com has been suppressed."));
+    assert!(html_page.contains("<td>ursula@example.com</td><td>manual</td><td>Asked by phone</td>"));
+
+    app.post_suppression(serde_json::json!({"email": "ursula@example.com"}))
+        .await;
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("ursula@example.com was already suppressed."));
+
+    let response = app
+        .post_remove_suppression(serde_json::json!({"email": "ursula@example.com"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("ursula@example.com is no longer suppressed."));
+    assert!(html_page.contains("No suppressed addresses."));
+}
+
+#[tokio::test]
+async fn invalid_addresses_are_not_suppressed() {
+    let app = spawn_app().await;
+    login(&app).await;
+
+    app.post_suppression(serde_json::json!({"email": "not-an-email"}))
+        .await;
+
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("not-an-email is not valid email"));
+    assert!(html_page.contains("No suppressed addresses."));
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_not_sent_a_confirmation_email() {
+    let app = spawn_app().await;
+    suppress_address(
+        &app.db_pool,
+        "ursula@example.com",
+        SuppressionReason::SpamComplaint,
+        None,
+    )
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=Ursula&email=Ursula%40example.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_left_out_of_new_issues() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app).await;
+    suppress_address(
+        &app.db_pool,
+        "ursula@example.com",
+        SuppressionReason::Manual,
+        None,
+    )
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.publish_newsletter(newsletter()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn issues_queued_before_the_address_was_suppressed_are_not_sent() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    login(&app).await;
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content_text": "Newsletter body as plain text",
+        "content_html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    suppress_address(
+        &app.db_pool,
+        "ursula@example.com",
+        SuppressionReason::Bounce,
+        None,
+    )
+    .await
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let outcome = sqlx::query!("SELECT outcome FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .outcome;
+    assert_eq!(outcome, "suppressed");
+}
@@ -0,0 +1,264 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::SubscriptionStatus;
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+async fn template_id(app: &TestApp, name: &str) -> Uuid {
+    sqlx::query_scalar!(
+        "SELECT template_id FROM email_templates WHERE name = $1",
+        name
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+fn layouts(postal_address: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": "Branded",
+        "postal_address": postal_address,
+        "html_layout": "<h1>Rust Weekly</h1>{{ content }}\
+            <footer><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a> {{ postal_address }}</footer>",
+        "text_layout": "{{ content }}\n--\nUnsubscribe: {{ unsubscribe_url }}\n{{ postal_address }}",
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_templates() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_template("", serde_json::json!({"name": "Branded"}))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn templates_can_be_added_and_edited() {
+    let app = spawn_app().await;
+    login(&app).await;
+
+    let response = app
+        .post_template("", serde_json::json!({"name": "Branded"}))
+        .await;
+    let template_id = template_id(&app, "Branded").await;
+    assert_is_redirect_to(&response, &format!("/admin/templates/{}", template_id));
+    let html_page = app.get_template_html(template_id).await;
+    assert!(html_page.contains("The template Branded has been added."));
+
+    let response = app
+        .post_template(
+            &format!("/{}", template_id),
+            layouts("1 Main Street, Springfield"),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/templates/{}", template_id));
+    let html_page = app.get_template_html(template_id).await;
+    assert!(html_page.contains("The template Branded has been saved."));
+    assert!(html_page.contains(&htmlescape::encode_attribute("1 Main Street, Springfield")));
+    let html_page = app.get_templates_html().await;
+    assert!(html_page.contains("<td>1 Main Street, Springfield</td>"));
+}
+
+#[tokio::test]
+async fn layouts_must_have_a_content_slot_and_a_footer() {
+    let app = spawn_app().await;
+    login(&app).await;
+    let template_id = template_id(&app, "Default").await;
+    let test_cases = [
+        (
+            "<p>{{ unsubscribe_url }} {{ postal_address }}</p>",
+            "HTML layout: The layout must have exactly one {{ content }} slot.",
+        ),
+        (
+            "{{ content }}<p>{{ postal_address }}</p>",
+            "HTML layout: The layout must have a {{ unsubscribe_url }} in its footer.",
+        ),
+        (
+            "{{ content }}<p>{{ unsubscribe_url }}</p>",
+            "HTML layout: The layout must have a {{ postal_address }} in its footer.",
+        ),
+        (
+            "{{ content }} {{ unsubscribe_url }} {{ postal_adress }}",
+            "HTML layout: {{ postal_adress }} is not a known placeholder.",
+        ),
+    ];
+
+    for (html_layout, error_message) in test_cases {
+        let mut body = layouts("1 Main Street");
+        body["html_layout"] = html_layout.into();
+        let response = app.post_template(&format!("/{}", template_id), body).await;
+        assert_is_redirect_to(&response, &format!("/admin/templates/{}", template_id));
+        let html_page = app.get_template_html(template_id).await;
+        assert!(
+            html_page.contains(&htmlescape::encode_minimal(error_message)),
+            "The layout was not refused with `{}`.",
+            error_message
+        );
+    }
+    let postal_address = sqlx::query_scalar!(
+        "SELECT postal_address FROM email_templates WHERE template_id = $1",
+        template_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(postal_address, "");
+}
+
+#[tokio::test]
+async fn the_default_template_cannot_be_deleted() {
+    let app = spawn_app().await;
+    login(&app).await;
+    app.post_template("", serde_json::json!({"name": "Branded"}))
+        .await;
+    let default_id = template_id(&app, "Default").await;
+    let branded_id = template_id(&app, "Branded").await;
+
+    let response = app
+        .post_template(&format!("/{}/delete", default_id), serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/templates");
+    let html_page = app.get_templates_html().await;
+    assert!(html_page.contains("The default template can&#x27;t be deleted."));
+
+    app.post_template(&format!("/{}/default", branded_id), serde_json::json!({}))
+        .await;
+    let html_page = app.get_templates_html().await;
+    assert!(html_page.contains("Branded is now the default template."));
+    app.post_template(&format!("/{}/delete", default_id), serde_json::json!({}))
+        .await;
+    let html_page = app.get_templates_html().await;
+    assert!(html_page.contains("The template Default has been deleted."));
+    let n_templates = sqlx::query_scalar!("SELECT COUNT(*) FROM email_templates")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_templates, Some(1));
+}
+
+#[tokio::test]
+async fn issues_are_wrapped_in_the_template_they_were_sent_with() {
+    let app = spawn_app().await;
+    login(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula@example.com', 'Ursula', now(), $2)
+        "#,
+        Uuid::new_v4(),
+        SubscriptionStatus::Confirmed as SubscriptionStatus,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.post_template("", serde_json::json!({"name": "Branded"}))
+        .await;
+    let template_id = template_id(&app, "Branded").await;
+    app.post_template(&format!("/{}", template_id), layouts("1 Main Street"))
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletter(serde_json::json!({
+        "title": "Rust Weekly",
+        "content_text": "Some news.",
+        "content_html": "<p>Some news.</p>",
+        "template_id": template_id,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<h1>Rust Weekly</h1><p>Some news.</p><footer>"));
+    assert!(html_body.ends_with("</a> 1 Main Street</footer>"));
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with("Some news.\n--\nUnsubscribe: http://127.0.0.1:"));
+    assert!(text_body.ends_with("\n1 Main Street"));
+}
+
+#[tokio::test]
+async fn issues_published_through_the_api_are_wrapped_in_the_default_template() {
+    let app = spawn_app().await;
+    login(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula@example.com', 'Ursula', now(), $2)
+        "#,
+        Uuid::new_v4(),
+        SubscriptionStatus::Confirmed as SubscriptionStatus,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let template_id = template_id(&app, "Default").await;
+    app.post_template(&format!("/{}", template_id), layouts("1 Main Street"))
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.publish_newsletter(serde_json::json!({
+        "title": "Rust Weekly",
+        "content": {"text": "Some news.", "html": "<p>Some news.</p>"}
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<h1>Rust Weekly</h1><p>Some news.</p><footer>"));
+    assert!(html_body.contains("/subscriptions/unsubscribe?subscriber_id="));
+    assert!(html_body.ends_with("</a> 1 Main Street</footer>"));
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with("Some news.\n--\nUnsubscribe: http://127.0.0.1:"));
+    assert!(text_body.ends_with("\n1 Main Street"));
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_wrapped_in_the_default_template() {
+    let app = spawn_app().await;
+    login(&app).await;
+    let template_id = template_id(&app, "Default").await;
+    app.post_template(&format!("/{}", template_id), layouts("1 Main Street"))
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<h1>Rust Weekly</h1>Welcome to our newsletter!"));
+    assert!(html_body.contains("/subscriptions/unsubscribe?subscriber_id="));
+    assert!(html_body.ends_with("1 Main Street</footer>"));
+    let confirmation_links = app.get_confirmation_links(email_request);
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+}
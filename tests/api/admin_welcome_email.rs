@@ -0,0 +1,77 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_edit_the_welcome_email() {
+    let app = spawn_app().await;
+
+    let response = app.get_welcome_email().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_welcome_email_form_shows_the_current_message() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+
+    let html_page = app.get_welcome_email_html().await;
+
+    assert!(html_page.contains("Thanks for confirming your subscription!"));
+}
+
+#[tokio::test]
+async fn the_welcome_email_can_be_updated() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+
+    let response = app
+        .post_welcome_email(serde_json::json!({
+            "subject": "Hello there",
+            "content_text": "Thanks for joining",
+            "content_html": "<p>Thanks for joining</p>",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/welcome_email");
+
+    let html_page = app.get_welcome_email_html().await;
+    assert!(html_page.contains("The welcome email has been saved."));
+    let saved = sqlx::query!("SELECT enabled, subject, text_content, html_content FROM welcome_email")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(!saved.enabled);
+    assert_eq!(saved.subject, "Hello there");
+    assert_eq!(saved.text_content, "Thanks for joining");
+    assert_eq!(saved.html_content, "<p>Thanks for joining</p>");
+}
+
+#[tokio::test]
+async fn the_welcome_email_subject_is_required() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+
+    let response = app
+        .post_welcome_email(serde_json::json!({
+            "enabled": "on",
+            "subject": "",
+            "content_text": "Thanks for joining",
+            "content_html": "<p>Thanks for joining</p>",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/welcome_email");
+
+    let html_page = app.get_welcome_email_html().await;
+    assert!(html_page.contains("Subject is required field"));
+}
@@ -0,0 +1,118 @@
+use crate::helpers::spawn_app;
+use uuid::Uuid;
+use zero2prod::topics::insert_topic;
+
+#[tokio::test]
+async fn the_archive_lists_published_issues() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+    app.post_newsletter(serde_json::json!({
+        "title": "Our very first issue",
+        "content_text": "newsletter content",
+        "content_html": "<p>newsletter content</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    let response = reqwest::get(format!("{}/archive", &app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Our very first issue"));
+}
+
+#[tokio::test]
+async fn an_unknown_archived_issue_returns_404() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/archive/{}", &app.address, Uuid::new_v4()))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn archived_issues_leave_subscriber_placeholders_blank() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+    app.post_newsletter(serde_json::json!({
+        "title": "Our very first issue",
+        "content_text": "Welcome to {{ issue.title }}, {{ subscriber.name }}!",
+        "content_html": "<p>Welcome to {{ issue.title }}, {{ subscriber.name }}!</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    let response = reqwest::get(format!("{}/archive/{}", &app.address, issue_id))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Welcome to Our very first issue, !"));
+}
+
+#[tokio::test]
+async fn issues_sent_to_some_topics_only_are_not_archived() {
+    let app = spawn_app().await;
+    let rust = insert_topic(&app.db_pool, "Rust", "").await.unwrap();
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+    let body = serde_html_form::to_string(serde_json::json!({
+        "title": "Rust only",
+        "content_text": "newsletter content",
+        "content_html": "<p>newsletter content</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "topics": [rust],
+    }))
+    .unwrap();
+    app.api_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    let html_page = reqwest::get(format!("{}/archive", &app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(!html_page.contains("Rust only"));
+    let response = reqwest::get(format!("{}/archive/{}", &app.address, issue_id))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
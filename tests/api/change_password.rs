@@ -108,7 +108,7 @@ async fn new_password_is_to_short() {
 async fn new_password_is_to_long() {
     let app = spawn_app().await;
     let new_password = (0..130)
-        .map(|_| ((rand::random::<u8>() % 26) + 'a' as u8) as char)
+        .map(|_| ((rand::random::<u8>() % 26) + b'a') as char)
         .collect::<String>();
 
     app.post_login(&serde_json::json!({
@@ -1,14 +1,18 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use chrono::{Duration, Utc};
 use once_cell::sync::Lazy;
+use secrecy::ExposeSecret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
 use wiremock::MockServer;
 
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::configuration::{get_configuration, DatabaseSettings, Settings, WebhookSettings};
 use zero2prod::email_client::EmailClient;
 use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
-use zero2prod::startup::{get_connection_pool, Application};
+use zero2prod::routes::{data_link, preferences_link, unsubscribe_link};
+use zero2prod::spam_protection::form_token;
+use zero2prod::startup::{get_connection_pool, Application, ApplicationBaseUrl, HmacSecret};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
@@ -34,12 +38,71 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub hmac_secret: HmacSecret,
+    pub webhooks: WebhookSettings,
 }
 
 impl TestApp {
+    /// Posts the signup form the way a person would, with a form token old
+    /// enough to pass the submit-time check.
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
+        self.post_subscriptions_raw(format!("{}&form_token={}", body, self.form_token()))
+            .await
+    }
+
+    pub async fn post_subscriptions_raw(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        let mut body = body.clone();
+        body["form_token"] = self.form_token().into();
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub fn form_token(&self) -> String {
+        form_token(&self.hmac_secret, Utc::now() - Duration::minutes(1))
+    }
+
+    pub async fn post_resend_confirmation(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/resend", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_data_request(&self, body: String) -> reqwest::Response {
+        self.post_data_request_raw(format!("{}&form_token={}", body, self.form_token()))
+            .await
+    }
+
+    pub async fn post_data_request_raw(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/data", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_data_erasure(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions/data/erase", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -49,7 +112,7 @@ impl TestApp {
 
     pub async fn publish_newsletter(&self, body: serde_json::Value) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/newsletters", &self.address))
+            .post(format!("{}/newsletters", &self.address))
             .basic_auth(
                 self.test_user.username.clone(),
                 Some(self.test_user.password.clone()),
@@ -59,6 +122,23 @@ impl TestApp {
             .await
             .expect("Failed to execute request")
     }
+
+    /// Posts a webhook payload the way Postmark would, with the configured
+    /// credentials.
+    pub async fn post_postmark_webhook(&self, body: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth(
+                &self.webhooks.username,
+                Some(self.webhooks.password.expose_secret()),
+            )
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -66,6 +146,11 @@ impl TestApp {
             let links: Vec<_> = linkify::LinkFinder::new()
                 .links(s)
                 .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                // Confirmation emails have the layout's footer links too.
+                .filter(|l| {
+                    !l.as_str().contains("/subscriptions/unsubscribe?")
+                        && !l.as_str().contains("/subscriptions/preferences?")
+                })
                 .collect();
             assert_eq!(links.len(), 1);
             let raw_confirmation_link = links[0].as_str().to_owned();
@@ -74,17 +159,75 @@ impl TestApp {
             confirmation_link.set_port(Some(self.port)).unwrap();
             confirmation_link
         };
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 
+    pub fn get_unsubscribe_link(&self, subscriber_id: Uuid) -> reqwest::Url {
+        let base_url = ApplicationBaseUrl(self.address.clone());
+        reqwest::Url::parse(&unsubscribe_link(&base_url, &self.hmac_secret, subscriber_id))
+            .unwrap()
+    }
+
+    pub fn get_preferences_link(&self, subscriber_id: Uuid) -> reqwest::Url {
+        let base_url = ApplicationBaseUrl(self.address.clone());
+        reqwest::Url::parse(&preferences_link(&base_url, &self.hmac_secret, subscriber_id))
+            .unwrap()
+    }
+
+    pub fn get_data_link(&self, subscriber_id: Uuid, expires: i64) -> reqwest::Url {
+        let base_url = ApplicationBaseUrl(self.address.clone());
+        reqwest::Url::parse(&data_link(
+            &base_url,
+            &self.hmac_secret,
+            subscriber_id,
+            expires,
+        ))
+        .unwrap()
+    }
+
+    /// Takes an urlencoded body, so that `topics` can be repeated.
+    pub async fn post_preferences(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/preferences", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_confirm_email_change(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/subscriptions/preferences/confirm_email",
+                &self.address
+            ))
+            .form(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_unsubscribe<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions/unsubscribe", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -93,7 +236,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -104,7 +247,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -120,7 +263,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -131,7 +274,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
@@ -144,7 +287,7 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/logout", &self.address))
+            .post(format!("{}/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -152,7 +295,7 @@ impl TestApp {
 
     pub async fn get_newsletters(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -171,27 +314,275 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_newsletter_preview<Body>(&self, body: Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/preview", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_welcome_email(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/welcome_email", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_welcome_email_html(&self) -> String {
+        self.get_welcome_email()
+            .await
+            .text()
+            .await
+            .expect("Failed to parse response body")
+    }
+
+    pub async fn post_welcome_email<Body>(&self, body: Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/welcome_email", &self.address))
             .form(&body)
             .send()
             .await
             .expect("Failed to execute request")
     }
 
+    pub async fn get_domain_rules_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/domain_rules", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .expect("Failed to parse response body")
+    }
+
+    pub async fn post_domain_rule<Body>(&self, body: Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/domain_rules", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_remove_domain_rule<Body>(&self, body: Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/domain_rules/delete", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_suppressions_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/suppressions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .expect("Failed to parse response body")
+    }
+
+    pub async fn post_suppression<Body>(&self, body: Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/suppressions", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_remove_suppression<Body>(&self, body: Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/suppressions/delete", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_templates_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/templates", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .expect("Failed to parse response body")
+    }
+
+    pub async fn get_template_html(&self, template_id: Uuid) -> String {
+        self.api_client
+            .get(format!("{}/admin/templates/{}", &self.address, template_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .expect("Failed to parse response body")
+    }
+
+    /// `path` is relative to `/admin/templates`, e.g. `/{id}/default`.
+    pub async fn post_template<Body>(&self, path: &str, body: Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/templates{}", &self.address, path))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// `query` is appended as is, e.g. `q=ursula&status=confirmed`.
+    pub async fn get_admin_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_admin_subscribers_html(&self, query: &str) -> String {
+        self.get_admin_subscribers(query)
+            .await
+            .text()
+            .await
+            .expect("Failed to read text content")
+    }
+
+    pub async fn get_subscribers_export(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers/export?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_admin_subscriber<Body>(&self, body: Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/subscribers", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// `action` is one of `confirm`, `unsubscribe` or `delete`.
+    pub async fn post_admin_subscriber_action(
+        &self,
+        subscriber_id: Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/{}",
+                &self.address, subscriber_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Uploads `csv` to the import form. `mode` is `confirmed` or
+    /// `send_confirmation`.
+    pub async fn post_import(&self, mode: &str, csv: &str) -> reqwest::Response {
+        let boundary = "----zero2prod-test-boundary";
+        let body = format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"mode\"\r\n\r\n\
+            {mode}\r\n\
+            --{boundary}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"subscribers.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            {csv}\r\n\
+            --{boundary}--\r\n"
+        );
+        self.api_client
+            .post(format!("{}/admin/imports", &self.address))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_admin_subscriber_html(&self, subscriber_id: Uuid) -> String {
+        self.api_client
+            .get(format!("{}/admin/subscribers/{}", &self.address, subscriber_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .expect("Failed to read text content")
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
-            {
+            if let ExecutionOutcome::EmptyQueue = self.dispatch_next_email().await {
                 break;
             }
         }
     }
+
+    /// Runs a single iteration of the delivery worker.
+    pub async fn dispatch_next_email(&self) -> ExecutionOutcome {
+        try_execute_task(
+            &self.db_pool,
+            &self.email_client,
+            &ApplicationBaseUrl(self.address.clone()),
+            &self.hmac_secret,
+        )
+        .await
+        .unwrap()
+    }
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Same as `spawn_app`, with a chance to tweak the configuration first.
+pub async fn spawn_app_with(customise: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
 
@@ -201,6 +592,11 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
 
         c.email_client.base_url = email_server.uri();
+        // Tests sign up addresses on the same few domains, all at once.
+        c.spam_protection.max_subscriptions_per_domain = u64::MAX;
+        // Each app counts its own attempts in the Redis instance they share.
+        c.spam_protection.redis_key_prefix = format!("{}:", Uuid::new_v4());
+        customise(&mut c);
         c
     };
     configure_database(&configuration.database).await;
@@ -210,6 +606,7 @@ pub async fn spawn_app() -> TestApp {
         .expect("Can't build server");
     let port = application.port();
     let address = format!("http://127.0.0.1:{}", port);
+    #[allow(clippy::let_underscore_future)]
     let _ = tokio::spawn(application.run_until_stopped());
 
     let api_client = reqwest::Client::builder()
@@ -226,6 +623,8 @@ pub async fn spawn_app() -> TestApp {
         api_client,
         test_user: TestUser::generate(),
         email_client: configuration.email_client.client(),
+        hmac_secret: HmacSecret(configuration.application.hmac_secret.clone()),
+        webhooks: configuration.webhooks.clone(),
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -1,5 +1,12 @@
 mod admin_dashboard;
+mod admin_domain_rules;
+mod admin_imports;
 mod admin_newsletters;
+mod admin_subscribers;
+mod admin_suppressions;
+mod admin_templates;
+mod admin_welcome_email;
+mod archive;
 mod change_password;
 mod health_check;
 mod helpers;
@@ -7,3 +14,10 @@ mod login;
 mod newsletter;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data;
+mod subscriptions_preferences;
+mod subscriptions_resend;
+mod subscriptions_spam_protection;
+mod subscriptions_unsubscribe;
+mod topics;
+mod webhooks;
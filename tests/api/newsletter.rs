@@ -56,6 +56,188 @@ async fn newsletter_are_delivered_to_confirmed_subscribers() {
     app.dispatch_all_pending_emails().await;
 }
 
+#[tokio::test]
+async fn confirmation_emails_are_sent_before_pending_issues() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.publish_newsletter(serde_json::json!({
+        "title": "newsletter title",
+        "content": {
+            "text": "newsletter content",
+            "html": "<p>newsletter content</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    let n_sent = app.email_server.received_requests().await.unwrap().len();
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_next_email().await;
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(email_requests.len(), n_sent + 1);
+    let body: serde_json::Value = serde_json::from_slice(&email_requests[n_sent].body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn newsletters_can_be_written_in_markdown() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .publish_newsletter(serde_json::json!({
+            "title": "newsletter title",
+            "content": {"markdown": "# Rust Weekly\n\nSome *news*."}
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("<h1>Rust Weekly</h1>\n<p>Some <em>news</em>.</p>\n"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Rust Weekly\n===========\n\nSome news.\n"));
+}
+
+#[tokio::test]
+async fn scripts_are_removed_from_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.publish_newsletter(serde_json::json!({
+        "title": "newsletter title",
+        "content": {
+            "text": "Some news.",
+            "html": "<p onclick=\"steal()\">Some news.</p><script>alert(1)</script>",
+        }
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<p>Some news.</p>"));
+    assert!(!html_body.contains("<script>"));
+    assert!(!html_body.contains("onclick"));
+}
+
+#[tokio::test]
+async fn placeholders_are_filled_in_for_each_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .publish_newsletter(serde_json::json!({
+            "title": "newsletter title",
+            "content": {
+                "text": "Hi {{ subscriber.name }}",
+                "html": "<p>Hi {{ subscriber.name }}</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let subscriber = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains(&format!(
+        "<p>Hi {}</p>",
+        htmlescape::encode_minimal(&subscriber.name)
+    )));
+    assert!(!html_body.contains("{{"));
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with(&format!("Hi {}", subscriber.name)));
+}
+
+#[tokio::test]
+async fn unknown_placeholders_are_refused() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .publish_newsletter(serde_json::json!({
+            "title": "newsletter title",
+            "content": {
+                "text": "Hi {{ subscriber.first_name }}",
+                "html": "<p>Hi</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .starts_with("Text content: {{ subscriber.first_name }} is not a known placeholder."));
+    app.dispatch_all_pending_emails().await;
+    let issues = sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, Some(0));
+}
+
 #[tokio::test]
 async fn newsletters_returns_a_400_for_invalid_data() {
     let app = spawn_app().await;
@@ -75,6 +257,13 @@ async fn newsletters_returns_a_400_for_invalid_data() {
             }),
             "content is required.",
         ),
+        (
+            serde_json::json!({
+                "title": "newsletter title",
+                "content": {"text": "newsletter content"}
+            }),
+            "content needs either markdown or both text and html.",
+        ),
     ];
 
     for (invalid_body, error_message) in test_cases {
@@ -89,7 +278,7 @@ async fn requests_missing_authorization_are_rejected() {
     let app = spawn_app().await;
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .json(&serde_json::json!({
             "title": "Newsletter title",
             "content": {
@@ -156,7 +345,7 @@ async fn invalid_password_is_rejected() {
 pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     let name: String = Name().fake();
     let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
+    let body = serde_urlencoded::to_string(serde_json::json!({
         "name": name,
         "email": email
     }))
@@ -169,10 +358,11 @@ pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .mount_as_scoped(&app.email_server)
         .await;
 
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
+    app.dispatch_all_pending_emails().await;
     app.get_confirmation_links(
         &app.email_server
             .received_requests()
@@ -190,4 +380,13 @@ pub async fn create_confirmed_subscriber(app: &TestApp) {
         .unwrap()
         .error_for_status()
         .unwrap();
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Welcome email")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
 }
@@ -1,6 +1,7 @@
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 use crate::helpers::spawn_app;
+use zero2prod::domain::SubscriptionStatus;
 
 
 #[tokio::test]
@@ -16,6 +17,7 @@ async fn subscribe_returns_a_200_for_valid_form_data() {
 
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
     let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     assert_eq!(200, response.status().as_u16());
 }
@@ -33,15 +35,16 @@ async fn subscribe_persists_the_new_subscriber() {
 
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
     let _ = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
-    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
+    let saved = sqlx::query!(r#"SELECT email, name, status AS "status: SubscriptionStatus" FROM subscriptions"#)
         .fetch_one(&app.db_pool)
         .await
         .expect("Failed to fetch saved subscriptions.");
 
     assert_eq!(saved.email, "ursula_le_guin@gmail.com");
     assert_eq!(saved.name, "le guin");
-    assert_eq!(saved.status, "pending_confirmation");
+    assert_eq!(saved.status, SubscriptionStatus::PendingConfirmation);
 }
 
 #[tokio::test]
@@ -56,6 +59,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = &app.get_confirmation_links(email_request);
@@ -82,6 +86,75 @@ async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
     }
 }
 
+#[tokio::test]
+async fn subscribe_accepts_a_json_body() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+    });
+    let response = app.post_subscriptions_json(&body).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!(r#"SELECT email, name, status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn subscribe_returns_structured_validation_errors() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": "", "email": "ursula_le_guin@gmail.com"}),
+            "name",
+            "invalid_name",
+        ),
+        (
+            serde_json::json!({"name": "Ursula", "email": "definetely-not-an-email"}),
+            "email",
+            "invalid_email",
+        ),
+    ];
+
+    for (body, field, code) in test_cases {
+        let response = app.post_subscriptions_json(&body).await;
+        assert_eq!(400, response.status().as_u16());
+
+        let error: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(error["field"], field, "Unexpected field for payload {}.", body);
+        assert_eq!(error["code"], code, "Unexpected code for payload {}.", body);
+        assert!(error["message"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn form_submissions_get_structured_validation_errors_too() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=Ursula&email=definetely-not-an-email".into())
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["field"], "email");
+    assert_eq!(error["code"], "invalid_email");
+}
+
 #[tokio::test]
 async fn subscribe_returns_a_400_when_data_is_missing() {
     let app = spawn_app().await;
@@ -117,3 +190,335 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
 
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_resends_the_confirmation_email() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_links(&email_requests[0]).html;
+    let second_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(first_link, second_link);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn subscribing_twice_in_quick_succession_sends_a_single_email() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn concurrent_first_time_signups_for_the_same_address_both_succeed() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let (response1, response2) = tokio::join!(
+        app.post_subscriptions(body.into()),
+        app.post_subscriptions(body.into())
+    );
+
+    assert_eq!(response1.status().as_u16(), 200);
+    assert_eq!(response2.status().as_u16(), 200);
+    let n_subscribers = sqlx::query_scalar!("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, Some(1));
+}
+
+#[tokio::test]
+async fn subscribing_again_once_confirmed_is_a_no_op() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // The confirmation email and the welcome email, nothing more.
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+}
+
+#[tokio::test]
+async fn email_addresses_are_compared_case_insensitively() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40Gmail.com".into())
+        .await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "Ursula_Le_Guin@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_restarts_double_opt_in() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_subscriptions("name=ursula&email=ursula_le_guin%40gmail.com".into())
+        .await;
+        app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(r#"SELECT name, status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name, "ursula");
+    assert_eq!(saved.status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn subscription_tokens_are_not_stored_in_plaintext() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let (_, subscription_token) = confirmation_links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "subscription_token")
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT subscription_token_hash FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription token.");
+
+    assert_ne!(saved.subscription_token_hash, subscription_token);
+    assert!(!saved.subscription_token_hash.contains(subscription_token.as_ref()));
+}
+
+#[tokio::test]
+async fn subscribe_responds_before_the_confirmation_email_is_sent() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT recipient FROM email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the queued confirmation email.");
+    assert_eq!(saved.recipient, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn a_failed_confirmation_email_is_retried_later() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    let saved = sqlx::query!("SELECT n_retries FROM email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The failed email should still be queued.");
+    assert_eq!(saved.n_retries, 1);
+
+    sqlx::query!("UPDATE email_outbox SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let remaining = sqlx::query!("SELECT email_id FROM email_outbox")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(remaining.is_empty());
+}
+
+#[tokio::test]
+async fn subscribe_records_where_the_signup_came_from() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com\
+        &referrer=https%3A%2F%2Fsearch.example.com%2F\
+        &utm_source=mastodon&utm_medium=social&utm_campaign=%20spring%20&utm_term=&utm_content=\
+        &consent_version=2025-02-19&form_token={}",
+        app.form_token()
+    );
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Referer", "https://blog.example.com/signup")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!(
+        r#"
+        SELECT source_url, referrer, utm_source, utm_medium, utm_campaign, utm_term,
+            consent_version, confirmed_at
+        FROM subscriptions
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.source_url.as_deref(), Some("https://blog.example.com/signup"));
+    assert_eq!(saved.referrer.as_deref(), Some("https://search.example.com/"));
+    assert_eq!(saved.utm_source.as_deref(), Some("mastodon"));
+    assert_eq!(saved.utm_medium.as_deref(), Some("social"));
+    assert_eq!(saved.utm_campaign.as_deref(), Some("spring"));
+    assert_eq!(saved.utm_term, None);
+    assert_eq!(saved.consent_version.as_deref(), Some("2025-02-19"));
+    assert_eq!(saved.confirmed_at, None);
+}
+
+#[tokio::test]
+async fn the_signup_form_passes_on_campaign_parameters_and_the_consent_version() {
+    let app = spawn_app().await;
+
+    let html_page = app
+        .api_client
+        .get(format!("{}/?utm_source=newsletter&utm_campaign=spring", &app.address))
+        .header("Referer", "https://search.example.com/")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains(r#"name="utm_source" value="newsletter""#));
+    assert!(html_page.contains(r#"name="utm_campaign" value="spring""#));
+    assert!(html_page.contains(r#"name="consent_version" value="2025-02-19""#));
+    assert!(html_page.contains("By subscribing you agree"));
+}
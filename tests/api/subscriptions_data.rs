@@ -0,0 +1,308 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn insert_subscriber(app: &TestApp, email: &str) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'Ursula', now(), 'confirmed')
+        "#,
+        subscriber_id,
+        email,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+fn query_param(url: &reqwest::Url, name: &str) -> String {
+    url.query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+        .unwrap()
+}
+
+fn erasure_body(link: &reqwest::Url) -> String {
+    format!(
+        "subscriber_id={}&expires={}&token={}",
+        query_param(link, "subscriber_id"),
+        query_param(link, "expires"),
+        query_param(link, "token"),
+    )
+}
+
+fn valid_link(app: &TestApp, subscriber_id: Uuid) -> reqwest::Url {
+    app.get_data_link(subscriber_id, (Utc::now() + Duration::hours(1)).timestamp())
+}
+
+#[tokio::test]
+async fn subscribers_get_a_link_to_their_data_by_email() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_data_request("email=ursula%40example.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let link = app.get_confirmation_links(email_request).html;
+    assert_eq!(link.path(), "/subscriptions/data/manage");
+    let html_page = reqwest::get(link).await.unwrap().text().await.unwrap();
+    assert!(html_page.contains("Erase my data"));
+}
+
+#[tokio::test]
+async fn requests_for_unknown_addresses_look_the_same_but_send_nothing() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let known = app
+        .post_data_request("email=ursula%40example.com".into())
+        .await
+        .text()
+        .await
+        .unwrap();
+    sqlx::query!("DELETE FROM email_outbox")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let unknown = app
+        .post_data_request("email=octavia%40example.com".into())
+        .await
+        .text()
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(known, unknown);
+}
+
+#[tokio::test]
+async fn data_requests_with_the_honeypot_filled_in_send_nothing() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_data_request("email=ursula%40example.com&website=spam.example".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post_data_request_raw("email=ursula%40example.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn a_second_data_link_is_not_sent_right_after_the_first() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..2 {
+        let response = app
+            .post_data_request("email=ursula%40example.com".into())
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn data_requests_are_rate_limited_per_ip_address() {
+    let app = spawn_app_with(|c| c.spam_protection.max_subscriptions_per_ip = 1).await;
+    insert_subscriber(&app, "ursula@example.com").await;
+    insert_subscriber(&app, "octavia@example.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for email in ["ursula%40example.com", "octavia%40example.com"] {
+        let response = app.post_data_request(format!("email={}", email)).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn the_download_contains_everything_held_on_the_subscriber() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ursula@example.com").await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token_hash, subscriber_id, created_at, expires_at)
+        VALUES ('hash', $1, now(), now() + interval '1 day')
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.post_data_request("email=ursula%40example.com".into())
+        .await;
+
+    let link = valid_link(&app, subscriber_id);
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/subscriptions/data/download?{}",
+            app.address,
+            link.query().unwrap()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscription"]["email"], "ursula@example.com");
+    assert_eq!(data["subscription"]["status"], "confirmed");
+    assert_eq!(data["confirmation_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(data["queued_emails"][0]["subject"], "Your data with us");
+    assert!(data["deliveries"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn expired_or_forged_links_are_rejected() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ursula@example.com").await;
+
+    let expired = app.get_data_link(subscriber_id, (Utc::now() - Duration::hours(1)).timestamp());
+    let response = reqwest::get(expired).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let mut forged = valid_link(&app, subscriber_id);
+    let other_subscriber = Uuid::new_v4();
+    let query = forged
+        .query()
+        .unwrap()
+        .replace(&subscriber_id.to_string(), &other_subscriber.to_string());
+    forged.set_query(Some(&query));
+    let response = reqwest::get(forged.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_data_erasure(erasure_body(&forged)).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn erasure_removes_the_subscriber_from_every_table() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ursula@example.com").await;
+    app.post_data_request("email=ursula%40example.com".into())
+        .await;
+    let user_id = app.test_user.user_id;
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_audit_log (audit_id, user_id, action, subscriber_id, subscriber_email, performed_at)
+        VALUES (gen_random_uuid(), $1, 'force_confirm', $2, 'ursula@example.com', now())
+        "#,
+        user_id,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_imports (import_id, user_id, confirmed, created_at)
+        VALUES ($1, $2, true, now())
+        "#,
+        import_id,
+        user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_import_rejections (import_id, row_number, email, name, reason)
+        VALUES ($1, 2, 'Ursula@Example.com', '', 'Missing name')
+        "#,
+        import_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (event_id, postmark_id, kind, email, subscriber_id, occurred_at, received_at)
+        VALUES (gen_random_uuid(), 42, 'bounce', 'ursula@example.com', $1, now(), now())
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_data_erasure(erasure_body(&valid_link(&app, subscriber_id)))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let counts = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM subscriptions) AS "subscriptions!",
+            (SELECT COUNT(*) FROM email_outbox) AS "emails!",
+            (SELECT COUNT(*) FROM subscriber_import_rejections) AS "rejections!",
+            (SELECT COUNT(*) FROM email_events) AS "email_events!"
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(counts.subscriptions, 0);
+    assert_eq!(counts.emails, 0);
+    assert_eq!(counts.rejections, 0);
+    assert_eq!(counts.email_events, 0);
+    let audit_log = sqlx::query!("SELECT subscriber_id, subscriber_email FROM admin_audit_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(audit_log.subscriber_id, subscriber_id);
+    assert_eq!(audit_log.subscriber_email, "[erased]");
+
+    // The link has nothing left to show.
+    let response = reqwest::get(format!(
+        "{}/subscriptions/data/download?{}",
+        app.address,
+        valid_link(&app, subscriber_id).query().unwrap()
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,313 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::topics::insert_topic;
+
+async fn create_confirmed_subscriber(app: &TestApp, email: &str) -> Uuid {
+    let body = format!("name=le%20guin&email={}", urlencoding::encode(email));
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create confirmed subscriber")
+        .expect(2)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+fn query_param(url: &reqwest::Url, name: &str) -> String {
+    url.query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+        .unwrap()
+}
+
+fn preferences_body(app: &TestApp, subscriber_id: Uuid, name: &str, email: &str) -> String {
+    let link = app.get_preferences_link(subscriber_id);
+    format!(
+        "subscriber_id={}&token={}&name={}&email={}",
+        subscriber_id,
+        query_param(&link, "token"),
+        urlencoding::encode(name),
+        urlencoding::encode(email),
+    )
+}
+
+#[tokio::test]
+async fn the_preferences_link_shows_the_current_preferences() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    let response = reqwest::get(app.get_preferences_link(subscriber_id))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+    assert!(html_page.contains(r#"action="/subscriptions/unsubscribe""#));
+}
+
+#[tokio::test]
+async fn a_forged_preferences_token_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    let unsubscribe_link = app.get_unsubscribe_link(subscriber_id);
+
+    // A token for another purpose must not open the preference center.
+    let response = reqwest::get(format!(
+        "{}/subscriptions/preferences?subscriber_id={}&token={}",
+        app.address,
+        subscriber_id,
+        query_param(&unsubscribe_link, "token")
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_update_their_name_and_topics() {
+    let app = spawn_app().await;
+    let rust = insert_topic(&app.db_pool, "Rust", "").await.unwrap();
+    insert_topic(&app.db_pool, "Databases", "").await.unwrap();
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    let body = format!(
+        "{}&topics={}",
+        preferences_body(&app, subscriber_id, "Ursula", "ursula_le_guin@gmail.com"),
+        rust
+    );
+    let response = app.post_preferences(body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Your preferences have been saved."));
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula");
+    let topics = sqlx::query!("SELECT topic_id FROM subscription_topics")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(topics.len(), 1);
+    assert_eq!(topics[0].topic_id, rust);
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_rejected_with_a_400() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    let body = preferences_body(&app, subscriber_id, " ", "ursula_le_guin@gmail.com");
+    let response = app.post_preferences(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn changing_the_email_only_takes_effect_once_the_new_address_is_confirmed() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = preferences_body(&app, subscriber_id, "le guin", "ursula@example.com");
+    let response = app.post_preferences(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email_body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email_body["To"], "ursula@example.com");
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("ursula@example.com"));
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+
+    let token = query_param(&confirmation_links.html, "token");
+    let response = app.post_confirm_email_change(&token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula@example.com");
+}
+
+#[tokio::test]
+async fn an_email_change_link_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = preferences_body(&app, subscriber_id, "le guin", "ursula@example.com");
+    app.post_preferences(body).await.error_for_status().unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let token = query_param(&app.get_confirmation_links(&email_request).html, "token");
+
+    app.post_confirm_email_change(&token)
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = app.post_confirm_email_change(&token).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_expired_email_change_link_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = preferences_body(&app, subscriber_id, "le guin", "ursula@example.com");
+    app.post_preferences(body).await.error_for_status().unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    sqlx::query!("UPDATE email_change_requests SET expires_at = now() - interval '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let token = query_param(&confirmation_links.html, "token");
+    let response = app.post_confirm_email_change(&token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let n_requests = sqlx::query_scalar!("SELECT COUNT(*) FROM email_change_requests")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_requests, Some(0));
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn an_email_change_to_an_address_already_subscribed_is_refused_with_a_409() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = preferences_body(&app, subscriber_id, "le guin", "ursula@example.com");
+    app.post_preferences(body).await.error_for_status().unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    // Someone else subscribes with the new address in the meantime.
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'Ursula@example.com', 'ursula', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let token = query_param(&confirmation_links.html, "token");
+    let response = app.post_confirm_email_change(&token).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+    let saved = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+}
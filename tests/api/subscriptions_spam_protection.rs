@@ -0,0 +1,273 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use chrono::Utc;
+use secrecy::ExposeSecret;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::spam_protection::form_token;
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+async fn assert_silently_rejected(app: &TestApp, response: reqwest::Response) {
+    assert_eq!(200, response.status().as_u16());
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(0, subscriber_count(app).await);
+}
+
+#[tokio::test]
+async fn filling_in_the_honeypot_is_silently_rejected() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&website=http%3A%2F%2Fspam.example".into(),
+        )
+        .await;
+
+    assert_silently_rejected(&app, response).await;
+}
+
+#[tokio::test]
+async fn submissions_without_a_valid_form_token_are_silently_rejected() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let an_hour_ago = (Utc::now() - chrono::Duration::hours(1)).timestamp();
+    let test_cases = vec![
+        ("".to_string(), "missing"),
+        (format!("&form_token={an_hour_ago}.deadbeef"), "forged"),
+        (
+            format!(
+                "&form_token={}",
+                form_token(&app.hmac_secret, Utc::now() - chrono::Duration::days(2))
+            ),
+            "expired",
+        ),
+    ];
+
+    for (form_token, description) in test_cases {
+        let response = app
+            .post_subscriptions_raw(format!(
+                "name=le%20guin&email=ursula_le_guin%40gmail.com{form_token}"
+            ))
+            .await;
+        assert_eq!(
+            200,
+            response.status().as_u16(),
+            "The API did not answer 200 OK when the form token was {}.",
+            description
+        );
+    }
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(0, subscriber_count(&app).await);
+}
+
+#[tokio::test]
+async fn forms_submitted_too_fast_are_silently_rejected() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions_raw(format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}",
+            form_token(&app.hmac_secret, Utc::now())
+        ))
+        .await;
+
+    assert_silently_rejected(&app, response).await;
+}
+
+#[tokio::test]
+async fn the_served_form_token_can_be_used_to_subscribe() {
+    let app = spawn_app_with(|c| c.spam_protection.min_submit_seconds = 0).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/subscriptions/form_token", app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let form_token = body["form_token"].as_str().unwrap();
+
+    let response = app
+        .post_subscriptions_raw(format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={form_token}"
+        ))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(1, subscriber_count(&app).await);
+}
+
+#[tokio::test]
+async fn subscriptions_are_rate_limited_per_ip_address() {
+    let app = spawn_app_with(|c| c.spam_protection.max_subscriptions_per_ip = 2).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    for i in 0..3 {
+        let response = app
+            .post_subscriptions(format!("name=le%20guin&email=ursula{i}%40gmail.com"))
+            .await;
+        assert_eq!(200, response.status().as_u16());
+    }
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(2, subscriber_count(&app).await);
+}
+
+#[tokio::test]
+async fn subscriptions_are_rate_limited_per_email_domain() {
+    let app = spawn_app_with(|c| c.spam_protection.max_subscriptions_per_domain = 2).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let domain = format!("{}.com", Uuid::new_v4());
+
+    for i in 0..3 {
+        let response = app
+            .post_subscriptions(format!("name=le%20guin&email=ursula{i}%40{domain}"))
+            .await;
+        assert_eq!(200, response.status().as_u16());
+    }
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(2, subscriber_count(&app).await);
+}
+
+async fn post_subscriptions_forwarded_for(
+    app: &TestApp,
+    forwarded_for: &str,
+    body: String,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", forwarded_for)
+        .body(format!("{}&form_token={}", body, app.form_token()))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn forwarding_headers_from_untrusted_peers_are_ignored() {
+    let app = spawn_app_with(|c| c.spam_protection.max_subscriptions_per_ip = 2).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    for i in 0..3 {
+        let response = post_subscriptions_forwarded_for(
+            &app,
+            &format!("10.0.0.{i}"),
+            format!("name=le%20guin&email=ursula{i}%40gmail.com"),
+        )
+        .await;
+        assert_eq!(200, response.status().as_u16());
+    }
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(2, subscriber_count(&app).await);
+}
+
+#[tokio::test]
+async fn clients_behind_a_trusted_proxy_are_rate_limited_separately() {
+    let app = spawn_app_with(|c| {
+        c.spam_protection.max_subscriptions_per_ip = 1;
+        c.spam_protection.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // What the client sent is to the left of what the proxy appended.
+    for (i, forwarded_for) in ["10.0.0.1", "10.0.0.2", "10.0.0.3, 10.0.0.2"]
+        .iter()
+        .enumerate()
+    {
+        let response = post_subscriptions_forwarded_for(
+            &app,
+            forwarded_for,
+            format!("name=le%20guin&email=ursula{i}%40gmail.com"),
+        )
+        .await;
+        assert_eq!(200, response.status().as_u16());
+    }
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(2, subscriber_count(&app).await);
+}
+
+#[tokio::test]
+async fn rate_limit_counters_expire_with_the_window() {
+    let prefix = format!("{}:", Uuid::new_v4());
+    let mut redis_uri = None;
+    let app = spawn_app_with(|c| {
+        c.spam_protection.redis_key_prefix = prefix.clone();
+        redis_uri = Some(c.redis_uri.expose_secret().clone());
+    })
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let mut redis = redis::Client::open(redis_uri.unwrap())
+        .unwrap()
+        .get_multiplexed_async_connection()
+        .await
+        .unwrap();
+    let key = format!("{}subscribe:ip:127.0.0.1", prefix);
+
+    app.post_subscriptions("name=le%20guin&email=ursula%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let ttl: i64 = redis::cmd("TTL")
+        .arg(&key)
+        .query_async(&mut redis)
+        .await
+        .unwrap();
+    assert!(ttl > 0 && ttl <= 3600, "The counter has a TTL of {}.", ttl);
+}
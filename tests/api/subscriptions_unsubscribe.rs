@@ -0,0 +1,144 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::SubscriptionStatus;
+
+async fn create_confirmed_subscriber(app: &TestApp) -> Uuid {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create confirmed subscriber")
+        .expect(2)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+fn query_param(url: &reqwest::Url, name: &str) -> String {
+    url.query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_asks_for_confirmation_before_unsubscribing() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+
+    let response = reqwest::get(app.get_unsubscribe_link(subscriber_id))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains(r#"action="/subscriptions/unsubscribe""#));
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+}
+
+#[tokio::test]
+async fn submitting_the_unsubscribe_form_unsubscribes_the_subscriber() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let link = app.get_unsubscribe_link(subscriber_id);
+
+    let response = app
+        .post_unsubscribe(&serde_json::json!({
+            "subscriber_id": subscriber_id,
+            "token": query_param(&link, "token"),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("unsubscribed"));
+    let saved = sqlx::query!(
+        r#"SELECT status AS "status: SubscriptionStatus", unsubscribed_at FROM subscriptions"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Unsubscribed);
+    assert!(saved.unsubscribed_at.is_some());
+}
+
+#[tokio::test]
+async fn a_tampered_unsubscribe_link_is_rejected() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let link = app.get_unsubscribe_link(Uuid::new_v4());
+
+    let response = app
+        .post_unsubscribe(&serde_json::json!({
+            "subscriber_id": subscriber_id,
+            "token": query_param(&link, "token"),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_after_unsubscribing() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let link = app.get_unsubscribe_link(subscriber_id);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+    app.post_newsletter(serde_json::json!({
+        "title": "newsletter title",
+        "content_text": "newsletter content",
+        "content_html": "<p>newsletter content</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.post_unsubscribe(&serde_json::json!({
+        "subscriber_id": subscriber_id,
+        "token": query_param(&link, "token"),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+}
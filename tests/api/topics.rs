@@ -0,0 +1,233 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::topics::insert_topic;
+
+async fn subscribe_to(app: &TestApp, email: &str, topics: &[Uuid]) {
+    let body = serde_html_form::to_string(serde_json::json!({
+        "name": "le guin",
+        "email": email,
+        "topics": topics,
+    }))
+    .unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+async fn subscribed_topics(app: &TestApp, email: &str) -> Vec<String> {
+    sqlx::query!(
+        r#"
+        SELECT t.name
+        FROM subscription_topics st
+        JOIN topics t ON t.topic_id = st.topic_id
+        JOIN subscriptions s ON s.id = st.subscriber_id
+        WHERE s.email = $1
+        ORDER BY t.name
+        "#,
+        email
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| row.name)
+    .collect()
+}
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+#[tokio::test]
+async fn subscribers_can_pick_topics_when_signing_up() {
+    let app = spawn_app().await;
+    let rust = insert_topic(&app.db_pool, "Rust", "").await.unwrap();
+    insert_topic(&app.db_pool, "Databases", "").await.unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe_to(&app, "ursula@gmail.com", &[rust]).await;
+
+    assert_eq!(subscribed_topics(&app, "ursula@gmail.com").await, vec!["Rust"]);
+}
+
+#[tokio::test]
+async fn picking_no_topic_subscribes_to_all_of_them() {
+    let app = spawn_app().await;
+    insert_topic(&app.db_pool, "Rust", "").await.unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe_to(&app, "ursula@gmail.com", &[]).await;
+
+    assert_eq!(
+        subscribed_topics(&app, "ursula@gmail.com").await,
+        vec!["General", "Rust"]
+    );
+}
+
+#[tokio::test]
+async fn the_signup_form_lists_the_topics() {
+    let app = spawn_app().await;
+    let rust = insert_topic(&app.db_pool, "Rust", "All about Rust").await.unwrap();
+
+    let html_page = app
+        .api_client
+        .get(&app.address)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains(&format!(r#"name="topics" value="{}""#, rust)));
+    assert!(html_page.contains("All about Rust"));
+}
+
+#[tokio::test]
+async fn issues_are_only_delivered_to_subscribers_of_the_chosen_topics() {
+    let app = spawn_app().await;
+    let rust = insert_topic(&app.db_pool, "Rust", "").await.unwrap();
+    let databases = insert_topic(&app.db_pool, "Databases", "").await.unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe_to(&app, "rustacean@gmail.com", &[rust]).await;
+    subscribe_to(&app, "dba@gmail.com", &[databases]).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    app.email_server.reset().await;
+
+    login(&app).await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = serde_html_form::to_string(serde_json::json!({
+        "title": "Newsletter title",
+        "content_text": "Newsletter body as plain text",
+        "content_html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "topics": [rust],
+    }))
+    .unwrap();
+    let response = app
+        .api_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["To"], "rustacean@gmail.com");
+}
+
+#[tokio::test]
+async fn issues_published_through_the_api_can_be_sent_to_some_topics_only() {
+    let app = spawn_app().await;
+    let rust = insert_topic(&app.db_pool, "Rust", "").await.unwrap();
+    let databases = insert_topic(&app.db_pool, "Databases", "").await.unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe_to(&app, "rustacean@gmail.com", &[rust]).await;
+    subscribe_to(&app, "dba@gmail.com", &[databases]).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    app.email_server.reset().await;
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.publish_newsletter(serde_json::json!({
+        "title": "Rust only",
+        "content": {"text": "newsletter content", "html": "<p>newsletter content</p>"},
+        "topics": [rust],
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["To"], "rustacean@gmail.com");
+    let html_page = reqwest::get(format!("{}/archive", &app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(!html_page.contains("Rust only"));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_topics() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/topics", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_can_add_topics() {
+    let app = spawn_app().await;
+    login(&app).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/topics", &app.address))
+        .form(&serde_json::json!({"name": "Rust", "description": "All about Rust"}))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/topics");
+
+    let html_page = app
+        .api_client
+        .get(format!("{}/admin/topics", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The topic Rust has been added."));
+    assert!(html_page.contains("All about Rust"));
+}
@@ -0,0 +1,331 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+use zero2prod::domain::SubscriptionStatus;
+
+// Payloads as Postmark sends them, trimmed of the message dumps.
+const HARD_BOUNCE: &str = r#"{
+  "RecordType": "Bounce",
+  "MessageStream": "outbound",
+  "ID": 4323372036854775807,
+  "Type": "HardBounce",
+  "TypeCode": 1,
+  "Name": "Hard bounce",
+  "Tag": "",
+  "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+  "Metadata": {},
+  "ServerID": 23,
+  "Description": "The server was unable to deliver your message (ex: unknown user, mailbox not found).",
+  "Details": "smtp;550 5.1.1 The email account that you tried to reach does not exist.",
+  "Email": "Ursula@example.com",
+  "From": "newsletter@example.com",
+  "BouncedAt": "2025-02-24T16:33:54.9070259Z",
+  "DumpAvailable": true,
+  "Inactive": true,
+  "CanActivate": true,
+  "Subject": "Our latest issue"
+}"#;
+
+const SOFT_BOUNCE: &str = r#"{
+  "RecordType": "Bounce",
+  "MessageStream": "outbound",
+  "ID": 4323372036854775808,
+  "Type": "SoftBounce",
+  "TypeCode": 4096,
+  "Name": "Soft bounce/Undeliverable",
+  "MessageID": "2c1b63fe-43f2-4db5-91b0-8bdfa44a4316",
+  "ServerID": 23,
+  "Description": "Unable to temporarily deliver this email.",
+  "Details": "smtp;452 4.2.2 The email account that you tried to reach is over quota.",
+  "Email": "ursula@example.com",
+  "From": "newsletter@example.com",
+  "BouncedAt": "2025-02-24T17:02:11.1240000Z",
+  "DumpAvailable": true,
+  "Inactive": false,
+  "CanActivate": true,
+  "Subject": "Our latest issue"
+}"#;
+
+const SPAM_COMPLAINT: &str = r#"{
+  "RecordType": "SpamComplaint",
+  "MessageStream": "outbound",
+  "ID": 42,
+  "Type": "SpamComplaint",
+  "TypeCode": 512,
+  "Name": "Spam complaint",
+  "Tag": "",
+  "MessageID": "00000000-0000-0000-0000-000000000000",
+  "Metadata": {},
+  "ServerID": 1234,
+  "Description": "",
+  "Details": "Test spam complaint details",
+  "Email": "ursula@example.com",
+  "From": "newsletter@example.com",
+  "BouncedAt": "2025-02-24T18:12:30Z",
+  "DumpAvailable": true,
+  "Inactive": true,
+  "CanActivate": false,
+  "Subject": "Our latest issue"
+}"#;
+
+const DELIVERY: &str = r#"{
+  "RecordType": "Delivery",
+  "MessageStream": "outbound",
+  "ServerID": 23,
+  "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+  "Recipient": "ursula@example.com",
+  "DeliveredAt": "2025-02-24T16:30:00Z",
+  "Details": "Test delivery webhook details"
+}"#;
+
+async fn insert_subscriber(app: &TestApp, status: SubscriptionStatus) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula@example.com', 'Ursula', now(), $2)
+        "#,
+        subscriber_id,
+        status as SubscriptionStatus,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+/// Queues an issue and an email for the subscriber.
+async fn queue_mail(app: &TestApp) {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content, published_at
+        )
+        VALUES ($1, 'Our latest issue', 'text', '<p>html</p>', now())
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        VALUES ($1, 'ursula@example.com')
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_outbox (email_id, recipient, subject, text_content, html_content, created_at)
+        VALUES (gen_random_uuid(), 'ursula@example.com', 'Welcome', 'text', '<p>html</p>', now())
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn subscriber_status(app: &TestApp) -> SubscriptionStatus {
+    sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+async fn queued_mail(app: &TestApp) -> i64 {
+    sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM issue_delivery_queue)
+            + (SELECT COUNT(*) FROM email_outbox) AS "count!"
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count
+}
+
+async fn suppressions(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT email, reason FROM email_suppressions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email, r.reason))
+        .collect()
+}
+
+async fn recorded_events(app: &TestApp) -> Vec<(String, Option<Uuid>)> {
+    sqlx::query!("SELECT kind, subscriber_id FROM email_events ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.kind, r.subscriber_id))
+        .collect()
+}
+
+#[tokio::test]
+async fn webhooks_without_valid_credentials_are_rejected() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, SubscriptionStatus::Confirmed).await;
+
+    let anonymous = app
+        .api_client
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .body(HARD_BOUNCE)
+        .send()
+        .await
+        .unwrap();
+    let wrong_password = app
+        .api_client
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .basic_auth(&app.webhooks.username, Some("not-the-password"))
+        .body(HARD_BOUNCE)
+        .send()
+        .await
+        .unwrap();
+
+    for response in [anonymous, wrong_password] {
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            r#"Basic realm="webhooks""#
+        );
+    }
+    assert_eq!(subscriber_status(&app).await, SubscriptionStatus::Confirmed);
+    assert!(recorded_events(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn a_hard_bounce_marks_the_subscriber_as_bounced_and_drops_their_queue() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, SubscriptionStatus::Confirmed).await;
+    queue_mail(&app).await;
+
+    let response = app.post_postmark_webhook(HARD_BOUNCE).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, SubscriptionStatus::Bounced);
+    assert_eq!(queued_mail(&app).await, 0);
+    assert_eq!(
+        recorded_events(&app).await,
+        vec![("bounce".to_string(), Some(subscriber_id))]
+    );
+    assert_eq!(
+        suppressions(&app).await,
+        vec![("ursula@example.com".to_string(), "bounce".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn a_soft_bounce_is_recorded_but_changes_nothing() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, SubscriptionStatus::Confirmed).await;
+    queue_mail(&app).await;
+
+    let response = app.post_postmark_webhook(SOFT_BOUNCE).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, SubscriptionStatus::Confirmed);
+    assert_eq!(queued_mail(&app).await, 2);
+    assert_eq!(recorded_events(&app).await.len(), 1);
+    assert!(suppressions(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn a_spam_complaint_marks_the_subscriber_as_complained() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, SubscriptionStatus::Unsubscribed).await;
+
+    let response = app.post_postmark_webhook(SPAM_COMPLAINT).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        subscriber_status(&app).await,
+        SubscriptionStatus::Complained
+    );
+}
+
+#[tokio::test]
+async fn a_bounce_does_not_override_an_earlier_unsubscription() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, SubscriptionStatus::Unsubscribed).await;
+
+    let response = app.post_postmark_webhook(HARD_BOUNCE).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        subscriber_status(&app).await,
+        SubscriptionStatus::Unsubscribed
+    );
+    assert_eq!(recorded_events(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn redelivered_webhooks_are_recorded_once() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, SubscriptionStatus::Confirmed).await;
+
+    for _ in 0..2 {
+        let response = app.post_postmark_webhook(HARD_BOUNCE).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    assert_eq!(recorded_events(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn events_for_unknown_addresses_are_still_recorded() {
+    let app = spawn_app().await;
+
+    let response = app.post_postmark_webhook(SPAM_COMPLAINT).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        recorded_events(&app).await,
+        vec![("spam_complaint".to_string(), None)]
+    );
+    assert_eq!(
+        suppressions(&app).await,
+        vec![("ursula@example.com".to_string(), "spam_complaint".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn other_webhooks_are_acknowledged_and_ignored() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, SubscriptionStatus::Confirmed).await;
+
+    let response = app.post_postmark_webhook(DELIVERY).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(recorded_events(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn malformed_payloads_are_rejected() {
+    let app = spawn_app().await;
+    let bad_timestamp = HARD_BOUNCE.replace("2025-02-24T16:33:54.9070259Z", "yesterday");
+    let cases = [
+        ("not json", "not JSON"),
+        (r#"{"RecordType": "Bounce"}"#, "missing fields"),
+        (bad_timestamp.as_str(), "with an invalid timestamp"),
+    ];
+
+    for (body, error_message) in cases {
+        let response = app.post_postmark_webhook(body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message
+        );
+    }
+}